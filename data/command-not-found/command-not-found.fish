@@ -1,3 +1,8 @@
 function fish_command_not_found
-    oma command-not-found -- $argv[1]
+    # Set OMA_CNF_INSTALL=1 to be offered to install the missing command
+    if set -q OMA_CNF_INSTALL
+        oma command-not-found --install -- $argv
+    else
+        oma command-not-found -- $argv[1]
+    end
 end
@@ -1,3 +1,8 @@
 command_not_found_handle() {
-    oma command-not-found -- $1
+    # Set OMA_CNF_INSTALL=1 to be offered to install the missing command
+    if [ -n "$OMA_CNF_INSTALL" ]; then
+        oma command-not-found --install -- "$@"
+    else
+        oma command-not-found -- $1
+    fi
 }
@@ -1355,7 +1355,7 @@ _oma() {
             return 0
             ;;
         oma__upgrade)
-            opts="-y -h --yes --force-yes --force-unsafe-io --force-confnew --dry-run --autoremove --no-refresh-topics --debug --no-color --follow-terminal-color --no-progress --no-check-dbus --sysroot --apt-options --help"
+            opts="-y -h --yes --force-yes --force-unsafe-io --force-confnew --dry-run --autoremove --offline --no-refresh-topics --debug --no-color --follow-terminal-color --no-progress --no-check-dbus --sysroot --apt-options --help"
             if [[ ${cur} == -* || ${COMP_CWORD} -eq 2 ]] ; then
                 COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
                 return 0
@@ -12,6 +12,8 @@ complete -c oma -n "__fish_use_subcommand" -l debug -d 'Run oma with debug mode'
 complete -c oma -n "__fish_use_subcommand" -l no-color -d 'No color output to result'
 complete -c oma -n "__fish_use_subcommand" -l no-progress -d 'Do not display progress bar'
 complete -c oma -n "__fish_use_subcommand" -l no-check-dbus
+complete -c oma -n "__fish_use_subcommand" -l progress-fd -d 'Write progress events as JSON lines to file descriptor' -r
+complete -c oma -n "__fish_use_subcommand" -l progress-socket -d 'Write progress events as JSON lines to Unix socket' -r -F
 complete -c oma -n "__fish_use_subcommand" -s v -l version -d 'Print version'
 complete -c oma -n "__fish_use_subcommand" -s h -l help -d 'Print help (see more with \'--help\')'
 complete -c oma -n "__fish_use_subcommand" -f -a "install" -d 'Install package(s) from the repository'
@@ -20,6 +22,7 @@ complete -c oma -n "__fish_use_subcommand" -f -a "download" -d 'Download package
 complete -c oma -n "__fish_use_subcommand" -f -a "remove" -d 'Remove the specified package(s)'
 complete -c oma -n "__fish_use_subcommand" -f -a "purge" -d 'purge (like apt purge) the specified package(s)'
 complete -c oma -n "__fish_use_subcommand" -f -a "refresh" -d 'Refresh repository metadata/catalog'
+complete -c oma -n "__fish_use_subcommand" -f -a "changelog" -d 'Show changelog of the specified package(s) newer than the installed version'
 complete -c oma -n "__fish_use_subcommand" -f -a "show" -d 'Show information on the specified package(s)'
 complete -c oma -n "__fish_use_subcommand" -f -a "search" -d 'Search for package(s) available from the repository'
 complete -c oma -n "__fish_use_subcommand" -f -a "files" -d 'List files in the specified package'
@@ -34,6 +37,19 @@ complete -c oma -n "__fish_use_subcommand" -f -a "rdepends" -d 'List reverse dep
 complete -c oma -n "__fish_use_subcommand" -f -a "clean" -d 'Clear downloaded package cache'
 complete -c oma -n "__fish_use_subcommand" -f -a "history" -d 'Show a history/log of package changes in the system'
 complete -c oma -n "__fish_use_subcommand" -f -a "undo" -d 'Undo system changes operation'
+complete -c oma -n "__fish_use_subcommand" -f -a "apply" -d 'Converge the system to the declarative state in /etc/oma/state.toml'
+complete -c oma -n "__fish_seen_subcommand_from apply" -l check -d 'Only check for drift'
+complete -c oma -n "__fish_seen_subcommand_from apply" -l dry-run -d 'Run oma in “dry-run” mode'
+complete -c oma -n "__fish_use_subcommand" -f -a "auto-upgrade" -d 'Upgrade packages allowed by the [auto_upgrade] policy in oma.toml without asking'
+complete -c oma -n "__fish_seen_subcommand_from auto-upgrade" -l dry-run -d 'Run oma in “dry-run” mode'
+complete -c oma -n "__fish_use_subcommand" -f -a "pin" -d 'Pin a package to a version or repository, or list pins'
+complete -c oma -n "__fish_seen_subcommand_from pin" -s p -l priority -d 'Pin priority' -r
+complete -c oma -n "__fish_use_subcommand" -f -a "unpin" -d 'Remove the pin(s) of package(s)'
+complete -c oma -n "__fish_use_subcommand" -f -a "export" -d 'Export manually installed packages, holds, topics, mirrors and sources to a manifest'
+complete -c oma -n "__fish_use_subcommand" -f -a "import" -d 'Restore system state from a manifest created by `oma export`'
+complete -c oma -n "__fish_seen_subcommand_from export" -s o -l output -d 'Write manifest to file instead of stdout' -r
+complete -c oma -n "__fish_seen_subcommand_from export" -l json -d 'Set output format as JSON'
+complete -c oma -n "__fish_seen_subcommand_from import" -l dry-run -d 'Run oma in “dry-run” mode'
 complete -c oma -n "__fish_use_subcommand" -f -a "pkgnames"
 complete -c oma -n "__fish_use_subcommand" -f -a "mirror" -d ''
 complete -c oma -n "__fish_use_subcommand" -f -a "mirrors" -d ''
@@ -57,6 +73,7 @@ complete -c oma -n "__fish_seen_subcommand_from install" -s y -l yes -d 'Bypass
 complete -c oma -n "__fish_seen_subcommand_from install" -l force-yes -d 'Ignore repository and package dependency issues'
 complete -c oma -n "__fish_seen_subcommand_from install" -l force-confnew -d 'Replace configuration file(s) in the system those shipped in the package(s) to be installed (invokes `dpkg --force-confnew`)'
 complete -c oma -n "__fish_seen_subcommand_from install" -l dry-run -d 'Run oma in “dry-run” mode'
+complete -c oma -n "__fish_seen_subcommand_from install" -l json -d 'Print the pending operation, progress and result as JSON lines'
 complete -c oma -n "__fish_seen_subcommand_from install" -l no-refresh-topics -d 'Do not refresh topics manifest.json file'
 complete -c oma -n "__fish_seen_subcommand_from install" -l debug -d 'Run oma with debug mode'
 complete -c oma -n "__fish_seen_subcommand_from install" -l no-color -d 'No color output to result'
@@ -70,8 +87,10 @@ complete -c oma -n "__fish_seen_subcommand_from upgrade" -s y -l yes -d 'Bypass
 complete -c oma -n "__fish_seen_subcommand_from upgrade" -l force-yes -d 'Ignore repository and package dependency issues'
 complete -c oma -n "__fish_seen_subcommand_from upgrade" -l force-confnew -d 'Replace configuration file(s) in the system those shipped in the package(s) to be installed (invokes `dpkg --force-confnew`)'
 complete -c oma -n "__fish_seen_subcommand_from upgrade" -l dry-run -d 'Run oma in “dry-run” mode'
+complete -c oma -n "__fish_seen_subcommand_from upgrade" -l json -d 'Print the pending operation, progress and result as JSON lines'
 complete -c oma -n "__fish_seen_subcommand_from upgrade" -l no-refresh-topics -d 'Do not refresh topics manifest.json file'
 complete -c oma -n "__fish_seen_subcommand_from upgrade" -l autoremove -d 'Auto remove unnecessary package(s)'
+complete -c oma -n "__fish_seen_subcommand_from upgrade" -l offline -d 'Download package(s) only, then install them at next boot'
 complete -c oma -n "__fish_seen_subcommand_from upgrade" -l debug -d 'Run oma with debug mode'
 complete -c oma -n "__fish_seen_subcommand_from upgrade" -l no-color -d 'No color output to result'
 complete -c oma -n "__fish_seen_subcommand_from upgrade" -l follow-terminal-color -d 'Output result with terminal theme color'
@@ -96,6 +115,7 @@ complete -c oma -n "__fish_seen_subcommand_from remove" -l force-yes -d 'Ignore
 complete -c oma -n "__fish_seen_subcommand_from remove" -l no-autoremove -d 'Do not remove package(s) without reverse dependencies'
 complete -c oma -n "__fish_seen_subcommand_from remove" -l remove-config -d 'Remove package(s) also remove configuration file(s), like apt purge'
 complete -c oma -n "__fish_seen_subcommand_from remove" -l dry-run -d 'Run oma in “dry-run” mode'
+complete -c oma -n "__fish_seen_subcommand_from remove" -l json -d 'Print the pending operation, progress and result as JSON lines'
 complete -c oma -n "__fish_seen_subcommand_from remove" -l debug -d 'Run oma with debug mode'
 complete -c oma -n "__fish_seen_subcommand_from remove" -l no-color -d 'No color output to result'
 complete -c oma -n "__fish_seen_subcommand_from remove" -l follow-terminal-color -d 'Output result with terminal theme color'
@@ -108,6 +128,7 @@ complete -c oma -n "__fish_seen_subcommand_from purge" -s y -l yes -d 'Bypass co
 complete -c oma -n "__fish_seen_subcommand_from purge" -l force-yes -d 'Ignore repository and package dependency issues'
 complete -c oma -n "__fish_seen_subcommand_from purge" -l no-autoremove -d 'Do not remove package(s) without reverse dependencies'
 complete -c oma -n "__fish_seen_subcommand_from purge" -l dry-run -d 'Run oma in “dry-run” mode'
+complete -c oma -n "__fish_seen_subcommand_from purge" -l json -d 'Print the pending operation, progress and result as JSON lines'
 complete -c oma -n "__fish_seen_subcommand_from purge" -l debug -d 'Run oma with debug mode'
 complete -c oma -n "__fish_seen_subcommand_from purge" -l no-color -d 'No color output to result'
 complete -c oma -n "__fish_seen_subcommand_from purge" -l follow-terminal-color -d 'Output result with terminal theme color'
@@ -155,6 +176,8 @@ complete -c oma -n "__fish_seen_subcommand_from provides" -lcomplete -c oma -n "
 complete -c oma -n "__fish_seen_subcommand_from provides" -s o -l apt-options -r
 complete -c oma -n "__fish_seen_subcommand_from provides" -l no-pager -l println -d 'Set output mode as current println mode'
 complete -c oma -n "__fish_seen_subcommand_from provides" -l bin -d 'Search binary of package(s)'
+complete -c oma -n "__fish_seen_subcommand_from provides" -l regex -d 'Treat pattern as a regular expression matching the full path'
+complete -c oma -n "__fish_seen_subcommand_from provides" -l glob -d 'Treat pattern as a glob matching the full path'
 complete -c oma -n "__fish_seen_subcommand_from provides" -l debug -d 'Run oma with debug mode'
 complete -c oma -n "__fish_seen_subcommand_from provides" -l no-color -d 'No color output to result'
 complete -c oma -n "__fish_seen_subcommand_from provides" -l follow-terminal-color -d 'Output result with terminal theme color'
@@ -235,8 +258,12 @@ complete -c oma -n "__fish_seen_subcommand_from history" -l follow-terminal-colo
 complete -c oma -n "__fish_seen_subcommand_from history" -l no-progress -d 'Do not display progress bar'
 complete -c oma -n "__fish_seen_subcommand_from history" -l no-check-dbus
 complete -c oma -n "__fish_seen_subcommand_from history" -s h -l help -d 'Print help (see more with \'--help\')'
+complete -c oma -n "__fish_seen_subcommand_from history" -f -a "show" -d 'Show details of a history entry'
+complete -c oma -n "__fish_seen_subcommand_from history; and __fish_seen_subcommand_from show" -l log -d 'Show dpkg output of this operation'
 complete -c oma -n "__fish_seen_subcommand_from undo" -lcomplete -c oma -n "__fish_seen_subcommand_from install" -l sysroot -d 'Set sysroot target directory' -r
 complete -c oma -n "__fish_seen_subcommand_from undo" -s o -l apt-options -r
+complete -c oma -n "__fish_seen_subcommand_from undo" -l to -d 'Undo all operations after the given history ID or date' -r
+complete -c oma -n "__fish_seen_subcommand_from undo" -s y -l yes -d 'Bypass confirmation prompts'
 complete -c oma -n "__fish_seen_subcommand_from undo" -l debug -d 'Run oma with debug mode'
 complete -c oma -n "__fish_seen_subcommand_from undo" -l no-color -d 'No color output to result'
 complete -c oma -n "__fish_seen_subcommand_from undo" -l follow-terminal-color -d 'Output result with terminal theme color'
@@ -268,6 +295,8 @@ complete -c oma -n "__fish_seen_subcommand_from tui" -l no-check-dbus
 complete -c oma -n "__fish_seen_subcommand_from tui" -s h -l help -d 'Print help (see more with \'--help\')'
 complete -c oma -n "__fish_seen_subcommand_from topics" -l opt-in -d 'Enroll in one or more topic(s), delimited by space' -r
 complete -c oma -n "__fish_seen_subcommand_from topics" -l opt-out -d 'Withdraw from one or more topic(s) and rollback to stable versions, delimited by space' -r
+complete -c oma -n "__fish_seen_subcommand_from topics" -s l -l list -d 'List available topics, use cached data when offline'
+complete -c oma -n "__fish_seen_subcommand_from topics; and not __fish_seen_subcommand_from show" -f -a "show" -d 'Show packages of a topic compared with the stable versions'
 complete -c oma -n "__fish_seen_subcommand_from topics" -lcomplete -c oma -n "__fish_seen_subcommand_from install" -l sysroot -d 'Set sysroot target directory' -r
 complete -c oma -n "__fish_seen_subcommand_from topics" -s o -l apt-options -r
 complete -c oma -n "__fish_seen_subcommand_from topics" -l dry-run -d 'Run oma in “dry-run” mode'
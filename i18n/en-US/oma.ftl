@@ -226,3 +226,89 @@ auth-need-permission = The repository shipping the package(s) may require author
 lack-auth-config-1 = oma is unable to access the specified package(s) from the repository due to missing authorization configurations. 
 lack-auth-config-2 = Please verify if your APT authorization configurations (/etc/apt/auth.conf.d) were set up correctly.
 success = The operation was completed successfully.
+offline-upgrade-prepared = All package(s) have been downloaded, the upgrade will be installed at next boot.
+offline-upgrade-reboot-tips = Please reboot your system to apply the upgrade.
+offline-upgrade-not-prepared = No offline upgrade has been prepared, please use `oma upgrade --offline' first.
+offline-upgrade-changed = System state has changed since the offline upgrade was prepared, oma has aborted the upgrade.
+offline-upgrade-start = Installing offline upgrade ...
+no-changelog = Unable to find changelog of package { $name }.
+failed-to-get-changelog = Failed to download changelog from { $url }.
+changelog-title = Changelog of { $name }
+changelog-no-newer-entry = No changelog entry newer than the installed version.
+question-tips-with-changelog-with-gui = Press [q] to end review, [c] to view changelogs, [Ctrl-c] to abort, [PgUp/Dn], arrow keys, or mouse wheel to scroll.
+question-tips-with-changelog = Press [q] to end review, [c] to view changelogs, [Ctrl-c] to abort, [PgUp/Dn] or arrow keys to scroll.
+history-no-term-log = No dpkg log was recorded for history entry #{ $id }.
+undo-nothing-to-do = There is no operation to undo after the specified point.
+undo-invalid-target = Invalid undo target: { $target }, expected a history ID or a date (YYYY-MM-DD [HH:MM:SS]).
+undo-version-unavailable = { $pkg } is no longer available from any mirror and will not be rolled back.
+undo-old-version-unknown = The previous version of { $pkg } was not recorded, it will not be rolled back.
+undo-failed-included = History entry #{ $id } did not complete successfully, some of its recorded changes may not have been applied.
+history-cmdline = Command line
+history-user = Requested by
+history-duration = Duration
+history-duration-seconds = { $secs }s
+history-error = Error
+export-done = Exported system state to { $p }.
+import-unsupported-version = Manifest version { $version } is not supported by this version of oma, please upgrade oma first.
+import-pkg-not-found = Package { $name } is not available from any enabled source, skipping.
+import-hold-not-installed = Package { $name } is not installed, skipping holding it.
+import-invalid-source = Invalid source file name { $name }, skipping.
+import-source-exists = { $p } already exists with different content, skipping.
+import-source-added = Added source file { $p }.
+import-sources-restored = Restored the sources, mirrors and topics changed by the import.
+apply-pkg-not-found = Package { $name } in the state file does not exist.
+apply-version-not-found = Version { $version } of package { $name } in the state file is not available.
+apply-conflict = Package { $name } is listed as both present and absent in the state file.
+apply-held-not-installed = Package { $name } is to be held but not installed, please also list it in `present`.
+apply-drift-missing = { $name } should be installed.
+apply-drift-version = { $name } should be at version { $version } (currently { $current }).
+apply-drift-absent = { $name } should be removed.
+apply-drift-hold = { $name } should be held.
+apply-drift-found = The system differs from the state file ({ $count } item(s)).
+apply-no-drift = The system is already in the declared state.
+mirror-latency = Latency
+topics-list-name = Name
+topics-list-description = Description
+topics-list-enabled = Enabled
+topics-list-cached = Unable to fetch topic metadata, showing cached data from { $date } ({ $age } ago).
+topics-show-package = Package
+topics-show-topic-version = Topic Version
+topics-show-installed-version = Installed
+topics-show-stable-version = Stable
+topics-show-download-size = Estimated download size: { $size }
+topics-show-unavailable = Unable to fetch package list of topic { $name }.
+topics-closed = Topic(s) closed upstream and disabled: { $topics }.
+topics-closed-pending = Packages from closed topics will be reverted to stable versions in the next interactive run of `oma upgrade'.
+invalid-custom-mirror = Invalid mirror { $mirror } in { $p }: { $reason }
+cnf-install-prompt = Install { $pkg } to provide `{ $cmd }'?
+cnf-rerun = Running `{ $cmd }' again ...
+cnf-rerun-failed = Failed to run `{ $cmd }'.
+building-contents-index = Building contents index ...
+build-contents-index-failed = Failed to build contents index, searching files will be slower: { $e }
+invalid-search-pattern = Invalid search pattern: { $pattern }
+auto-upgrade-invalid-window = Invalid blackout window `{ $window }' in [auto_upgrade], expected HH:MM-HH:MM.
+auto-upgrade-blackout = Skipping automatic upgrade: within the blackout window { $window }.
+auto-upgrade-on-battery = Skipping automatic upgrade: the system is running on battery.
+auto-upgrade-nothing = No upgrades are allowed by the automatic upgrade policy.
+auto-upgrade-not-allowed = Skipping automatic upgrade: { $len } package(s) are not from repositories allowed by the policy: { $pkgs }.
+auto-upgrade-dropped = Not upgrading { $pkg }: it depends on { $deps }, which are not from repositories allowed by the policy.
+auto-upgrade-would-remove = Skipping automatic upgrade: { $len } package(s) would be removed.
+auto-upgrade-too-large = Skipping automatic upgrade: { $size } to download exceeds the limit of { $limit }.
+auto-upgrade-done = Automatically upgraded { $len } package(s).
+invalid-pin = Invalid pin in { $p }: { $pin }
+pin-target-not-found = Package { $name } has no version or repository matching `{ $target }'.
+pinned = Pinned { $name } to { $pin } (priority { $priority }).
+pin-candidate = The candidate version of { $name } is now { $version }.
+unpinned = Removed the pin of { $name }.
+not-pinned = { $name } is not pinned.
+no-pins = No package is pinned.
+pin-effect-hold-back = Holds back { $version }
+pin-effect-downgrade = Downgrades to { $version }
+pin-effect-no-candidate = No candidate
+pin-list-package = Package
+pin-list-pin = Pin
+pin-list-priority = Priority
+pin-list-installed = Installed
+pin-list-candidate = Candidate
+pin-list-effect = Effect
+pin-blocks-upgrade = { $name } is pinned to { $pin }, version { $version } will not be used.
@@ -222,3 +222,89 @@ auth-need-permission = 软件包所在的软件源可能需要访问鉴权，请
 lack-auth-config-1 = 由于缺少鉴权配置，oma 无法从软件源访问指定的软件包。
 lack-auth-config-2 = 请检查您的 APT 鉴权配置 (/etc/apt/auth.conf.d)。
 success = 操作已成功完成。
+offline-upgrade-prepared = 已下载所有软件包，将在下次启动时安装更新。
+offline-upgrade-reboot-tips = 请重启系统以应用更新。
+offline-upgrade-not-prepared = 未准备离线更新，请先运行 `oma upgrade --offline'。
+offline-upgrade-changed = 准备离线更新后系统状态已发生变化，oma 已中止本次更新。
+offline-upgrade-start = 正在安装离线更新 ...
+no-changelog = 无法找到软件包 { $name } 的更新日志。
+failed-to-get-changelog = 无法从 { $url } 下载更新日志。
+changelog-title = { $name } 的更新日志
+changelog-no-newer-entry = 没有比已安装版本更新的更新日志条目。
+question-tips-with-changelog-with-gui = 按 [q] 结束审阅并应用更改，按 [c] 查看更新日志，按 [Ctrl-c] 中止操作，按 [PgUp/Dn]、方向键或使用鼠标滚轮翻页。
+question-tips-with-changelog = 按 [q] 结束审阅并应用更改，按 [c] 查看更新日志，按 [Ctrl-c] 中止操作，按 [PgUp/Dn] 或方向键翻页。
+history-no-term-log = 历史记录 #{ $id } 没有记录 dpkg 日志。
+undo-nothing-to-do = 指定的时间点之后没有需要撤销的操作。
+undo-invalid-target = 无效的撤销目标：{ $target }，应为历史记录 ID 或日期（YYYY-MM-DD [HH:MM:SS]）。
+undo-version-unavailable = 所有镜像源中均已没有 { $pkg }，将不会回滚该软件包。
+undo-old-version-unknown = 历史记录中没有 { $pkg } 的旧版本号，将不会回滚该软件包。
+undo-failed-included = 历史记录 #{ $id } 未成功完成，其中记录的部分更改可能没有被应用。
+history-cmdline = 命令行
+history-user = 请求用户
+history-duration = 耗时
+history-duration-seconds = { $secs } 秒
+history-error = 错误
+export-done = 已将系统状态导出至 { $p }。
+import-unsupported-version = 当前版本的 oma 不支持版本为 { $version } 的清单，请先升级 oma。
+import-pkg-not-found = 已启用的软件源中没有软件包 { $name }，跳过。
+import-hold-not-installed = 软件包 { $name } 未安装，跳过锁定该软件包。
+import-invalid-source = 无效的软件源文件名 { $name }，跳过。
+import-source-exists = { $p } 已存在且内容不同，跳过。
+import-source-added = 已添加软件源文件 { $p }。
+import-sources-restored = 已还原导入时更改的软件源、镜像源与测试源。
+apply-pkg-not-found = 状态文件中的软件包 { $name } 不存在。
+apply-version-not-found = 状态文件中软件包 { $name } 的版本 { $version } 不可用。
+apply-conflict = 软件包 { $name } 在状态文件中同时被列为需要安装和需要删除。
+apply-held-not-installed = 软件包 { $name } 需要被锁定但未安装，请同时将其列入 `present`。
+apply-drift-missing = { $name } 应当被安装。
+apply-drift-version = { $name } 的版本应当为 { $version }（当前为 { $current }）。
+apply-drift-absent = { $name } 应当被删除。
+apply-drift-hold = { $name } 应当被锁定。
+apply-drift-found = 系统状态与状态文件不一致（共 { $count } 项）。
+apply-no-drift = 系统已处于状态文件声明的状态。
+mirror-latency = 延迟
+topics-list-name = 名称
+topics-list-description = 描述
+topics-list-enabled = 已启用
+topics-list-cached = 无法获取测试源元数据，正在显示 { $date }（{ $age } 前）缓存的数据。
+topics-show-package = 软件包
+topics-show-topic-version = 测试源版本
+topics-show-installed-version = 已安装版本
+topics-show-stable-version = 稳定版本
+topics-show-download-size = 预计下载大小：{ $size }
+topics-show-unavailable = 无法获取测试源 { $name } 的软件包列表。
+topics-closed = 以下测试源已被上游关闭并禁用：{ $topics }。
+topics-closed-pending = 来自已关闭测试源的软件包将在下次交互式运行 `oma upgrade' 时回退到稳定版本。
+invalid-custom-mirror = { $p } 中的镜像源 { $mirror } 无效：{ $reason }
+cnf-install-prompt = 是否安装 { $pkg } 以提供 `{ $cmd }'？
+cnf-rerun = 正在重新运行 `{ $cmd }' ...
+cnf-rerun-failed = 无法运行 `{ $cmd }'。
+building-contents-index = 正在构建 Contents 索引 ...
+build-contents-index-failed = 无法构建 Contents 索引，搜索文件将会变慢：{ $e }
+invalid-search-pattern = 无效的搜索模式：{ $pattern }
+auto-upgrade-invalid-window = [auto_upgrade] 中的禁止时段 `{ $window }' 无效，应为 HH:MM-HH:MM 格式。
+auto-upgrade-blackout = 跳过自动更新：当前处于禁止时段 { $window } 内。
+auto-upgrade-on-battery = 跳过自动更新：系统正在使用电池供电。
+auto-upgrade-nothing = 自动更新策略没有允许任何更新。
+auto-upgrade-not-allowed = 跳过自动更新：{ $len } 个软件包不来自策略允许的软件源：{ $pkgs }。
+auto-upgrade-dropped = 不更新 { $pkg }：其依赖 { $deps } 不来自策略允许的软件源。
+auto-upgrade-would-remove = 跳过自动更新：将会删除 { $len } 个软件包。
+auto-upgrade-too-large = 跳过自动更新：需下载 { $size }，超过了 { $limit } 的上限。
+auto-upgrade-done = 已自动更新 { $len } 个软件包。
+invalid-pin = { $p } 中有无效的固定项：{ $pin }
+pin-target-not-found = 软件包 { $name } 没有匹配 `{ $target }' 的版本或软件源。
+pinned = 已将 { $name } 固定到 { $pin }（优先级 { $priority }）。
+pin-candidate = { $name } 的候选版本现为 { $version }。
+unpinned = 已移除 { $name } 的固定。
+not-pinned = { $name } 未被固定。
+no-pins = 没有固定任何软件包。
+pin-effect-hold-back = 阻止更新到 { $version }
+pin-effect-downgrade = 降级到 { $version }
+pin-effect-no-candidate = 无候选版本
+pin-list-package = 软件包
+pin-list-pin = 固定
+pin-list-priority = 优先级
+pin-list-installed = 已安装
+pin-list-candidate = 候选版本
+pin-list-effect = 效果
+pin-blocks-upgrade = { $name } 已固定到 { $pin }，将不会使用版本 { $version }。
@@ -225,3 +225,89 @@ tui-no-system-update = 目前沒有系統更新。
 tui-continue-tips = 請按 [c] 繼續。
 dpkg-triggers-only-a-non-zero = `dpkg --triggers-only -a` 返回了錯誤。
 success = 操作已成功完成。
+offline-upgrade-prepared = 已下載所有軟體套件，將在下次開機時安裝更新。
+offline-upgrade-reboot-tips = 請重新啟動系統以套用更新。
+offline-upgrade-not-prepared = 未準備離線更新，請先執行 `oma upgrade --offline'。
+offline-upgrade-changed = 準備離線更新後系統狀態已發生變化，oma 已中止本次更新。
+offline-upgrade-start = 正在安裝離線更新 ...
+no-changelog = 無法找到軟體套件 { $name } 的更新日誌。
+failed-to-get-changelog = 無法從 { $url } 下載更新日誌。
+changelog-title = { $name } 的更新日誌
+changelog-no-newer-entry = 沒有比已安裝版本更新的更新日誌條目。
+question-tips-with-changelog-with-gui = 按 [q] 結束檢閱並套用更改，按 [c] 檢視更新日誌，按 [Ctrl-c] 中止操作，按 [PgUp/Dn]、方向鍵或使用滑鼠滾輪翻頁。
+question-tips-with-changelog = 按 [q] 結束檢閱並套用更改，按 [c] 檢視更新日誌，按 [Ctrl-c] 中止操作，按 [PgUp/Dn] 或方向鍵翻頁。
+history-no-term-log = 歷史記錄 #{ $id } 沒有記錄 dpkg 日誌。
+undo-nothing-to-do = 指定的時間點之後沒有需要撤銷的操作。
+undo-invalid-target = 無效的撤銷目標：{ $target }，應為歷史記錄 ID 或日期（YYYY-MM-DD [HH:MM:SS]）。
+undo-version-unavailable = 所有鏡像源中均已沒有 { $pkg }，將不會回滾該軟體包。
+undo-old-version-unknown = 歷史記錄中沒有 { $pkg } 的舊版本號，將不會回滾該軟體包。
+undo-failed-included = 歷史記錄 #{ $id } 未成功完成，其中記錄的部分更改可能沒有被套用。
+history-cmdline = 命令列
+history-user = 請求使用者
+history-duration = 耗時
+history-duration-seconds = { $secs } 秒
+history-error = 錯誤
+export-done = 已將系統狀態匯出至 { $p }。
+import-unsupported-version = 目前版本的 oma 不支援版本為 { $version } 的清單，請先升級 oma。
+import-pkg-not-found = 已啟用的軟體源中沒有軟體包 { $name }，跳過。
+import-hold-not-installed = 軟體包 { $name } 未安裝，跳過鎖定該軟體包。
+import-invalid-source = 無效的軟體源檔名 { $name }，跳過。
+import-source-exists = { $p } 已存在且內容不同，跳過。
+import-source-added = 已新增軟體源檔案 { $p }。
+import-sources-restored = 已還原匯入時變更的軟體庫、鏡像源與測試庫。
+apply-pkg-not-found = 狀態檔案中的軟體包 { $name } 不存在。
+apply-version-not-found = 狀態檔案中軟體包 { $name } 的版本 { $version } 無法使用。
+apply-conflict = 軟體包 { $name } 在狀態檔案中同時被列為需要安裝和需要刪除。
+apply-held-not-installed = 軟體包 { $name } 需要被鎖定但未安裝，請同時將其列入 `present`。
+apply-drift-missing = { $name } 應當被安裝。
+apply-drift-version = { $name } 的版本應當為 { $version }（目前為 { $current }）。
+apply-drift-absent = { $name } 應當被刪除。
+apply-drift-hold = { $name } 應當被鎖定。
+apply-drift-found = 系統狀態與狀態檔案不一致（共 { $count } 項）。
+apply-no-drift = 系統已處於狀態檔案宣告的狀態。
+mirror-latency = 延遲
+topics-list-name = 名稱
+topics-list-description = 描述
+topics-list-enabled = 已啟用
+topics-list-cached = 無法取得測試源元資料，正在顯示 { $date }（{ $age } 前）快取的資料。
+topics-show-package = 軟體包
+topics-show-topic-version = 測試源版本
+topics-show-installed-version = 已安裝版本
+topics-show-stable-version = 穩定版本
+topics-show-download-size = 預計下載大小：{ $size }
+topics-show-unavailable = 無法取得測試源 { $name } 的軟體包列表。
+topics-closed = 以下測試庫已被上游關閉並停用：{ $topics }。
+topics-closed-pending = 來自已關閉測試庫的軟體包將在下次互動式執行 `oma upgrade' 時回退到穩定版本。
+invalid-custom-mirror = { $p } 中的鏡像源 { $mirror } 無效：{ $reason }
+cnf-install-prompt = 是否安裝 { $pkg } 以提供 `{ $cmd }'？
+cnf-rerun = 正在重新執行 `{ $cmd }' ...
+cnf-rerun-failed = 無法執行 `{ $cmd }'。
+building-contents-index = 正在建構 Contents 索引 ...
+build-contents-index-failed = 無法建構 Contents 索引，搜尋檔案將會變慢：{ $e }
+invalid-search-pattern = 無效的搜尋模式：{ $pattern }
+auto-upgrade-invalid-window = [auto_upgrade] 中的禁止時段 `{ $window }' 無效，應為 HH:MM-HH:MM 格式。
+auto-upgrade-blackout = 略過自動更新：目前處於禁止時段 { $window } 內。
+auto-upgrade-on-battery = 略過自動更新：系統正在使用電池供電。
+auto-upgrade-nothing = 自動更新策略沒有允許任何更新。
+auto-upgrade-not-allowed = 略過自動更新：{ $len } 個軟體包不來自策略允許的軟體庫：{ $pkgs }。
+auto-upgrade-dropped = 不更新 { $pkg }：其依賴 { $deps } 不來自策略允許的軟體庫。
+auto-upgrade-would-remove = 略過自動更新：將會移除 { $len } 個軟體包。
+auto-upgrade-too-large = 略過自動更新：需下載 { $size }，超過了 { $limit } 的上限。
+auto-upgrade-done = 已自動更新 { $len } 個軟體包。
+invalid-pin = { $p } 中有無效的固定項：{ $pin }
+pin-target-not-found = 軟體包 { $name } 沒有符合 `{ $target }' 的版本或軟體源。
+pinned = 已將 { $name } 固定到 { $pin }（優先順序 { $priority }）。
+pin-candidate = { $name } 的候選版本現為 { $version }。
+unpinned = 已移除 { $name } 的固定。
+not-pinned = { $name } 未被固定。
+no-pins = 沒有固定任何軟體包。
+pin-effect-hold-back = 阻止更新到 { $version }
+pin-effect-downgrade = 降級到 { $version }
+pin-effect-no-candidate = 無候選版本
+pin-list-package = 軟體包
+pin-list-pin = 固定
+pin-list-priority = 優先順序
+pin-list-installed = 已安裝
+pin-list-candidate = 候選版本
+pin-list-effect = 效果
+pin-blocks-upgrade = { $name } 已固定到 { $pin }，將不會使用版本 { $version }。
@@ -48,6 +48,15 @@ impl<'a> Pager<'a> {
         Ok(res)
     }
 
+    /// Allow user to press [c] to exit the pager with [`PagerExit::ShowChangelog`]
+    pub fn with_changelog_key(mut self) -> Self {
+        if let Pager::External(app) = &mut self {
+            app.changelog_key = true;
+        }
+
+        self
+    }
+
     /// Get writer to writer something to pager
     pub fn get_writer(&mut self) -> io::Result<Box<dyn Write + '_>> {
         let res = match self {
@@ -140,6 +149,8 @@ pub struct OmaPager<'a> {
     mode: TuiMode,
     /// A reference to a trait object that provides UI text for the pager.
     ui_text: &'a dyn PagerUIText,
+    /// Whether to allow user to press [c] to exit the pager and view changelogs.
+    changelog_key: bool,
 }
 
 impl<'a> Write for OmaPager<'a> {
@@ -178,6 +189,7 @@ pub enum PagerExit {
     NormalExit,
     Sigint,
     DryRun,
+    ShowChangelog,
 }
 
 impl From<PagerExit> for i32 {
@@ -186,6 +198,7 @@ impl From<PagerExit> for i32 {
             PagerExit::NormalExit => 0,
             PagerExit::Sigint => 130,
             PagerExit::DryRun => 0,
+            PagerExit::ShowChangelog => 0,
         }
     }
 }
@@ -212,6 +225,7 @@ impl<'a> OmaPager<'a> {
             current_result_index: 0,
             mode: TuiMode::Noemal,
             ui_text,
+            changelog_key: false,
         }
     }
     /// Run the pager
@@ -273,6 +287,11 @@ impl<'a> OmaPager<'a> {
                                 }
                                 return Ok(PagerExit::NormalExit);
                             }
+                            KeyCode::Char('c')
+                                if self.changelog_key && self.mode != TuiMode::SearchInputText =>
+                            {
+                                return Ok(PagerExit::ShowChangelog);
+                            }
                             KeyCode::Down => {
                                 self.down();
                             }
@@ -1,8 +1,12 @@
-use oma_contents::searcher::{pure_search, Mode};
+use oma_contents::searcher::{pure_search, Mode, QueryKind};
 
 fn main() {
-    pure_search("/var/lib/apt/lists", Mode::Files, "apt", |(pkg, file)| {
-        println!("{pkg}: {file}")
-    })
+    pure_search(
+        "/var/lib/apt/lists",
+        Mode::Files,
+        "apt",
+        QueryKind::Plain,
+        |(pkg, file)| println!("{pkg}: {file}"),
+    )
     .unwrap();
 }
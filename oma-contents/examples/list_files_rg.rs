@@ -1,8 +1,12 @@
-use oma_contents::searcher::{ripgrep_search, Mode};
+use oma_contents::searcher::{ripgrep_search, Mode, QueryKind};
 
 fn main() {
-    ripgrep_search("/var/lib/apt/lists", Mode::Files, "apt", |(pkg, file)| {
-        println!("{pkg}: {file}")
-    })
+    ripgrep_search(
+        "/var/lib/apt/lists",
+        Mode::Files,
+        "apt",
+        QueryKind::Plain,
+        |(pkg, file)| println!("{pkg}: {file}"),
+    )
     .unwrap();
 }
@@ -0,0 +1,744 @@
+//! On-disk index of Contents files
+//!
+//! The index holds the Contents entries (path and package names) of every Contents file in
+//! the lists directory, one section per file. A section is only rebuilt when the checksum of
+//! its Contents file changes.
+//!
+//! Layout:
+//!
+//! ```text
+//! OMA-CONTENTS-INDEX 2
+//! <kind>\t<sha256>\t<offset>\t<length>\t<keys length>\t<file name>
+//! ...
+//! <empty line>
+//! <sections>
+//! ```
+//!
+//! Offsets are relative to the first byte after the empty line. A section is a list of zstd
+//! compressed blocks of `<path>\t<package>[,<package>...]` lines, followed by the zstd
+//! compressed keys:
+//!
+//! ```text
+//! <block length>[,<block length>...]
+//! <number of package keys>
+//! <package>\t<block>[,<block>...]
+//! ...
+//! <path component>\t<block>[,<block>...]
+//! ...
+//! ```
+//!
+//! Keys are sorted, a search only decompresses the blocks of the keys matching the query.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use faster_hex::hex_string;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::{
+    parser::single_line,
+    query::Matcher,
+    searcher::{contents_files, contents_reader, pkg_name, prefix, ContentsKind, Mode, QueryKind},
+    OmaContentsError,
+};
+
+const MAGIC: &str = "OMA-CONTENTS-INDEX 2";
+
+/// Number of lines in a compressed block
+const BLOCK_LINES: usize = 1024;
+
+#[derive(Debug)]
+struct Section {
+    kind: ContentsKind,
+    checksum: String,
+    offset: u64,
+    len: u64,
+    keys_len: u64,
+    name: String,
+}
+
+/// Keys of a section
+#[derive(Debug, Default)]
+struct Keys {
+    /// Offset and length of each block, relative to the section
+    blocks: Vec<(u64, u64)>,
+    pkgs: Vec<(String, Vec<u32>)>,
+    components: Vec<(String, Vec<u32>)>,
+}
+
+#[derive(Debug, Default)]
+pub struct IndexSummary {
+    /// Sections copied from the previous index
+    pub reused: usize,
+    /// Sections built from Contents files
+    pub rebuilt: usize,
+}
+
+/// Build or update the index of all Contents files in `lists_dir`
+pub fn build_index(
+    lists_dir: impl AsRef<Path>,
+    index: impl AsRef<Path>,
+) -> Result<IndexSummary, OmaContentsError> {
+    let index = index.as_ref();
+    let files = contents_files(lists_dir.as_ref())?;
+
+    let old = read_header(index).ok();
+
+    if let Some(parent) = index.parent() {
+        fs::create_dir_all(parent).map_err(|e| io_err(parent, e))?;
+    }
+
+    let data_path = tmp_path(index, "data");
+    let mut data = BufWriter::new(File::create(&data_path).map_err(|e| io_err(&data_path, e))?);
+
+    let mut summary = IndexSummary::default();
+    let mut sections = vec![];
+    let mut offset = 0;
+
+    for (kind, path) in files {
+        let name = path
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let checksum = checksum(&path)?;
+
+        let reuse = old.as_ref().and_then(|(sections, data_start)| {
+            sections
+                .iter()
+                .find(|x| x.name == name && x.kind == kind && x.checksum == checksum)
+                .map(|x| (x, *data_start))
+        });
+
+        let (len, keys_len) = match reuse {
+            Some((section, data_start)) => {
+                debug!("Reuse contents index section of {name}");
+                summary.reused += 1;
+                copy_section(index, data_start + section.offset, section.len, &mut data)?;
+                (section.len, section.keys_len)
+            }
+            None => {
+                debug!("Build contents index section of {name}");
+                summary.rebuilt += 1;
+                write_section(&path, &mut data).map_err(|e| io_err(&data_path, e))?
+            }
+        };
+
+        sections.push(Section {
+            kind,
+            checksum,
+            offset,
+            len,
+            keys_len,
+            name,
+        });
+
+        offset += len;
+    }
+
+    data.flush().map_err(|e| io_err(&data_path, e))?;
+    drop(data);
+
+    let index_tmp = tmp_path(index, "tmp");
+    let mut f = BufWriter::new(File::create(&index_tmp).map_err(|e| io_err(&index_tmp, e))?);
+
+    let mut header = format!("{MAGIC}\n");
+    for i in &sections {
+        header.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\n",
+            i.kind.as_str(),
+            i.checksum,
+            i.offset,
+            i.len,
+            i.keys_len,
+            i.name
+        ));
+    }
+    header.push('\n');
+
+    f.write_all(header.as_bytes())
+        .and_then(|_| io::copy(&mut File::open(&data_path)?, &mut f))
+        .and_then(|_| f.flush())
+        .map_err(|e| io_err(&index_tmp, e))?;
+
+    drop(f);
+
+    fs::rename(&index_tmp, index).map_err(|e| io_err(index, e))?;
+    fs::remove_file(&data_path).ok();
+
+    Ok(summary)
+}
+
+/// Search the index, same as [`crate::searcher::pure_search`]
+///
+/// Returns [`OmaContentsError::IndexNotAvailable`] if the index does not exist or is
+/// older than the Contents files, the caller should fall back to searching Contents files.
+pub fn index_search(
+    lists_dir: impl AsRef<Path>,
+    index: impl AsRef<Path>,
+    mode: Mode,
+    query: &str,
+    kind: QueryKind,
+    mut cb: impl FnMut((String, String)),
+) -> Result<(), OmaContentsError> {
+    let index = index.as_ref();
+
+    let (sections, data_start) = read_header(index)?;
+
+    if !is_fresh(lists_dir.as_ref(), index, &sections)? {
+        debug!("Contents index is outdated");
+        return Err(OmaContentsError::IndexNotAvailable);
+    }
+
+    let matcher = Matcher::new(mode, query, kind)?;
+
+    let mut has_result = false;
+
+    for section in sections.iter().filter(|x| x.kind == mode.kind()) {
+        let mut f = File::open(index).map_err(|e| io_err(index, e))?;
+        let start = data_start + section.offset;
+
+        let keys = read_zstd(
+            &mut f,
+            start + section.len - section.keys_len,
+            section.keys_len,
+        )
+        .and_then(|x| parse_keys(&x).ok_or_else(|| invalid_data("bad keys")))
+        .map_err(|e| io_err(index, e))?;
+
+        let blocks = match candidate_blocks(&keys, &matcher, mode, kind) {
+            Some(blocks) => blocks.into_iter().collect::<Vec<_>>(),
+            None => (0..keys.blocks.len() as u32).collect(),
+        };
+
+        debug!(
+            "Search {} of {} blocks in {}",
+            blocks.len(),
+            keys.blocks.len(),
+            section.name
+        );
+
+        for id in blocks {
+            let Some((offset, len)) = keys.blocks.get(id as usize) else {
+                return Err(io_err(index, invalid_data("bad block")));
+            };
+
+            let block = read_zstd(&mut f, start + offset, *len).map_err(|e| io_err(index, e))?;
+
+            for line in block.lines() {
+                let Some((file, pkgs)) = line.rsplit_once('\t') else {
+                    continue;
+                };
+
+                for pkg in pkgs.split(',') {
+                    if matcher.is_match(pkg, file) {
+                        cb((pkg.to_string(), prefix(file)));
+                        has_result = true;
+                    }
+                }
+            }
+        }
+    }
+
+    if !has_result {
+        return Err(OmaContentsError::NoResult);
+    }
+
+    Ok(())
+}
+
+fn read_header(index: &Path) -> Result<(Vec<Section>, u64), OmaContentsError> {
+    let f = File::open(index).map_err(|_| OmaContentsError::IndexNotAvailable)?;
+    let mut reader = BufReader::new(f);
+
+    let mut buffer = String::new();
+    let mut data_start = 0;
+    let mut sections = vec![];
+
+    let mut read_line = |buffer: &mut String| -> Result<(), OmaContentsError> {
+        buffer.clear();
+        let len = reader
+            .read_line(buffer)
+            .map_err(|_| OmaContentsError::IndexNotAvailable)?;
+
+        if len == 0 {
+            return Err(OmaContentsError::IndexNotAvailable);
+        }
+
+        data_start += len as u64;
+
+        Ok(())
+    };
+
+    read_line(&mut buffer)?;
+
+    if buffer.trim_end() != MAGIC {
+        return Err(OmaContentsError::IndexNotAvailable);
+    }
+
+    loop {
+        read_line(&mut buffer)?;
+
+        let line = buffer.trim_end_matches('\n');
+
+        if line.is_empty() {
+            break;
+        }
+
+        sections.push(parse_section(line).ok_or(OmaContentsError::IndexNotAvailable)?);
+    }
+
+    Ok((sections, data_start))
+}
+
+fn parse_section(line: &str) -> Option<Section> {
+    let mut split = line.splitn(6, '\t');
+
+    Some(Section {
+        kind: ContentsKind::parse(split.next()?)?,
+        checksum: split.next()?.to_string(),
+        offset: split.next()?.parse().ok()?,
+        len: split.next()?.parse().ok()?,
+        keys_len: split.next()?.parse().ok()?,
+        name: split.next()?.to_string(),
+    })
+}
+
+fn parse_keys(s: &str) -> Option<Keys> {
+    let mut lines = s.lines();
+    let mut keys = Keys::default();
+
+    let mut offset = 0;
+    for len in lines.next()?.split(',').filter(|x| !x.is_empty()) {
+        let len = len.parse().ok()?;
+        keys.blocks.push((offset, len));
+        offset += len;
+    }
+
+    let pkgs_count = lines.next()?.parse().ok()?;
+
+    let parse_key = |line: &str| -> Option<(String, Vec<u32>)> {
+        let (key, blocks) = line.split_once('\t')?;
+        let blocks = blocks
+            .split(',')
+            .map(|x| x.parse().ok())
+            .collect::<Option<Vec<_>>>()?;
+
+        Some((key.to_string(), blocks))
+    };
+
+    keys.pkgs = lines
+        .by_ref()
+        .take(pkgs_count)
+        .map(parse_key)
+        .collect::<Option<_>>()?;
+
+    keys.components = lines.map(parse_key).collect::<Option<_>>()?;
+
+    Some(keys)
+}
+
+/// Blocks which may have matched lines, `None` if every block has to be searched
+fn candidate_blocks(
+    keys: &Keys,
+    matcher: &Matcher,
+    mode: Mode,
+    kind: QueryKind,
+) -> Option<BTreeSet<u32>> {
+    if matches!(mode, Mode::Files | Mode::FilesSrc | Mode::BinFiles) {
+        // 软件包名不多，逐个匹配即可
+        return Some(union(
+            keys.pkgs
+                .iter()
+                .filter(|(pkg, _)| matcher.is_pkg_match(pkg)),
+        ));
+    }
+
+    let literal = matcher.required_literal(kind)?;
+    let parts = literal.split('/').collect::<Vec<_>>();
+    let components = &keys.components;
+
+    let mut sets = vec![];
+
+    // 中间的部分都是完整的路径组成部分
+    for part in parts.get(1..parts.len() - 1).unwrap_or_default() {
+        let blocks = components
+            .binary_search_by(|(x, _)| x.as_str().cmp(part))
+            .map(|i| components[i].1.iter().copied().collect())
+            .unwrap_or_default();
+
+        sets.push(blocks);
+    }
+
+    let first = parts[0];
+    let last = parts[parts.len() - 1];
+
+    if parts.len() > 1 && !last.is_empty() {
+        // 最后一部分是某个路径组成部分的开头
+        let start = components.partition_point(|(x, _)| x.as_str() < last);
+        sets.push(union(
+            components[start..]
+                .iter()
+                .take_while(|(x, _)| x.starts_with(last)),
+        ));
+    }
+
+    if sets.is_empty() && !first.is_empty() {
+        // 第一部分是某个路径组成部分的结尾，只有一部分时可以是其中任意位置
+        sets.push(union(components.iter().filter(|(x, _)| {
+            if parts.len() > 1 {
+                x.ends_with(first)
+            } else {
+                x.contains(first)
+            }
+        })));
+    }
+
+    sets.into_iter().reduce(|a, b| &a & &b)
+}
+
+fn union<'a>(keys: impl Iterator<Item = &'a (String, Vec<u32>)>) -> BTreeSet<u32> {
+    keys.flat_map(|(_, blocks)| blocks.iter().copied())
+        .collect()
+}
+
+fn read_zstd(f: &mut File, offset: u64, len: u64) -> io::Result<String> {
+    f.seek(SeekFrom::Start(offset))?;
+    let data = zstd::stream::decode_all(f.take(len))?;
+
+    String::from_utf8(data).map_err(|_| invalid_data("not utf-8"))
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// The index covers the same Contents files and is newer than all of them
+fn is_fresh(
+    lists_dir: &Path,
+    index: &Path,
+    sections: &[Section],
+) -> Result<bool, OmaContentsError> {
+    let files = contents_files(lists_dir)?;
+
+    if files.len() != sections.len() {
+        return Ok(false);
+    }
+
+    let index_mtime = fs::metadata(index)
+        .and_then(|x| x.modified())
+        .map_err(|e| OmaContentsError::FailedToGetFileMetadata(index.display().to_string(), e))?;
+
+    for (kind, path) in files {
+        let name = path.file_name().map(|x| x.to_string_lossy());
+
+        if !sections
+            .iter()
+            .any(|x| x.kind == kind && name.as_deref() == Some(x.name.as_str()))
+        {
+            return Ok(false);
+        }
+
+        let mtime = fs::metadata(&path)
+            .and_then(|x| x.modified())
+            .map_err(|e| {
+                OmaContentsError::FailedToGetFileMetadata(path.display().to_string(), e)
+            })?;
+
+        if mtime > index_mtime {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn checksum(path: &Path) -> Result<String, OmaContentsError> {
+    let mut f = File::open(path).map_err(|e| io_err(path, e))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut f, &mut hasher).map_err(|e| io_err(path, e))?;
+
+    Ok(hex_string(&hasher.finalize()))
+}
+
+fn copy_section(
+    index: &Path,
+    offset: u64,
+    len: u64,
+    out: &mut impl Write,
+) -> Result<u64, OmaContentsError> {
+    let mut f = File::open(index).map_err(|e| io_err(index, e))?;
+    f.seek(SeekFrom::Start(offset))
+        .map_err(|e| io_err(index, e))?;
+
+    io::copy(&mut f.take(len), out).map_err(|e| io_err(index, e))
+}
+
+/// Write the section of a Contents file, returns the length of the section and its keys
+fn write_section(path: &Path, out: &mut impl Write) -> io::Result<(u64, u64)> {
+    let mut reader = BufReader::new(contents_reader(path).map_err(io::Error::other)?);
+
+    let mut builder = SectionBuilder::default();
+    let mut buffer = String::new();
+
+    while reader.read_line(&mut buffer)? > 0 {
+        if let Some((file, pkgs)) = single_line(&buffer) {
+            let pkgs = pkgs.into_iter().filter_map(pkg_name).collect::<Vec<_>>();
+
+            if !pkgs.is_empty() {
+                builder.push(file, &pkgs, out)?;
+            }
+        }
+
+        buffer.clear();
+    }
+
+    builder.finish(out)
+}
+
+#[derive(Default)]
+struct SectionBuilder {
+    block: String,
+    lines: usize,
+    block_lens: Vec<u64>,
+    pkgs: BTreeMap<String, Vec<u32>>,
+    components: BTreeMap<String, Vec<u32>>,
+}
+
+impl SectionBuilder {
+    fn push(&mut self, file: &str, pkgs: &[&str], out: &mut impl Write) -> io::Result<()> {
+        let id = self.block_lens.len() as u32;
+
+        self.block.push_str(file);
+        self.block.push('\t');
+        self.block.push_str(&pkgs.join(","));
+        self.block.push('\n');
+
+        for pkg in pkgs {
+            add_key(&mut self.pkgs, pkg, id);
+        }
+
+        for component in file.split('/').filter(|x| !x.is_empty()) {
+            add_key(&mut self.components, component, id);
+        }
+
+        self.lines += 1;
+
+        if self.lines == BLOCK_LINES {
+            self.flush(out)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if self.lines == 0 {
+            return Ok(());
+        }
+
+        let data = zstd::bulk::compress(self.block.as_bytes(), 0)?;
+        out.write_all(&data)?;
+        self.block_lens.push(data.len() as u64);
+
+        self.block.clear();
+        self.lines = 0;
+
+        Ok(())
+    }
+
+    fn finish(mut self, out: &mut impl Write) -> io::Result<(u64, u64)> {
+        self.flush(out)?;
+
+        let join = |blocks: &[u32]| {
+            blocks
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        let mut keys = self
+            .block_lens
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        keys.push_str(&format!("\n{}\n", self.pkgs.len()));
+
+        for (key, blocks) in self.pkgs.iter().chain(&self.components) {
+            keys.push_str(&format!("{key}\t{}\n", join(blocks)));
+        }
+
+        let keys = zstd::bulk::compress(keys.as_bytes(), 0)?;
+        out.write_all(&keys)?;
+
+        let blocks_len = self.block_lens.iter().sum::<u64>();
+
+        Ok((blocks_len + keys.len() as u64, keys.len() as u64))
+    }
+}
+
+fn add_key(map: &mut BTreeMap<String, Vec<u32>>, key: &str, id: u32) {
+    match map.get_mut(key) {
+        Some(blocks) => {
+            // 按顺序写入块，只需要和最后一个比较去重
+            if blocks.last() != Some(&id) {
+                blocks.push(id);
+            }
+        }
+        None => {
+            map.insert(key.to_string(), vec![id]);
+        }
+    }
+}
+
+fn tmp_path(index: &Path, suffix: &str) -> PathBuf {
+    let mut name = index.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{suffix}"));
+
+    index.with_file_name(name)
+}
+
+#[inline]
+fn io_err(path: &Path, e: io::Error) -> OmaContentsError {
+    OmaContentsError::FailedToOperateDirOrFile(path.display().to_string(), e)
+}
+
+#[test]
+fn test_build_and_search_index() {
+    let dir = std::env::temp_dir().join(format!("oma-contents-index-{}", std::process::id()));
+    let lists = dir.join("lists");
+    let index = dir.join("contents.idx");
+
+    fs::create_dir_all(&lists).unwrap();
+    fs::write(
+        lists.join("repo_debs_dists_stable_main_Contents-amd64"),
+        "usr/bin/yakuake   Trinity/yakuake-trinity,utils/yakuake\nusr/share/doc/apt/README   admin/apt\n",
+    )
+    .unwrap();
+
+    let summary = build_index(&lists, &index).unwrap();
+    assert_eq!((summary.reused, summary.rebuilt), (0, 1));
+
+    let mut res = vec![];
+    index_search(
+        &lists,
+        &index,
+        Mode::Provides,
+        "/usr/bin/yakuake",
+        QueryKind::Plain,
+        |x| res.push(x),
+    )
+    .unwrap();
+
+    assert_eq!(
+        res,
+        vec![
+            (
+                "yakuake-trinity".to_string(),
+                "/usr/bin/yakuake".to_string()
+            ),
+            ("yakuake".to_string(), "/usr/bin/yakuake".to_string())
+        ]
+    );
+
+    let mut res = vec![];
+    index_search(&lists, &index, Mode::Files, "apt", QueryKind::Plain, |x| {
+        res.push(x)
+    })
+    .unwrap();
+    assert_eq!(
+        res,
+        vec![("apt".to_string(), "/usr/share/doc/apt/README".to_string())]
+    );
+
+    let summary = build_index(&lists, &index).unwrap();
+    assert_eq!((summary.reused, summary.rebuilt), (1, 0));
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_index_candidate_blocks() {
+    let dir = std::env::temp_dir().join(format!("oma-contents-blocks-{}", std::process::id()));
+    let lists = dir.join("lists");
+    let index = dir.join("contents.idx");
+
+    fs::create_dir_all(&lists).unwrap();
+
+    let mut contents = String::new();
+    for i in 0..BLOCK_LINES * 3 {
+        contents.push_str(&format!("usr/share/pkg{i}/file{i}   misc/pkg{i}\n"));
+    }
+    contents.push_str("usr/bin/yakuake   utils/yakuake\n");
+
+    fs::write(
+        lists.join("repo_debs_dists_stable_main_Contents-amd64"),
+        contents,
+    )
+    .unwrap();
+
+    build_index(&lists, &index).unwrap();
+
+    let (sections, data_start) = read_header(&index).unwrap();
+    let section = &sections[0];
+    let mut f = File::open(&index).unwrap();
+    let keys = read_zstd(
+        &mut f,
+        data_start + section.offset + section.len - section.keys_len,
+        section.keys_len,
+    )
+    .map(|x| parse_keys(&x).unwrap())
+    .unwrap();
+
+    assert_eq!(keys.blocks.len(), 4);
+
+    let blocks = |mode, query, kind| {
+        let matcher = Matcher::new(mode, query, kind).unwrap();
+        candidate_blocks(&keys, &matcher, mode, kind).map(|x| x.into_iter().collect::<Vec<_>>())
+    };
+
+    assert_eq!(
+        blocks(Mode::Provides, "/usr/bin/yakuake", QueryKind::Plain),
+        Some(vec![3])
+    );
+    assert_eq!(
+        blocks(Mode::Provides, "file1025", QueryKind::Plain),
+        Some(vec![1])
+    );
+    assert_eq!(
+        blocks(Mode::Files, "pkg2048", QueryKind::Plain),
+        Some(vec![2])
+    );
+    assert_eq!(
+        blocks(Mode::Provides, "/usr/share/pkg1*/*", QueryKind::Glob),
+        Some(vec![0, 1])
+    );
+    assert_eq!(blocks(Mode::Provides, "*", QueryKind::Glob), None);
+
+    let mut res = vec![];
+    index_search(
+        &lists,
+        &index,
+        Mode::Provides,
+        "pkg3000/",
+        QueryKind::Plain,
+        |x| res.push(x),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        vec![(
+            "pkg3000".to_string(),
+            "/usr/share/pkg3000/file3000".to_string()
+        )]
+    );
+
+    fs::remove_dir_all(&dir).ok();
+}
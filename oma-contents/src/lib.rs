@@ -6,6 +6,7 @@
 //!
 //! - `parser`: Functions for parsing lines from contents files, extracting file paths and associated packages.
 //! - `searcher`: Provides functions to search through contents files, supporting various compression formats and search modes.
+//! - `index`: An on-disk index of all contents files, rebuilt incrementally and used by `searcher` when present.
 //!
 //! ## Features
 //!
@@ -17,11 +18,14 @@
 //!   - `FilesSrc`: Search for files provided by a specific source package.
 //!   - `BinProvides`: Search for binary packages that provide a specific file.
 //!   - `BinFiles`: Search for files provided by a specific binary package.
+//! - Queries can be plain strings, globs or regexes (`QueryKind`), with the same semantics in all backends.
 //! - Utilizes parallel processing for efficient searching.
 //! - Supports both ripgrep-based and pure Rust search implementations.
 //!
 
+pub mod index;
 mod parser;
+mod query;
 pub mod searcher;
 
 #[derive(Debug, thiserror::Error)]
@@ -48,4 +52,8 @@ pub enum OmaContentsError {
     NoResult,
     #[error("Illegal file: {0}")]
     IllegalFile(String),
+    #[error("Contents index does not exist or is outdated")]
+    IndexNotAvailable,
+    #[error("Invalid pattern {0}: {1}")]
+    InvalidPattern(String, regex::Error),
 }
@@ -0,0 +1,337 @@
+use regex::Regex;
+
+use crate::{
+    searcher::{prefix, strip_path_prefix, Mode, QueryKind, BIN_PREFIX},
+    OmaContentsError,
+};
+
+/// Compiled query shared by all search backends
+#[derive(Debug)]
+pub(crate) struct Matcher {
+    mode: Mode,
+    query: String,
+    pattern: Option<Regex>,
+}
+
+impl Matcher {
+    pub(crate) fn new(mode: Mode, query: &str, kind: QueryKind) -> Result<Self, OmaContentsError> {
+        let pattern = match kind {
+            QueryKind::Plain => None,
+            QueryKind::Glob => Some(glob_to_regex(query)),
+            QueryKind::Regex => Some(query.to_string()),
+        };
+
+        let pattern = pattern
+            .map(|x| Regex::new(&x).map_err(|e| OmaContentsError::InvalidPattern(query.into(), e)))
+            .transpose()?;
+
+        let query = match kind {
+            QueryKind::Plain => strip_path_prefix(query).to_string(),
+            _ => query.to_string(),
+        };
+
+        Ok(Self {
+            mode,
+            query,
+            pattern,
+        })
+    }
+
+    /// `file` is the path in Contents, without the leading `/`
+    pub(crate) fn is_match(&self, pkg: &str, file: &str) -> bool {
+        let Some(pattern) = &self.pattern else {
+            return self.mode.matcher()(pkg, file, &self.query);
+        };
+
+        // 模式匹配 Provides 的完整路径（以 / 开头）或 Files 的软件包名
+        match self.mode {
+            Mode::Provides | Mode::ProvidesSrc => pattern.is_match(&prefix(file)),
+            Mode::BinProvides => file.starts_with(BIN_PREFIX) && pattern.is_match(&prefix(file)),
+            Mode::Files | Mode::FilesSrc => pattern.is_match(pkg),
+            Mode::BinFiles => file.starts_with(BIN_PREFIX) && pattern.is_match(pkg),
+        }
+    }
+
+    /// The package name matches the query, ignoring the path, for the files modes
+    pub(crate) fn is_pkg_match(&self, pkg: &str) -> bool {
+        match &self.pattern {
+            Some(pattern) => pattern.is_match(pkg),
+            None => pkg == self.query,
+        }
+    }
+
+    /// A literal which every matched Contents line must contain, used to filter lines quickly
+    pub(crate) fn required_literal(&self, kind: QueryKind) -> Option<String> {
+        let literal = match kind {
+            QueryKind::Plain => return Some(self.query.clone()),
+            QueryKind::Glob => glob_literal(&self.query),
+            QueryKind::Regex => regex_literal(&self.query),
+        }?;
+
+        // Contents 中的路径不以 / 开头
+        let literal = literal.strip_prefix('/').unwrap_or(&literal);
+
+        (!literal.is_empty()).then(|| literal.to_string())
+    }
+}
+
+/// Convert a glob to regex
+///
+/// `*` and `?` do not match `/`, `**` matches anything. A glob not starting with `/` may
+/// match the path from any directory, e.g. `libfoo.so.*` matches `/usr/lib/libfoo.so.1`.
+fn glob_to_regex(glob: &str) -> String {
+    let mut res = String::from(if glob.starts_with('/') {
+        "^"
+    } else {
+        "(?:^|/)"
+    });
+
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                res.push_str(".*");
+            }
+            '*' => res.push_str("[^/]*"),
+            '?' => res.push_str("[^/]"),
+            '[' => {
+                let mut class = String::new();
+                let mut closed = false;
+
+                for c in chars.by_ref() {
+                    if c == ']' && !class.is_empty() {
+                        closed = true;
+                        break;
+                    }
+                    class.push(c);
+                }
+
+                if closed {
+                    res.push('[');
+
+                    let class = match class.strip_prefix('!') {
+                        Some(class) => {
+                            res.push('^');
+                            class
+                        }
+                        None => &class,
+                    };
+
+                    res.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                    res.push(']');
+                } else {
+                    res.push_str(&regex::escape(&format!("[{class}")));
+                }
+            }
+            c => res.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    res.push('$');
+
+    res
+}
+
+/// Longest run of literal characters in a glob
+fn glob_literal(glob: &str) -> Option<String> {
+    let mut longest = String::new();
+    let mut run = String::new();
+    let mut chars = glob.chars();
+
+    let mut end_run = |run: &mut String| {
+        if run.len() > longest.len() {
+            longest = run.clone();
+        }
+        run.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '?' => end_run(&mut run),
+            '[' => {
+                // 与 glob_to_regex 一致，没有闭合的 [ 是普通字符
+                let mut class = String::new();
+                let mut closed = false;
+
+                for c in chars.by_ref() {
+                    if c == ']' && !class.is_empty() {
+                        closed = true;
+                        break;
+                    }
+                    class.push(c);
+                }
+
+                if closed {
+                    end_run(&mut run);
+                } else {
+                    run.push('[');
+                    run.push_str(&class);
+                }
+            }
+            c => run.push(c),
+        }
+    }
+
+    end_run(&mut run);
+
+    (!longest.is_empty()).then_some(longest)
+}
+
+/// Longest literal a regex requires, conservative: give up on alternations and flags
+fn regex_literal(regex: &str) -> Option<String> {
+    if regex.contains('|') || regex.contains("(?") {
+        return None;
+    }
+
+    let mut longest = String::new();
+    let mut run = String::new();
+    let mut chars = regex.chars().peekable();
+
+    let mut end_run = |run: &mut String| {
+        if run.len() > longest.len() {
+            longest = run.clone();
+        }
+        run.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c) if c.is_ascii_punctuation() => run.push(c),
+                Some(c) => {
+                    end_run(&mut run);
+
+                    // 跳过整个转义序列，例如 \x41、\u{e9}、\p{Greek} 与 \pL
+                    let len = match c {
+                        'x' => 2,
+                        'u' => 4,
+                        'U' => 8,
+                        'p' | 'P' => 1,
+                        _ => 0,
+                    };
+
+                    if len > 0 && chars.peek() == Some(&'{') {
+                        for c in chars.by_ref() {
+                            if c == '}' {
+                                break;
+                            }
+                        }
+                    } else {
+                        for _ in 0..len {
+                            chars.next();
+                        }
+                    }
+                }
+                None => end_run(&mut run),
+            },
+            // 前一个字符可以不出现
+            '*' | '?' | '{' => {
+                run.pop();
+                end_run(&mut run);
+
+                if c == '{' {
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            break;
+                        }
+                    }
+                }
+            }
+            '(' => {
+                // 组可能是可选的，跳过组内的内容
+                end_run(&mut run);
+                let mut depth = 1;
+
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '(' => depth += 1,
+                        ')' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            '[' => {
+                end_run(&mut run);
+
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        ']' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '.' | '^' | '$' | '+' | ')' | ']' | '}' => end_run(&mut run),
+            c => run.push(c),
+        }
+    }
+
+    end_run(&mut run);
+
+    (!longest.is_empty()).then_some(longest)
+}
+
+#[test]
+fn test_glob_to_regex() {
+    assert_eq!(glob_to_regex("/usr/bin/*"), "^/usr/bin/[^/]*$");
+    assert_eq!(glob_to_regex("libfoo.so.?"), "(?:^|/)libfoo\\.so\\.[^/]$");
+    assert_eq!(glob_to_regex("/usr/**/[!a]b"), "^/usr/.*/[^a]b$");
+}
+
+#[test]
+fn test_pattern_match() {
+    let m = Matcher::new(Mode::Provides, "libfoo.so.*", QueryKind::Glob).unwrap();
+    assert!(m.is_match("libfoo", "usr/lib/libfoo.so.1"));
+    assert!(!m.is_match("libfoo", "usr/lib/libfoo.so.1/bar"));
+    assert!(!m.is_match("libfoo", "usr/lib/libbarfoo.so.1"));
+
+    let m = Matcher::new(Mode::BinProvides, "^/usr/bin/ya.*e$", QueryKind::Regex).unwrap();
+    assert!(m.is_match("yakuake", "usr/bin/yakuake"));
+    assert!(!m.is_match("yakuake", "usr/share/yakuake"));
+
+    let m = Matcher::new(Mode::Files, "apt-*", QueryKind::Glob).unwrap();
+    assert!(m.is_match("apt-utils", "usr/bin/apt-ftparchive"));
+    assert!(!m.is_match("apt", "usr/bin/apt"));
+
+    assert!(Matcher::new(Mode::Files, "(", QueryKind::Regex).is_err());
+}
+
+#[test]
+fn test_required_literal() {
+    let m = Matcher::new(Mode::Provides, "/usr/bin/*", QueryKind::Glob).unwrap();
+    assert_eq!(
+        m.required_literal(QueryKind::Glob).as_deref(),
+        Some("usr/bin/")
+    );
+
+    assert_eq!(
+        regex_literal("^libfoo\\.so(\\.1)?$").as_deref(),
+        Some("libfoo.so")
+    );
+    assert_eq!(regex_literal("yakuakes?").as_deref(), Some("yakuake"));
+    assert_eq!(regex_literal("foo|bar"), None);
+
+    assert_eq!(glob_literal("lib[abcdef]x.so").as_deref(), Some("x.so"));
+    assert_eq!(glob_literal("[]abc]de").as_deref(), Some("de"));
+    assert_eq!(glob_literal("foo[bar").as_deref(), Some("foo[bar"));
+    assert_eq!(glob_literal("*"), None);
+
+    assert_eq!(regex_literal("ab\\x41cde").as_deref(), Some("cde"));
+    assert_eq!(regex_literal("ab\\x{41}cde").as_deref(), Some("cde"));
+    assert_eq!(regex_literal("\\u00e9tude").as_deref(), Some("tude"));
+    assert_eq!(regex_literal("\\p{Greek}foo").as_deref(), Some("foo"));
+    assert_eq!(regex_literal("a\\pLfoo").as_deref(), Some("foo"));
+    assert_eq!(regex_literal("\\d+foo\\.so").as_deref(), Some("foo.so"));
+}
@@ -17,7 +17,7 @@ use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use tracing::debug;
 use zstd::Decoder;
 
-use crate::{parser::single_line, OmaContentsError};
+use crate::{index::index_search, parser::single_line, query::Matcher, OmaContentsError};
 
 const ZSTD_MAGIC: &[u8] = &[40, 181, 47, 253];
 const LZ4_MAGIC: &[u8] = &[0x04, 0x22, 0x4d, 0x18];
@@ -43,41 +43,121 @@ pub enum Mode {
     BinFiles,
 }
 
-const BIN_PREFIX: &str = "usr/bin";
+/// How the query is matched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueryKind {
+    /// Substring of the path (provides) or exact package name (files)
+    #[default]
+    Plain,
+    /// Glob on the full path (provides) or package name (files)
+    ///
+    /// `*` and `?` do not match `/`, `**` matches anything, a glob not starting with `/`
+    /// may match from any directory.
+    Glob,
+    /// Regex searched in the full path (provides) or package name (files)
+    Regex,
+}
+
+/// Kind of a Contents file in the lists directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ContentsKind {
+    /// `*_Contents-<arch>`
+    Contents,
+    /// `*_Contents-source`
+    Source,
+    /// `*_BinContents-<arch>`
+    Bin,
+}
+
+impl ContentsKind {
+    fn from_filename(name: &str) -> Option<Self> {
+        if name.contains("_Contents-source") {
+            Some(Self::Source)
+        } else if name.contains("_Contents-") {
+            Some(Self::Contents)
+        } else if name.contains("_BinContents-") {
+            Some(Self::Bin)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Contents => "contents",
+            Self::Source => "source",
+            Self::Bin => "bin",
+        }
+    }
+
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "contents" => Some(Self::Contents),
+            "source" => Some(Self::Source),
+            "bin" => Some(Self::Bin),
+            _ => None,
+        }
+    }
+}
+
+/// All Contents files in the lists directory
+pub(crate) fn contents_files(dir: &Path) -> Result<Vec<(ContentsKind, PathBuf)>, OmaContentsError> {
+    let mut paths = vec![];
+
+    for i in fs::read_dir(dir)
+        .map_err(|e| OmaContentsError::FailedToOperateDirOrFile(dir.display().to_string(), e))?
+        .flatten()
+    {
+        if let Some(kind) = i.file_name().to_str().and_then(ContentsKind::from_filename) {
+            paths.push((kind, i.path()));
+        }
+    }
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+pub(crate) const BIN_PREFIX: &str = "usr/bin";
 #[cfg(not(feature = "aosc"))]
 const BIN_PREFIX_WITH_PREFIX: &str = "/usr/bin";
 
 impl Mode {
-    fn paths(&self, dir: &Path) -> Result<Vec<PathBuf>, OmaContentsError> {
-        use std::fs;
-
-        #[cfg(feature = "aosc")]
-        let contains_name = match self {
-            Mode::FilesSrc | Mode::ProvidesSrc => |x: &str| x.contains("_Contents-source"),
-            Mode::Provides | Mode::Files => {
-                |x: &str| x.contains("_Contents-") && !x.contains("_Contents-source")
+    /// Whether the file or package of a Contents entry matches the query
+    pub(crate) fn matcher(&self) -> fn(&str, &str, &str) -> bool {
+        match self {
+            Mode::Provides | Mode::ProvidesSrc => |_pkg: &str, file: &str, query: &str| {
+                memmem::find(file.as_bytes(), query.as_bytes()).is_some()
+            },
+            Mode::Files | Mode::FilesSrc => |pkg: &str, _file: &str, query: &str| pkg == query,
+            Mode::BinProvides => |_pkg: &str, file: &str, query: &str| {
+                memmem::find(file.as_bytes(), query.as_bytes()).is_some()
+                    && file.starts_with(BIN_PREFIX)
+            },
+            Mode::BinFiles => {
+                |pkg: &str, file: &str, query: &str| pkg == query && file.starts_with(BIN_PREFIX)
             }
-            Mode::BinProvides | Mode::BinFiles => |x: &str| x.contains("_BinContents-"),
-        };
+        }
+    }
 
-        #[cfg(not(feature = "aosc"))]
-        let contains_name = match self {
-            Mode::FilesSrc | Mode::ProvidesSrc => |x: &str| x.contains("_Contents-source"),
-            Mode::Provides | Mode::Files | Mode::BinFiles | Mode::BinProvides => {
-                |x: &str| x.contains("_Contents-") && !x.contains("_Contents-source")
-            }
-        };
+    /// Which kind of Contents file to search
+    pub(crate) fn kind(&self) -> ContentsKind {
+        match self {
+            Mode::FilesSrc | Mode::ProvidesSrc => ContentsKind::Source,
+            #[cfg(feature = "aosc")]
+            Mode::BinProvides | Mode::BinFiles => ContentsKind::Bin,
+            _ => ContentsKind::Contents,
+        }
+    }
 
-        let mut paths = vec![];
+    fn paths(&self, dir: &Path) -> Result<Vec<PathBuf>, OmaContentsError> {
+        let kind = self.kind();
 
-        for i in fs::read_dir(dir)
-            .map_err(|e| OmaContentsError::FailedToOperateDirOrFile(dir.display().to_string(), e))?
-            .flatten()
-        {
-            if i.file_name().to_str().is_some_and(contains_name) {
-                paths.push(i.path());
-            }
-        }
+        let paths = contents_files(dir)?
+            .into_iter()
+            .filter(|(k, _)| *k == kind)
+            .map(|(_, path)| path)
+            .collect::<Vec<_>>();
 
         if paths.is_empty() {
             return Err(OmaContentsError::ContentsNotExist);
@@ -87,6 +167,27 @@ impl Mode {
     }
 }
 
+/// Search Contents with the index if it is up to date, otherwise with ripgrep or pure search
+pub fn search(
+    dir: impl AsRef<Path>,
+    index: impl AsRef<Path>,
+    mode: Mode,
+    query: &str,
+    kind: QueryKind,
+    mut cb: impl FnMut((String, String)) + Sync + Send,
+) -> Result<(), OmaContentsError> {
+    match index_search(&dir, index, mode, query, kind, &mut cb) {
+        Err(OmaContentsError::IndexNotAvailable) => {}
+        res => return res,
+    }
+
+    if which::which("rg").is_ok() {
+        ripgrep_search(dir, mode, query, kind, cb)
+    } else {
+        pure_search(dir, mode, query, kind, cb)
+    }
+}
+
 /// Perform a search using ripgrep
 ///
 /// This function performs a search using the `ripgrep` command-line tool based on the specified mode and query.
@@ -97,6 +198,7 @@ impl Mode {
 /// * `dir` - A reference to a `Path` that specifies the directory to search in.
 /// * `mode` - A `Mode` enum value that specifies the type of search operation to perform.
 /// * `query` - A string slice that contains the search query.
+/// * `kind` - A `QueryKind` enum value that specifies how the query is matched.
 /// * `cb` - A mutable callback function that takes a tuple of two strings (the matched line and the matched part) as its argument.
 ///
 /// # Returns
@@ -106,6 +208,23 @@ pub fn ripgrep_search(
     dir: impl AsRef<Path>,
     mode: Mode,
     query: &str,
+    kind: QueryKind,
+    cb: impl FnMut((String, String)),
+) -> Result<(), OmaContentsError> {
+    let matcher = Matcher::new(mode, query, kind)?;
+    let paths = mode.paths(dir.as_ref())?;
+
+    if kind == QueryKind::Plain {
+        ripgrep_plain_search(paths, mode, query, cb)
+    } else {
+        ripgrep_pattern_search(paths, &matcher, kind, cb)
+    }
+}
+
+fn ripgrep_plain_search(
+    paths: Vec<PathBuf>,
+    mode: Mode,
+    query: &str,
     mut cb: impl FnMut((String, String)),
 ) -> Result<(), OmaContentsError> {
     let query = regex::escape(query);
@@ -124,7 +243,7 @@ pub fn ripgrep_search(
     let mut cmd = Command::new("rg")
         .arg("-N")
         .arg("-I")
-        .args(mode.paths(dir.as_ref())?)
+        .args(paths)
         .arg("--search-zip")
         .arg("-e")
         .arg(regex)
@@ -182,7 +301,66 @@ pub fn ripgrep_search(
     Ok(())
 }
 
-fn strip_path_prefix(query: &str) -> &str {
+fn ripgrep_pattern_search(
+    paths: Vec<PathBuf>,
+    matcher: &Matcher,
+    kind: QueryKind,
+    mut cb: impl FnMut((String, String)),
+) -> Result<(), OmaContentsError> {
+    let mut cmd = Command::new("rg");
+    cmd.arg("-N").arg("-I").args(paths).arg("--search-zip");
+
+    // rg 只用来快速过滤出可能匹配的行，由 matcher 保证和其它搜索方式的匹配结果一致
+    match matcher.required_literal(kind) {
+        Some(literal) => cmd.arg("-F").arg("-e").arg(literal),
+        None => cmd.arg("-e").arg(""),
+    };
+
+    let mut cmd = cmd
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(OmaContentsError::ExecuteRgFailed)?;
+
+    let stdout = cmd
+        .stdout
+        .as_mut()
+        .expect("Unexpected error: can not get stdout, maybe you environment is broken?");
+
+    let mut stdout_reader = BufReader::new(stdout);
+
+    let mut has_result = false;
+
+    let mut buffer = String::new();
+
+    while stdout_reader.read_line(&mut buffer).is_ok_and(|x| x > 0) {
+        if let Some((file, pkgs)) = single_line(&buffer) {
+            for pkg in pkgs.into_iter().filter_map(pkg_name) {
+                if matcher.is_match(pkg, file) {
+                    cb((pkg.to_string(), prefix(file)));
+                    has_result = true;
+                }
+            }
+        }
+
+        buffer.clear();
+    }
+
+    if !has_result {
+        return Err(OmaContentsError::NoResult);
+    }
+
+    if !cmd
+        .wait()
+        .map_err(OmaContentsError::FailedToWaitExit)?
+        .success()
+    {
+        return Err(OmaContentsError::RgWithError);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn strip_path_prefix(query: &str) -> &str {
     if Path::new(query).is_absolute() {
         query.strip_prefix('/').unwrap_or(query)
     } else {
@@ -198,6 +376,7 @@ fn strip_path_prefix(query: &str) -> &str {
 /// * `path` - A reference to a `Path` that specifies the directory or file to search in.
 /// * `mode` - A `Mode` enum value that specifies the type of search operation to perform.
 /// * `query` - A string slice that contains the search query.
+/// * `kind` - A `QueryKind` enum value that specifies how the query is matched.
 /// * `cb` - A mutable callback function that takes a tuple of two strings (the matched line and the matched part) as its argument. The callback must implement `Sync` and `Send` traits.
 ///
 /// # Returns
@@ -207,18 +386,18 @@ pub fn pure_search(
     path: impl AsRef<Path>,
     mode: Mode,
     query: &str,
+    kind: QueryKind,
     mut cb: impl FnMut((String, String)) + Sync + Send,
 ) -> Result<(), OmaContentsError> {
+    let matcher = Arc::new(Matcher::new(mode, query, kind)?);
     let paths = mode.paths(path.as_ref())?;
-    let query = strip_path_prefix(query);
-    let query = Arc::from(query);
 
     let (tx, rx) = mpsc::channel();
 
     let worker = thread::spawn(move || {
         paths
             .par_iter()
-            .map(move |path| pure_search_contents_from_path(path, &query, mode, &tx))
+            .map(move |path| pure_search_contents_from_path(path, &matcher, &tx))
             .collect::<Result<(), OmaContentsError>>()
     });
 
@@ -235,10 +414,18 @@ pub fn pure_search(
 
 fn pure_search_contents_from_path(
     path: &Path,
-    query: &str,
-    mode: Mode,
+    matcher: &Matcher,
     tx: &Sender<(String, String)>,
 ) -> Result<(), OmaContentsError> {
+    let reader = BufReader::new(contents_reader(path)?);
+
+    pure_search_foreach_result(matcher, reader, tx);
+
+    Ok(())
+}
+
+/// Open a Contents file, decompressing it by the file extension
+pub(crate) fn contents_reader(path: &Path) -> Result<Box<dyn Read>, OmaContentsError> {
     let mut f = fs::File::open(path)
         .map_err(|e| OmaContentsError::FailedToOperateDirOrFile(path.display().to_string(), e))?;
 
@@ -251,44 +438,26 @@ fn pure_search_contents_from_path(
 
     let ext = path.extension().and_then(|x| x.to_str());
 
-    let contents_reader: &mut dyn Read = match ext {
+    let contents_reader: Box<dyn Read> = match ext {
         Some("zst") => {
             check_file_magic_4bytes(buf, path, ZSTD_MAGIC)?;
             // https://github.com/gyscos/zstd-rs/issues/281
-            &mut Decoder::new(BufReader::new(f)).unwrap()
+            Box::new(Decoder::new(BufReader::new(f)).unwrap())
         }
         Some("lz4") => {
             check_file_magic_4bytes(buf, path, LZ4_MAGIC)?;
-            &mut BufReadDecompressor::new(BufReader::new(f))?
+            Box::new(BufReadDecompressor::new(BufReader::new(f))?)
         }
         Some("gz") => {
             if buf[..2] != *GZIP_MAGIC {
                 return Err(OmaContentsError::IllegalFile(path.display().to_string()));
             }
-            &mut GzDecoder::new(BufReader::new(f))
-        }
-        _ => &mut BufReader::new(f),
-    };
-
-    let reader = BufReader::new(contents_reader);
-
-    let can_next = match mode {
-        Mode::Provides | Mode::ProvidesSrc => |_pkg: &str, file: &str, query: &str| {
-            memmem::find(file.as_bytes(), query.as_bytes()).is_some()
-        },
-        Mode::Files | Mode::FilesSrc => |pkg: &str, _file: &str, query: &str| pkg == query,
-        Mode::BinProvides => |_pkg: &str, file: &str, query: &str| {
-            memmem::find(file.as_bytes(), query.as_bytes()).is_some()
-                && file.starts_with(BIN_PREFIX)
-        },
-        Mode::BinFiles => {
-            |pkg: &str, file: &str, query: &str| pkg == query && file.starts_with(BIN_PREFIX)
+            Box::new(GzDecoder::new(BufReader::new(f)))
         }
+        _ => Box::new(BufReader::new(f)),
     };
 
-    pure_search_foreach_result(can_next, reader, query, tx);
-
-    Ok(())
+    Ok(contents_reader)
 }
 
 #[inline]
@@ -305,9 +474,8 @@ fn check_file_magic_4bytes(
 }
 
 fn pure_search_foreach_result(
-    next: impl Fn(&str, &str, &str) -> bool,
-    mut reader: BufReader<&mut dyn Read>,
-    query: &str,
+    matcher: &Matcher,
+    mut reader: impl BufRead,
     tx: &Sender<(String, String)>,
 ) {
     let mut buffer = String::new();
@@ -320,7 +488,7 @@ fn pure_search_foreach_result(
 
         for pkg in pkgs {
             if let Some(pkg) = pkg_name(pkg) {
-                if next(pkg, file, query) {
+                if matcher.is_match(pkg, file) {
                     let line = (pkg.to_string(), prefix(file));
 
                     tx.send(line).unwrap();
@@ -361,12 +529,12 @@ fn rg_filter_line(line: &str, is_list: bool, query: &str) -> Option<Vec<(String,
     }
 }
 
-fn pkg_name(pkg: &str) -> Option<&str> {
+pub(crate) fn pkg_name(pkg: &str) -> Option<&str> {
     pkg.split('/').last()
 }
 
 #[inline]
-fn prefix(s: &str) -> String {
+pub(crate) fn prefix(s: &str) -> String {
     if s.starts_with('/') {
         s.to_string()
     } else {
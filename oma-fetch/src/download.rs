@@ -1,4 +1,4 @@
-use crate::{CompressFile, DownloadProgressControl, DownloadSource};
+use crate::{limiter::SpeedLimiter, mirror, CompressFile, DownloadProgressControl, DownloadSource};
 use std::{
     fs::Permissions,
     io::{self, ErrorKind, SeekFrom},
@@ -9,11 +9,11 @@ use std::{
 
 use async_compression::futures::bufread::{BzDecoder, GzipDecoder, XzDecoder, ZstdDecoder};
 use bon::Builder;
-use futures::{io::BufReader, AsyncRead, TryStreamExt};
+use futures::{io::BufReader, AsyncRead, StreamExt, TryStreamExt};
 use oma_utils::url_no_escape::url_no_escape;
 use reqwest::{
     header::{HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, RANGE},
-    Client, Method, RequestBuilder,
+    Client, Method, RequestBuilder, StatusCode,
 };
 use tokio::{
     fs::{self, File},
@@ -35,8 +35,14 @@ pub(crate) struct SingleDownloader<'a> {
     download_list_index: usize,
     file_type: CompressFile,
     set_permission: Option<u32>,
+    speed_limiter: Option<&'a SpeedLimiter>,
+    mirrors: &'a [String],
+    max_segments: usize,
 }
 
+// 分段下载时每段的最小大小
+const MIN_SEGMENT_SIZE: u64 = 8 * 1024 * 1024;
+
 impl SingleDownloader<'_> {
     pub(crate) async fn try_download(
         self,
@@ -44,15 +50,40 @@ impl SingleDownloader<'_> {
         progress_manager: &dyn DownloadProgressControl,
     ) -> DownloadResult<Summary> {
         let mut sources = self.entry.source.clone();
-        sources.sort_unstable_by(|a, b| b.source_type.cmp(&a.source_type));
+        mirror::add_mirror_sources(&mut sources, self.mirrors);
+        // 使用稳定排序，保持同类下载源原本的优先级
+        sources.sort_by(|a, b| b.source_type.cmp(&a.source_type));
+        // 本次运行中已经失败过的镜像源放到最后尝试
+        sources.sort_by_key(|x| mirror::is_bad(&x.url));
 
         let msg = self.msg.as_deref().unwrap_or(&*self.entry.filename);
 
+        let http_sources = sources
+            .iter()
+            .filter(|x| matches!(x.source_type, DownloadSourceType::Http { .. }))
+            .collect::<Vec<_>>();
+
         for (i, c) in sources.iter().enumerate() {
             let download_res = match &c.source_type {
                 DownloadSourceType::Http { auth } => {
-                    self.try_http_download(progress_manager, global_progress, c, auth)
-                        .await
+                    // 分段下载时当前镜像源优先，其余分段使用其它可用的镜像源
+                    let segment_sources = std::iter::once(c)
+                        .chain(
+                            http_sources
+                                .iter()
+                                .copied()
+                                .filter(|x| x.url != c.url && !mirror::is_bad(&x.url)),
+                        )
+                        .collect::<Vec<_>>();
+
+                    self.try_http_download(
+                        progress_manager,
+                        global_progress,
+                        c,
+                        auth,
+                        &segment_sources,
+                    )
+                    .await
                 }
                 DownloadSourceType::Local(as_symlink) => {
                     self.download_local(progress_manager, global_progress, c, *as_symlink)
@@ -60,12 +91,20 @@ impl SingleDownloader<'_> {
                 }
             };
 
+            let is_http = matches!(c.source_type, DownloadSourceType::Http { .. });
+
             match download_res {
                 Ok(download_res) => {
+                    if is_http {
+                        mirror::mark_good(&c.url);
+                    }
                     progress_manager.download_done(self.download_list_index, msg);
                     return Ok(download_res);
                 }
                 Err(e) => {
+                    if is_http && mirror::is_mirror_failure(&e) {
+                        mirror::mark_bad(&c.url);
+                    }
                     if i == sources.len() - 1 {
                         return Err(e);
                     }
@@ -86,6 +125,7 @@ impl SingleDownloader<'_> {
         global_progress: &AtomicU64,
         source: &DownloadSource,
         auth: &Option<(Box<str>, Box<str>)>,
+        segment_sources: &[&DownloadSource],
     ) -> DownloadResult<Summary> {
         let mut times = 1;
         let mut allow_resume = self.entry.allow_resume;
@@ -97,6 +137,7 @@ impl SingleDownloader<'_> {
                     allow_resume,
                     source,
                     auth,
+                    segment_sources,
                 )
                 .await
             {
@@ -135,7 +176,9 @@ impl SingleDownloader<'_> {
         allow_resume: bool,
         source: &DownloadSource,
         auth: &Option<(Box<str>, Box<str>)>,
+        segment_sources: &[&DownloadSource],
     ) -> DownloadResult<Summary> {
+        let mut allow_resume = allow_resume;
         let file = self.entry.dir.join(&*self.entry.filename);
         let file_exist = file.exists();
         let mut file_size = file.metadata().ok().map(|x| x.len()).unwrap_or(0);
@@ -265,6 +308,37 @@ impl SingleDownloader<'_> {
 
         debug!("File total size is: {total_size}");
 
+        // 大文件分段从多个镜像源同时下载，解压需要按顺序读取数据，因此只分段下载不需要解压的文件
+        let ranges = segment_ranges(total_size, self.max_segments);
+        if can_resume
+            && dest.is_none()
+            && self.file_type == CompressFile::Nothing
+            && !ranges.is_empty()
+        {
+            progress_manager.progress_done(self.download_list_index);
+
+            match self
+                .segmented_download(
+                    progress_manager,
+                    global_progress,
+                    segment_sources,
+                    &file,
+                    total_size,
+                    ranges,
+                )
+                .await
+            {
+                Ok(s) => return Ok(s),
+                Err(e) => {
+                    // 各分段可能来自不同的镜像源，校验失败时也改为从当前源完整下载
+                    debug!("Segmented download failed: {e}, fallback to single stream.");
+                    allow_resume = false;
+                    file_size = 0;
+                    progress_manager.new_progress_spinner(self.download_list_index, &msg);
+                }
+            }
+        }
+
         let mut req = self.build_request_with_basic_auth(&source.url, Method::GET, auth);
 
         if can_resume && allow_resume {
@@ -376,10 +450,17 @@ impl SingleDownloader<'_> {
         // 下载！
         debug!("Start download!");
 
-        let bytes_stream = source
-            .bytes_stream()
-            .map_err(|e| io::Error::new(ErrorKind::Other, e))
-            .into_async_read();
+        // 限速按照网络上实际传输的（压缩的）数据量计算
+        let speed_limiter = self.speed_limiter;
+        let bytes_stream = Box::pin(source.bytes_stream().then(|chunk| async move {
+            if let (Some(limiter), Ok(chunk)) = (speed_limiter, &chunk) {
+                limiter.consume(chunk.len()).await;
+            }
+
+            chunk
+        }))
+        .map_err(|e| io::Error::new(ErrorKind::Other, e))
+        .into_async_read();
 
         let reader: &mut (dyn AsyncRead + Unpin + Send) = match self.file_type {
             CompressFile::Xz => &mut XzDecoder::new(BufReader::new(bytes_stream)),
@@ -456,6 +537,240 @@ impl SingleDownloader<'_> {
         })
     }
 
+    /// Download the file in ranges concurrently, and verify the whole file at the end
+    async fn segmented_download(
+        &self,
+        progress_manager: &dyn DownloadProgressControl,
+        global_progress: &AtomicU64,
+        sources: &[&DownloadSource],
+        file: &Path,
+        total_size: u64,
+        ranges: Vec<(u64, u64)>,
+    ) -> DownloadResult<Summary> {
+        debug!(
+            "{} will be downloaded in {} segments",
+            self.entry.filename,
+            ranges.len()
+        );
+
+        let f = File::create(file)
+            .await
+            .map_err(|e| DownloadError::IOError(self.entry.filename.to_string(), e))?;
+
+        f.set_len(total_size)
+            .await
+            .map_err(|e| DownloadError::IOError(self.entry.filename.to_string(), e))?;
+
+        self.set_permission(&f).await?;
+        drop(f);
+
+        let msg = self.progress_msg();
+        progress_manager.new_progress_bar(self.download_list_index, &msg, total_size);
+
+        let downloaded = AtomicU64::new(0);
+
+        let tasks = ranges.into_iter().enumerate().map(|(i, range)| {
+            // 每段轮流使用不同的镜像源，失败时换用下一个镜像源
+            let sources = sources
+                .iter()
+                .cycle()
+                .skip(i)
+                .take(sources.len())
+                .copied()
+                .collect::<Vec<_>>();
+            self.download_segment(
+                progress_manager,
+                global_progress,
+                sources,
+                file,
+                range,
+                &downloaded,
+            )
+        });
+
+        if let Err(e) = futures::future::try_join_all(tasks).await {
+            global_progress.fetch_sub(downloaded.load(Ordering::SeqCst), Ordering::SeqCst);
+            progress_manager.global_progress_set(global_progress);
+            progress_manager.progress_done(self.download_list_index);
+            return Err(e);
+        }
+
+        if let Some(hash) = &self.entry.hash {
+            let mut v = hash.get_validator();
+            let mut f = File::open(file)
+                .await
+                .map_err(|e| DownloadError::IOError(self.entry.filename.to_string(), e))?;
+
+            let mut buf = vec![0u8; 64 * 1024];
+
+            loop {
+                let size = f
+                    .read(&mut buf[..])
+                    .await
+                    .map_err(|e| DownloadError::IOError(self.entry.filename.to_string(), e))?;
+
+                if size == 0 {
+                    break;
+                }
+
+                v.update(&buf[..size]);
+            }
+
+            if !v.finish() {
+                debug!("checksum fail: {}", self.entry.filename);
+                global_progress.fetch_sub(total_size, Ordering::SeqCst);
+                progress_manager.global_progress_set(global_progress);
+                progress_manager.progress_done(self.download_list_index);
+                return Err(DownloadError::ChecksumMismatch(
+                    self.entry.filename.to_string(),
+                ));
+            }
+
+            debug!("checksum success: {}", self.entry.filename);
+        }
+
+        progress_manager.progress_done(self.download_list_index);
+
+        Ok(Summary {
+            filename: self.entry.filename.clone(),
+            wrote: true,
+            count: self.download_list_index,
+            context: self.msg.clone(),
+        })
+    }
+
+    async fn download_segment(
+        &self,
+        progress_manager: &dyn DownloadProgressControl,
+        global_progress: &AtomicU64,
+        sources: Vec<&DownloadSource>,
+        file: &Path,
+        range: (u64, u64),
+        downloaded: &AtomicU64,
+    ) -> DownloadResult<()> {
+        let mut last_err = None;
+
+        for source in sources {
+            let mut wrote = 0;
+
+            match self
+                .download_range(
+                    progress_manager,
+                    global_progress,
+                    source,
+                    file,
+                    range,
+                    downloaded,
+                    &mut wrote,
+                )
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    debug!("Download range {range:?} from {} failed: {e}", source.url);
+
+                    if mirror::is_mirror_failure(&e) {
+                        mirror::mark_bad(&source.url);
+                    }
+
+                    // 换用其它镜像源时需要重新下载这一段
+                    downloaded.fetch_sub(wrote, Ordering::SeqCst);
+                    global_progress.fetch_sub(wrote, Ordering::SeqCst);
+                    progress_manager.global_progress_set(global_progress);
+                    progress_manager
+                        .progress_set(self.download_list_index, downloaded.load(Ordering::SeqCst));
+
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or(DownloadError::EmptySources))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn download_range(
+        &self,
+        progress_manager: &dyn DownloadProgressControl,
+        global_progress: &AtomicU64,
+        source: &DownloadSource,
+        file: &Path,
+        (start, end): (u64, u64),
+        downloaded: &AtomicU64,
+        wrote: &mut u64,
+    ) -> DownloadResult<()> {
+        let auth = match &source.source_type {
+            DownloadSourceType::Http { auth } => auth,
+            DownloadSourceType::Local(_) => {
+                return Err(DownloadError::InvalidURL(source.url.to_string()))
+            }
+        };
+
+        let resp = self
+            .build_request_with_basic_auth(&source.url, Method::GET, auth)
+            .header(RANGE, format!("bytes={start}-{end}"))
+            .send()
+            .await
+            .and_then(|x| x.error_for_status())
+            .map_err(DownloadError::ReqwestError)?;
+
+        // 服务器忽略 Range 时会返回整个文件
+        if resp.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(DownloadError::RangeNotSupported(source.url.to_string()));
+        }
+
+        let mut dest = fs::OpenOptions::new()
+            .write(true)
+            .open(file)
+            .await
+            .map_err(|e| DownloadError::IOError(self.entry.filename.to_string(), e))?;
+
+        dest.seek(SeekFrom::Start(start))
+            .await
+            .map_err(|e| DownloadError::IOError(self.entry.filename.to_string(), e))?;
+
+        let len = end - start + 1;
+        let mut stream = resp.bytes_stream();
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(DownloadError::ReqwestError)?;
+
+            if *wrote + chunk.len() as u64 > len {
+                return Err(DownloadError::RangeNotSupported(source.url.to_string()));
+            }
+
+            if let Some(limiter) = self.speed_limiter {
+                limiter.consume(chunk.len()).await;
+            }
+
+            dest.write_all(&chunk)
+                .await
+                .map_err(|e| DownloadError::IOError(self.entry.filename.to_string(), e))?;
+
+            let size = chunk.len() as u64;
+            *wrote += size;
+
+            downloaded.fetch_add(size, Ordering::SeqCst);
+            progress_manager
+                .progress_set(self.download_list_index, downloaded.load(Ordering::SeqCst));
+            global_progress.fetch_add(size, Ordering::SeqCst);
+            progress_manager.global_progress_set(global_progress);
+        }
+
+        if *wrote != len {
+            return Err(DownloadError::IOError(
+                self.entry.filename.to_string(),
+                io::Error::new(ErrorKind::UnexpectedEof, "range is incomplete"),
+            ));
+        }
+
+        dest.shutdown()
+            .await
+            .map_err(|e| DownloadError::IOError(self.entry.filename.to_string(), e))?;
+
+        Ok(())
+    }
+
     async fn set_permission(&self, f: &File) -> Result<(), DownloadError> {
         if let Some(mode) = self.set_permission {
             debug!("Setting {} permission to {:#o}", self.entry.filename, mode);
@@ -617,3 +932,54 @@ impl SingleDownloader<'_> {
         })
     }
 }
+
+/// Split the file into inclusive byte ranges, returns empty if the file is too small
+fn segment_ranges(total_size: u64, max_segments: usize) -> Vec<(u64, u64)> {
+    let count = (total_size / MIN_SEGMENT_SIZE).min(max_segments as u64);
+
+    if count < 2 {
+        return vec![];
+    }
+
+    let len = total_size.div_ceil(count);
+
+    (0..count)
+        .map(|i| (i * len, ((i + 1) * len).min(total_size) - 1))
+        .collect()
+}
+
+#[test]
+fn test_segment_ranges() {
+    const MIB: u64 = 1024 * 1024;
+
+    // 文件太小或只允许一段时不分段
+    assert!(segment_ranges(0, 4).is_empty());
+    assert!(segment_ranges(15 * MIB, 4).is_empty());
+    assert!(segment_ranges(64 * MIB, 1).is_empty());
+    assert!(segment_ranges(64 * MIB, 0).is_empty());
+
+    assert_eq!(
+        segment_ranges(16 * MIB, 4),
+        vec![(0, 8 * MIB - 1), (8 * MIB, 16 * MIB - 1)]
+    );
+
+    // 分段数受 max_segments 限制，最后一段较短
+    let total = 100 * MIB + 1;
+    let ranges = segment_ranges(total, 4);
+    assert_eq!(ranges.len(), 4);
+    assert_eq!(ranges[0].0, 0);
+    assert_eq!(ranges[3].1, total - 1);
+
+    // 各段首尾相接，覆盖整个文件
+    for w in ranges.windows(2) {
+        assert_eq!(w[0].1 + 1, w[1].0);
+    }
+
+    assert_eq!(
+        ranges
+            .iter()
+            .map(|(start, end)| end - start + 1)
+            .sum::<u64>(),
+        total
+    );
+}
@@ -1,15 +1,20 @@
 use std::{cmp::Ordering, path::PathBuf, sync::atomic::AtomicU64};
 
-use bon::{builder, Builder};
+use bon::Builder;
 use checksum::Checksum;
 use download::SingleDownloader;
 use futures::StreamExt;
+use limiter::SpeedLimiter;
 
 use reqwest::Client;
 
 pub mod checksum;
 mod download;
+mod limiter;
+mod mirror;
+mod proxy;
 
+pub use proxy::{ProxyConfig, ProxySetting};
 pub use reqwest;
 
 #[derive(thiserror::Error, Debug)]
@@ -28,6 +33,8 @@ pub enum DownloadError {
     InvalidURL(String),
     #[error("download source list is empty")]
     EmptySources,
+    #[error("Server does not support range requests: {0}")]
+    RangeNotSupported(String),
 }
 
 pub type DownloadResult<T> = std::result::Result<T, DownloadError>;
@@ -162,6 +169,14 @@ pub struct DownloadManager<'a> {
     #[builder(default)]
     total_size: u64,
     set_permission: Option<u32>,
+    /// Download speed limit (bytes/sec) shared by all download tasks
+    speed_limit: Option<u64>,
+    /// Equivalent mirror URLs, a file on one of them can also be downloaded from the others
+    #[builder(default)]
+    mirrors: &'a [String],
+    /// Large files are split into at most this many ranges and downloaded concurrently, 1 to disable
+    #[builder(default = 4)]
+    max_segments: usize,
 }
 
 #[derive(Debug)]
@@ -200,6 +215,11 @@ impl<T: DownloadProgressControl> AsDownloadProgressControl for T {
 impl<'a> DownloadManager<'a> {
     /// Start download
     pub async fn start_download(&self) -> Vec<DownloadResult<Summary>> {
+        let limiter = self
+            .speed_limit
+            .filter(|limit| *limit > 0)
+            .map(SpeedLimiter::new);
+
         let mut tasks = Vec::new();
         let mut list = vec![];
         for (i, c) in self.download_list.iter().enumerate() {
@@ -213,6 +233,9 @@ impl<'a> DownloadManager<'a> {
                 .retry_times(self.retry_times)
                 .file_type(c.file_type)
                 .maybe_set_permission(self.set_permission)
+                .maybe_speed_limiter(limiter.as_ref())
+                .mirrors(self.mirrors)
+                .max_segments(self.max_segments)
                 .build();
 
             list.push(single);
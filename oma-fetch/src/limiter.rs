@@ -0,0 +1,112 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+/// Global download speed limiter (token bucket), shared by all download tasks
+#[derive(Debug)]
+pub(crate) struct SpeedLimiter {
+    limit: u64,
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    available: f64,
+    last: Instant,
+}
+
+impl SpeedLimiter {
+    pub(crate) fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            state: Mutex::new(LimiterState {
+                available: 0.0,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// Consume `size` bytes, wait if exceeds the limit
+    pub(crate) async fn consume(&self, size: usize) {
+        let wait = self.take(Instant::now(), size);
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take `size` bytes from the bucket at `now`, return how long to wait
+    fn take(&self, now: Instant, size: usize) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let limit = self.limit as f64;
+
+        // 最多只积攒一秒的额度，避免空闲一段时间后出现突发流量
+        state.available =
+            (state.available + now.duration_since(state.last).as_secs_f64() * limit).min(limit);
+        state.last = now;
+        state.available -= size as f64;
+
+        if state.available < 0.0 {
+            Duration::from_secs_f64(-state.available / limit)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_refill() {
+        let limiter = SpeedLimiter::new(1000);
+        let start = limiter.state.lock().unwrap().last;
+
+        // 一开始没有额度
+        assert_eq!(limiter.take(start, 500), Duration::from_millis(500));
+
+        // 半秒后补充 500 字节，刚好还清
+        let t = start + Duration::from_millis(500);
+        assert_eq!(limiter.take(t, 0), Duration::ZERO);
+        assert_eq!(limiter.take(t, 250), Duration::from_millis(250));
+
+        let t = t + Duration::from_millis(500);
+        assert_eq!(limiter.take(t, 250), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_burst_cap() {
+        let limiter = SpeedLimiter::new(1000);
+        let start = limiter.state.lock().unwrap().last;
+
+        // 空闲十秒也只积攒一秒的额度
+        let t = start + Duration::from_secs(10);
+        assert_eq!(limiter.take(t, 1000), Duration::ZERO);
+        assert_eq!(limiter.take(t, 1000), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shared_by_tasks() {
+        let limiter = Arc::new(SpeedLimiter::new(1000));
+        let start = Instant::now();
+
+        let tasks = (0..4)
+            .map(|_| {
+                let limiter = limiter.clone();
+                tokio::spawn(async move { limiter.consume(1000).await })
+            })
+            .collect::<Vec<_>>();
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // 四个任务共享 1000 B/s 的限速，总共需要四秒
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs(4), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(4100), "{elapsed:?}");
+    }
+}
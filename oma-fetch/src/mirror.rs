@@ -0,0 +1,216 @@
+use std::{
+    collections::HashSet,
+    sync::{LazyLock, Mutex},
+};
+
+use reqwest::Url;
+use tracing::debug;
+
+use crate::{DownloadError, DownloadSource, DownloadSourceType};
+
+// 本次运行中已经失败的镜像源，之后的文件会优先从其它镜像源下载
+static BAD_MIRRORS: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+fn origin(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()
+        .map(|x| x.origin().ascii_serialization())
+}
+
+pub(crate) fn is_bad(url: &str) -> bool {
+    origin(url).is_some_and(|x| BAD_MIRRORS.lock().unwrap().contains(&x))
+}
+
+pub(crate) fn mark_bad(url: &str) {
+    if let Some(origin) = origin(url) {
+        debug!("Mark {origin} as unavailable in this session");
+        BAD_MIRRORS.lock().unwrap().insert(origin);
+    }
+}
+
+pub(crate) fn mark_good(url: &str) {
+    if let Some(origin) = origin(url) {
+        BAD_MIRRORS.lock().unwrap().remove(&origin);
+    }
+}
+
+/// Whether the mirror itself is unavailable (connection error, timeout or 5xx)
+pub(crate) fn is_mirror_failure(e: &DownloadError) -> bool {
+    match e {
+        DownloadError::ReqwestError(e) => {
+            e.is_connect() || e.is_timeout() || e.status().is_some_and(|x| x.is_server_error())
+        }
+        // 下载过程中连接断开
+        DownloadError::IOError(_, e) => e
+            .get_ref()
+            .is_some_and(|x| x.downcast_ref::<reqwest::Error>().is_some()),
+        _ => false,
+    }
+}
+
+/// Add the same file on the other mirrors as fallback sources
+pub(crate) fn add_mirror_sources(sources: &mut Vec<DownloadSource>, mirrors: &[String]) {
+    let urls = sources
+        .iter()
+        .filter(|x| matches!(x.source_type, DownloadSourceType::Http { .. }))
+        .flat_map(|x| equivalent_urls(&x.url, mirrors))
+        .collect::<Vec<_>>();
+
+    for url in urls {
+        if sources.iter().all(|x| x.url != url) {
+            // 认证信息只属于原来的镜像源，不能发送给其它镜像源
+            sources.push(DownloadSource {
+                url,
+                source_type: DownloadSourceType::Http { auth: None },
+            });
+        }
+    }
+}
+
+fn equivalent_urls(url: &str, mirrors: &[String]) -> Vec<String> {
+    let mirrors = mirrors
+        .iter()
+        .map(|x| x.trim_end_matches('/'))
+        .collect::<Vec<_>>();
+
+    let Some((prefix, path)) = mirrors.iter().find_map(|m| {
+        url.strip_prefix(m)
+            .filter(|path| path.starts_with('/'))
+            .map(|path| (*m, path))
+    }) else {
+        return vec![];
+    };
+
+    // 本地镜像源（file://）不能作为 HTTP 下载源
+    mirrors
+        .iter()
+        .filter(|m| **m != prefix && m.starts_with("http"))
+        .map(|m| format!("{m}{path}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+
+    const MIRRORS: &[&str] = &[
+        "https://repo.aosc.io/anthon/",
+        "https://mirrors.example.com/anthon",
+        "file:///mnt/repo/anthon/",
+    ];
+
+    fn mirrors() -> Vec<String> {
+        MIRRORS.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn test_equivalent_urls() {
+        assert_eq!(
+            equivalent_urls(
+                "https://repo.aosc.io/anthon/debs/pool/stable/main/f/foo_1.0_amd64.deb",
+                &mirrors()
+            ),
+            vec!["https://mirrors.example.com/anthon/debs/pool/stable/main/f/foo_1.0_amd64.deb"]
+        );
+
+        // 只匹配完整的路径
+        assert!(
+            equivalent_urls("https://repo.aosc.io/anthon2/debs/InRelease", &mirrors()).is_empty()
+        );
+        assert!(equivalent_urls(
+            "https://other.example.com/anthon/debs/InRelease",
+            &mirrors()
+        )
+        .is_empty());
+        assert!(equivalent_urls("https://repo.aosc.io/anthon/debs/InRelease", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_add_mirror_sources() {
+        let mut sources = vec![DownloadSource {
+            url: "https://repo.aosc.io/anthon/debs/InRelease".to_string(),
+            source_type: DownloadSourceType::Http {
+                auth: Some(("user".into(), "password".into())),
+            },
+        }];
+
+        add_mirror_sources(&mut sources, &mirrors());
+        add_mirror_sources(&mut sources, &mirrors());
+
+        assert_eq!(sources.len(), 2);
+        assert_eq!(
+            sources[1].url,
+            "https://mirrors.example.com/anthon/debs/InRelease"
+        );
+        assert_eq!(
+            sources[1].source_type,
+            DownloadSourceType::Http { auth: None }
+        );
+    }
+
+    /// 返回指定状态码的 HTTP 服务器
+    fn serve(status: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // 读完请求头再返回响应
+            let mut line = String::new();
+            let mut reader = BufReader::new(&stream);
+            while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                line.clear();
+            }
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+            .ok();
+        });
+
+        format!("http://{addr}/InRelease")
+    }
+
+    async fn request(url: &str) -> DownloadError {
+        let err = reqwest::Client::builder()
+            .no_proxy()
+            .build()
+            .unwrap()
+            .get(url)
+            .send()
+            .await
+            .and_then(|x| x.error_for_status())
+            .unwrap_err();
+
+        DownloadError::ReqwestError(err)
+    }
+
+    #[tokio::test]
+    async fn test_is_mirror_failure() {
+        assert!(is_mirror_failure(
+            &request(&serve("503 Service Unavailable")).await
+        ));
+        assert!(!is_mirror_failure(&request(&serve("404 Not Found")).await));
+
+        // 连接被拒绝
+        let closed = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/InRelease", listener.local_addr().unwrap())
+        };
+        assert!(is_mirror_failure(&request(&closed).await));
+
+        assert!(!is_mirror_failure(&DownloadError::ChecksumMismatch(
+            "InRelease".to_string()
+        )));
+        assert!(!is_mirror_failure(&DownloadError::IOError(
+            "InRelease".to_string(),
+            std::io::Error::other("disk full")
+        )));
+    }
+}
@@ -0,0 +1,237 @@
+use std::{collections::HashMap, env};
+
+use reqwest::{Proxy, Url};
+use tracing::{debug, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxySetting {
+    /// Connect to server directly (`DIRECT`)
+    Direct,
+    /// http(s)://, socks5:// or socks5h:// proxy
+    Proxy(Url),
+}
+
+impl ProxySetting {
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+
+        if s.is_empty() {
+            return None;
+        }
+
+        if s.eq_ignore_ascii_case("DIRECT") {
+            return Some(Self::Direct);
+        }
+
+        match Url::parse(s) {
+            Ok(url) => Some(Self::Proxy(url)),
+            Err(e) => {
+                warn!("Invalid proxy {s}: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// Proxy settings of apt (`Acquire::http::Proxy`, `Acquire::https::Proxy` and per-host `Acquire::http::Proxy::<host>`)
+#[derive(Debug, Clone, Default)]
+pub struct ProxyConfig {
+    default: HashMap<String, ProxySetting>,
+    hosts: HashMap<(String, String), ProxySetting>,
+}
+
+impl ProxyConfig {
+    /// Read proxy settings from apt config tree (key, value)
+    pub fn from_apt_config<K: AsRef<str>, V: AsRef<str>>(config_tree: &[(K, V)]) -> Self {
+        let mut res = Self::default();
+
+        for (k, v) in config_tree {
+            // apt 的配置项不区分大小写
+            let k = k.as_ref().to_ascii_lowercase();
+
+            let Some((scheme, rest)) = k
+                .strip_prefix("acquire::")
+                .and_then(|x| x.split_once("::proxy"))
+            else {
+                continue;
+            };
+
+            if scheme != "http" && scheme != "https" {
+                continue;
+            }
+
+            let Some(setting) = ProxySetting::parse(v.as_ref()) else {
+                continue;
+            };
+
+            debug!("Proxy config: {k} => {setting:?}");
+
+            if rest.is_empty() {
+                res.default.insert(scheme.to_string(), setting);
+            } else if let Some(host) = rest.strip_prefix("::").filter(|x| !x.is_empty()) {
+                res.hosts
+                    .insert((scheme.to_string(), host.to_string()), setting);
+            }
+        }
+
+        res
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.default.is_empty() && self.hosts.is_empty()
+    }
+
+    /// Get the proxy of url, return `None` if should connect directly
+    pub fn proxy_for(&self, url: &Url) -> Option<Url> {
+        let scheme = url.scheme();
+        let host = url.host_str()?.to_ascii_lowercase();
+
+        // 与 apt 一致，https 没有单独设置代理时使用 http 的代理设置
+        let schemes: &[&str] = match scheme {
+            "https" => &["https", "http"],
+            "http" => &["http"],
+            _ => return None,
+        };
+
+        // 针对主机的设置（包括 DIRECT）优先于默认设置
+        let setting = schemes
+            .iter()
+            .find_map(|s| self.hosts.get(&(s.to_string(), host.clone())))
+            .or_else(|| schemes.iter().find_map(|s| self.default.get(*s)));
+
+        match setting {
+            Some(ProxySetting::Direct) => None,
+            Some(ProxySetting::Proxy(proxy)) => Some(proxy.clone()),
+            None => proxy_from_env(scheme, &host, |key| env::var(key).ok()),
+        }
+    }
+
+    /// Convert to reqwest proxy, return `None` if apt has no proxy settings (use system proxy)
+    pub fn into_reqwest_proxy(self) -> Option<Proxy> {
+        if self.is_empty() {
+            return None;
+        }
+
+        Some(Proxy::custom(move |url| self.proxy_for(url)))
+    }
+}
+
+/// `http_proxy`/`https_proxy` environment variables, hosts in `no_proxy` are connected directly
+fn proxy_from_env(scheme: &str, host: &str, var: impl Fn(&str) -> Option<String>) -> Option<Url> {
+    let get = |key: &str| var(key).or_else(|| var(&key.to_ascii_uppercase()));
+
+    if get("no_proxy").is_some_and(|x| is_no_proxy(&x, host)) {
+        return None;
+    }
+
+    get(&format!("{scheme}_proxy")).and_then(|x| Url::parse(&x).ok())
+}
+
+/// The host matches an entry of `no_proxy`, e.g. `localhost,.example.org,10.0.0.1`
+fn is_no_proxy(no_proxy: &str, host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    no_proxy
+        .split(',')
+        .map(|x| x.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|x| !x.is_empty())
+        .any(|x| {
+            x == "*"
+                || host == x
+                || host
+                    .strip_suffix(x.as_str())
+                    .is_some_and(|x| x.ends_with('.'))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_proxy_for() {
+        let config = ProxyConfig::from_apt_config(&[
+            ("Acquire::http::Proxy", "http://proxy:3128"),
+            ("Acquire::http::Proxy::repo.aosc.io", "DIRECT"),
+            (
+                "Acquire::http::Proxy::mirror.example.org",
+                "socks5h://socks:1080",
+            ),
+            ("Acquire::https::Proxy", "http://https-proxy:3128"),
+            ("Acquire::ftp::Proxy", "http://ftp-proxy:3128"),
+        ]);
+
+        assert_eq!(
+            config.proxy_for(&url("http://deb.debian.org/debian")),
+            Some(url("http://proxy:3128"))
+        );
+        assert_eq!(
+            config.proxy_for(&url("https://deb.debian.org/debian")),
+            Some(url("http://https-proxy:3128"))
+        );
+
+        // 针对主机的 DIRECT 优先于 https 的默认代理
+        assert_eq!(config.proxy_for(&url("http://repo.aosc.io/debs")), None);
+        assert_eq!(config.proxy_for(&url("https://REPO.aosc.io/debs")), None);
+
+        assert_eq!(
+            config.proxy_for(&url("https://mirror.example.org/debs")),
+            Some(url("socks5h://socks:1080"))
+        );
+    }
+
+    #[test]
+    fn test_https_fallback_to_http() {
+        let config = ProxyConfig::from_apt_config(&[("acquire::http::proxy", "http://proxy:3128")]);
+
+        assert_eq!(
+            config.proxy_for(&url("https://repo.aosc.io/debs")),
+            Some(url("http://proxy:3128"))
+        );
+        assert_eq!(config.proxy_for(&url("ftp://repo.aosc.io/debs")), None);
+        assert!(ProxyConfig::from_apt_config::<&str, &str>(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_proxy_from_env() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |key: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+
+        let vars = env(&[
+            ("https_proxy", "http://proxy:3128"),
+            ("NO_PROXY", "localhost, .example.org,10.0.0.1"),
+        ]);
+
+        assert_eq!(
+            proxy_from_env("https", "repo.aosc.io", vars),
+            Some(url("http://proxy:3128"))
+        );
+        assert_eq!(proxy_from_env("http", "repo.aosc.io", vars), None);
+        assert_eq!(proxy_from_env("https", "localhost", vars), None);
+        assert_eq!(proxy_from_env("https", "mirror.example.org", vars), None);
+        assert_eq!(proxy_from_env("https", "example.org", vars), None);
+        assert_eq!(proxy_from_env("https", "10.0.0.1", vars), None);
+        assert_eq!(
+            proxy_from_env("https", "badexample.org", vars),
+            Some(url("http://proxy:3128"))
+        );
+
+        let vars = env(&[("HTTP_PROXY", "http://proxy:3128"), ("no_proxy", "*")]);
+        assert_eq!(proxy_from_env("http", "repo.aosc.io", vars), None);
+
+        let vars = env(&[("HTTP_PROXY", "http://proxy:3128")]);
+        assert_eq!(
+            proxy_from_env("http", "repo.aosc.io", vars),
+            Some(url("http://proxy:3128"))
+        );
+    }
+}
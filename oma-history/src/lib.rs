@@ -1,6 +1,7 @@
 use std::{
     fs,
     path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use oma_pm_operation_type::{InstallEntry, OmaOperation, RemoveEntry};
@@ -21,6 +22,7 @@ pub enum SummaryType {
         remove: Vec<String>,
     },
     Undo,
+    AutoUpgrade(Vec<String>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -54,10 +56,42 @@ pub enum HistoryError {
 
 pub const DATABASE_PATH: &str = "var/lib/oma/history.db";
 
+/// 数据库结构迁移，第 n 项将数据库从版本 n 升级到版本 n + 1
+const MIGRATIONS: &[&str] = &[
+    // 旧版本 oma 创建的数据库已经有这个表，但没有版本记录
+    "CREATE TABLE IF NOT EXISTS \"history_oma_1.2\" (
+        id INTEGER PRIMARY KEY,
+        typ BLOB NOT NULL,
+        time INTEGER NOT NULL,
+        is_success INTEGER NOT NULL,
+        install_packages BLOB,
+        remove_packages BLOB,
+        disk_size INTEGER NOT NULL,
+        total_download_size INTEGER
+    );",
+    "CREATE TABLE IF NOT EXISTS \"history_oma_term_log\" (
+        id INTEGER PRIMARY KEY,
+        log TEXT NOT NULL
+    );",
+    "ALTER TABLE \"history_oma_1.2\" ADD COLUMN cmdline TEXT;
+    ALTER TABLE \"history_oma_1.2\" ADD COLUMN user TEXT;
+    ALTER TABLE \"history_oma_1.2\" ADD COLUMN error TEXT;
+    ALTER TABLE \"history_oma_1.2\" ADD COLUMN duration INTEGER;",
+];
+
+/// `MIGRATIONS` 中添加 cmdline、user、error、duration 列的那一项
+const DETAIL_COLUMNS_MIGRATION: usize = 2;
+
+/// 数据库版本至少为此值时才有 cmdline、user、error、duration 列
+const DETAIL_COLUMNS_VERSION: i64 = DETAIL_COLUMNS_MIGRATION as i64 + 1;
+
+/// Current history database schema version
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
 pub fn connect_db<P: AsRef<Path>>(db_path: P, write: bool) -> HistoryResult<Connection> {
     let conn = Connection::open(db_path);
 
-    let conn = match conn {
+    let mut conn = match conn {
         Ok(conn) => conn,
         Err(e) => match e {
             Error::SqliteFailure(err, _) if [1, 14].contains(&err.extended_code) => {
@@ -68,23 +102,58 @@ pub fn connect_db<P: AsRef<Path>>(db_path: P, write: bool) -> HistoryResult<Conn
     };
 
     if write {
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS \"history_oma_1.2\" (
-                id INTEGER PRIMARY KEY,
-                typ BLOB NOT NULL,
-                time INTEGER NOT NULL,
-                is_success INTEGER NOT NULL,
-                install_packages BLOB,
-                remove_packages BLOB,
-                disk_size INTEGER NOT NULL,
-                total_download_size INTEGER
-            )",
-            (), // empty list of parameters.
+        migrate(&mut conn)?;
+    }
+
+    Ok(conn)
+}
+
+/// Get schema version of history database, `0` means created by old oma without version table
+pub fn schema_version(conn: &Connection) -> HistoryResult<i64> {
+    match conn.query_row("SELECT version FROM \"schema_version\"", [], |row| {
+        row.get(0)
+    }) {
+        Ok(version) => Ok(version),
+        Err(Error::QueryReturnedNoRows) => Ok(0),
+        Err(Error::SqliteFailure(err, _)) if err.extended_code == 1 => Ok(0),
+        Err(e) => Err(HistoryError::ExecuteError(e)),
+    }
+}
+
+/// Upgrade history database to [`SCHEMA_VERSION`]
+fn migrate(conn: &mut Connection) -> HistoryResult<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS \"schema_version\" (version INTEGER NOT NULL)",
+        (),
+    )
+    .map_err(HistoryError::ExecuteError)?;
+
+    let version = schema_version(conn)?;
+
+    if version > SCHEMA_VERSION {
+        // 新版本 oma 创建的数据库，只会增加列，仍然可以写入
+        debug!("History database schema version {version} is newer than {SCHEMA_VERSION}");
+        return Ok(());
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let new_version = i as i64 + 1;
+        debug!("Migrate history database to schema version {new_version}");
+
+        let tx = conn.transaction().map_err(HistoryError::ExecuteError)?;
+        tx.execute_batch(migration)
+            .map_err(HistoryError::ExecuteError)?;
+        tx.execute("DELETE FROM \"schema_version\"", ())
+            .map_err(HistoryError::ExecuteError)?;
+        tx.execute(
+            "INSERT INTO \"schema_version\" (version) VALUES (?1)",
+            [new_version],
         )
         .map_err(HistoryError::ExecuteError)?;
+        tx.commit().map_err(HistoryError::ExecuteError)?;
     }
 
-    Ok(conn)
+    Ok(())
 }
 
 pub fn create_db_file<P: AsRef<Path>>(sysroot: P) -> HistoryResult<PathBuf> {
@@ -106,24 +175,60 @@ pub fn create_db_file<P: AsRef<Path>>(sysroot: P) -> HistoryResult<PathBuf> {
     Ok(db_path)
 }
 
+/// 执行 oma 的用户：sudo 的调用者、pkexec 的调用者，否则为当前用户
+fn invoking_user() -> String {
+    if let (Ok(user), Ok(uid)) = (std::env::var("SUDO_USER"), std::env::var("SUDO_UID")) {
+        return format!("{user} ({uid})");
+    }
+
+    let uid = std::env::var("PKEXEC_UID")
+        .ok()
+        .and_then(|x| x.parse::<u32>().ok())
+        .unwrap_or_else(|| unsafe { libc::getuid() });
+
+    match user_name(uid) {
+        Some(user) => format!("{user} ({uid})"),
+        None => uid.to_string(),
+    }
+}
+
+fn user_name(uid: u32) -> Option<String> {
+    let passwd = fs::read_to_string("/etc/passwd").ok()?;
+
+    passwd.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+
+        (fields.nth(1)?.parse::<u32>().ok()? == uid).then(|| name.to_string())
+    })
+}
+
 pub fn write_history_entry(
     summary: OmaOperation,
     typ: SummaryType,
     conn: Connection,
     dry_run: bool,
     start_time: i64,
-    success: bool,
+    error: Option<String>,
+    term_log: Option<String>,
 ) -> HistoryResult<()> {
     if dry_run {
         debug!("In dry-run mode, oma will not write history entries");
         return Ok(());
     }
 
+    let cmdline = std::env::args().collect::<Vec<_>>().join(" ");
+    let user = Some(invoking_user());
+    let duration = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs() as i64 - start_time)
+        .ok();
+
     conn.execute(
-        "INSERT INTO \"history_oma_1.2\" (typ, time, is_success, install_packages, remove_packages, disk_size, total_download_size) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        "INSERT INTO \"history_oma_1.2\" (typ, time, is_success, install_packages, remove_packages, disk_size, total_download_size, cmdline, user, error, duration) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
         (serde_json::to_string(&typ).map_err(HistoryError::ParseError)?,
         start_time,
-        if success { 1 } else { 0 },
+        if error.is_none() { 1 } else { 0 },
         serde_json::to_string(&summary.install).map_err(HistoryError::ParseError)?,
         serde_json::to_string(&summary.remove).map_err(HistoryError::ParseError)?,
         match (summary.disk_size.0.as_ref(), summary.disk_size.1) {
@@ -131,10 +236,24 @@ pub fn write_history_entry(
             ("-", x) => 0 - x as i64,
             _ => unreachable!()
         },
-        summary.total_download_size),
+        summary.total_download_size,
+        cmdline,
+        user,
+        error,
+        duration),
     )
     .map_err(HistoryError::ExecuteError)?;
 
+    if let Some(log) = term_log {
+        // 与本次操作的历史记录使用同一个 id
+        let id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO \"history_oma_term_log\" (id, log) VALUES (?1, ?2)",
+            (id, log),
+        )
+        .map_err(HistoryError::ExecuteError)?;
+    }
+
     Ok(())
 }
 
@@ -188,6 +307,40 @@ pub fn list_history(conn: &Connection) -> HistoryResult<Vec<HistoryListEntry>> {
     Ok(res)
 }
 
+/// Details of history entry, `None` if the entry was written by old oma
+#[derive(Debug, Default)]
+pub struct HistoryDetail {
+    pub cmdline: Option<String>,
+    pub user: Option<String>,
+    pub error: Option<String>,
+    /// in seconds
+    pub duration: Option<i64>,
+}
+
+pub fn find_history_detail_by_id(conn: &Connection, id: i64) -> HistoryResult<HistoryDetail> {
+    // 没有权限写入时不会迁移数据库，旧数据库没有这些列
+    if schema_version(conn)? < DETAIL_COLUMNS_VERSION {
+        return Ok(HistoryDetail::default());
+    }
+
+    conn.query_row(
+        "SELECT cmdline, user, error, duration FROM \"history_oma_1.2\" WHERE id = (?1)",
+        [id],
+        |row| {
+            Ok(HistoryDetail {
+                cmdline: row.get(0)?,
+                user: row.get(1)?,
+                error: row.get(2)?,
+                duration: row.get(3)?,
+            })
+        },
+    )
+    .map_err(|e| match e {
+        Error::QueryReturnedNoRows => HistoryError::NoResult(id),
+        e => HistoryError::ExecuteError(e),
+    })
+}
+
 pub fn find_history_by_id(conn: &Connection, id: i64) -> HistoryResult<OmaOperation> {
     let mut stmt = conn
         .prepare("SELECT install_packages, remove_packages, disk_size, total_download_size FROM \"history_oma_1.2\" WHERE id = (?1)")
@@ -237,3 +390,123 @@ pub fn find_history_by_id(conn: &Connection, id: i64) -> HistoryResult<OmaOperat
 
     res.ok_or_else(|| HistoryError::NoResult(id))
 }
+
+/// Get the dpkg terminal log of history entry, return `None` if it was not recorded
+pub fn find_term_log_by_id(conn: &Connection, id: i64) -> HistoryResult<Option<String>> {
+    let stmt = conn.prepare("SELECT log FROM \"history_oma_term_log\" WHERE id = (?1)");
+
+    let mut stmt = match stmt {
+        Ok(stmt) => stmt,
+        // 旧版本 oma 创建的数据库没有这个表
+        Err(Error::SqliteFailure(err, _)) if err.extended_code == 1 => return Ok(None),
+        Err(e) => return Err(HistoryError::ExecuteError(e)),
+    };
+
+    let mut res_iter = stmt
+        .query_map([id], |row| row.get::<_, String>(0))
+        .map_err(HistoryError::ExecuteError)?;
+
+    res_iter
+        .next()
+        .transpose()
+        .map_err(HistoryError::ParseDbError)
+}
+
+#[test]
+fn test_migrate_old_database() {
+    let mut conn = Connection::open_in_memory().unwrap();
+
+    // oma 1.2 创建的数据库
+    conn.execute_batch(MIGRATIONS[0]).unwrap();
+    assert_eq!(schema_version(&conn).unwrap(), 0);
+
+    migrate(&mut conn).unwrap();
+    assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+
+    // 再次连接时不应重复迁移
+    migrate(&mut conn).unwrap();
+    assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+
+    conn.execute(
+        "INSERT INTO \"history_oma_1.2\" (typ, time, is_success, disk_size, cmdline, duration) VALUES ('\"FixBroken\"', 0, 1, 0, 'oma fix-broken', 3)",
+        (),
+    )
+    .unwrap();
+
+    let detail = find_history_detail_by_id(&conn, conn.last_insert_rowid()).unwrap();
+    assert_eq!(detail.cmdline.as_deref(), Some("oma fix-broken"));
+    assert_eq!(detail.duration, Some(3));
+    assert_eq!(detail.user, None);
+}
+
+#[test]
+fn test_detail_columns_migration() {
+    let db_path = std::env::temp_dir().join(format!("oma-history-test-{}.db", std::process::id()));
+    let _ = fs::remove_file(&db_path);
+
+    // 还没有 cmdline、user、error、duration 列的数据库
+    let conn = Connection::open(&db_path).unwrap();
+    for migration in &MIGRATIONS[..DETAIL_COLUMNS_MIGRATION] {
+        conn.execute_batch(migration).unwrap();
+    }
+    conn.execute_batch(&format!(
+        "CREATE TABLE \"schema_version\" (version INTEGER NOT NULL);
+        INSERT INTO \"schema_version\" (version) VALUES ({DETAIL_COLUMNS_MIGRATION});
+        INSERT INTO \"history_oma_1.2\" (typ, time, is_success, install_packages, remove_packages, disk_size, total_download_size) VALUES ('\"FixBroken\"', 0, 1, '[]', '[]', 0, 0);"
+    ))
+    .unwrap();
+    let old_id = conn.last_insert_rowid();
+    drop(conn);
+
+    // 只读打开时不迁移，也不能查询新列
+    let conn = connect_db(&db_path, false).unwrap();
+    assert_eq!(
+        schema_version(&conn).unwrap(),
+        DETAIL_COLUMNS_MIGRATION as i64
+    );
+    assert!(find_history_detail_by_id(&conn, old_id)
+        .unwrap()
+        .cmdline
+        .is_none());
+    drop(conn);
+
+    let conn = connect_db(&db_path, true).unwrap();
+    assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+
+    let detail = find_history_detail_by_id(&conn, old_id).unwrap();
+    assert!(detail.cmdline.is_none() && detail.user.is_none() && detail.error.is_none());
+    assert!(find_history_by_id(&conn, old_id).is_ok());
+
+    let op = OmaOperation {
+        install: vec![],
+        remove: vec![],
+        disk_size: ("+".into(), 0),
+        autoremovable: (0, 0),
+        total_download_size: 0,
+    };
+
+    write_history_entry(
+        op,
+        SummaryType::FixBroken,
+        conn,
+        false,
+        0,
+        Some("dpkg returned an error code".to_string()),
+        None,
+    )
+    .unwrap();
+
+    let conn = connect_db(&db_path, false).unwrap();
+    let list = list_history(&conn).unwrap();
+    assert_eq!(list.len(), 2);
+    assert!(!list[0].is_success);
+
+    let detail = find_history_detail_by_id(&conn, list[0].id).unwrap();
+    assert!(detail.cmdline.is_some());
+    assert!(detail.user.is_some());
+    assert!(detail.duration.is_some());
+    assert_eq!(detail.error.as_deref(), Some("dpkg returned an error code"));
+
+    drop(conn);
+    fs::remove_file(&db_path).unwrap();
+}
@@ -7,9 +7,12 @@ use std::{
 use ahash::HashMap;
 use indexmap::{indexmap, IndexMap};
 use once_cell::sync::OnceCell;
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
-use tracing::debug;
+use tracing::{debug, warn};
+
+pub mod speedtest;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Status {
@@ -62,6 +65,12 @@ pub enum MirrorError {
     WriteFile { path: PathBuf, source: io::Error },
     #[snafu(display("Failed to create status file: {}", path.display()))]
     CreateFile { path: PathBuf, source: io::Error },
+    #[snafu(display("Invalid mirror {mirror_name} in {}: {reason}", path.display()))]
+    InvalidMirror {
+        path: PathBuf,
+        mirror_name: Box<str>,
+        reason: Box<str>,
+    },
 }
 
 pub struct MirrorManager {
@@ -71,6 +80,7 @@ pub struct MirrorManager {
     mirrors_data: OnceCell<HashMap<Box<str>, Mirror>>,
     status_file_path: PathBuf,
     mirrors_file_path: PathBuf,
+    custom_mirrors_dir: PathBuf,
     apt_status_file: PathBuf,
 }
 
@@ -78,6 +88,7 @@ impl MirrorManager {
     pub fn new(rootfs: PathBuf) -> Result<Self, MirrorError> {
         let status_file_path = rootfs.join("var/lib/apt/gen/status.json");
         let mirrors_file_path = rootfs.join("usr/share/distro-repository-data/mirrors.yml");
+        let custom_mirrors_dir = rootfs.join("etc/oma/mirrors.d");
         let apt_status_file = rootfs.join("etc/apt/sources.list");
 
         let status: Status = if status_file_path.is_file() {
@@ -102,6 +113,7 @@ impl MirrorManager {
             mirrors_data: OnceCell::new(),
             status_file_path,
             mirrors_file_path,
+            custom_mirrors_dir,
             apt_status_file,
         })
     }
@@ -150,14 +162,29 @@ impl MirrorManager {
     fn try_mirrors(&self) -> Result<&HashMap<Box<str>, Mirror>, MirrorError> {
         self.mirrors_data
             .get_or_try_init(|| -> Result<HashMap<Box<str>, Mirror>, MirrorError> {
-                let f = fs::read(&self.mirrors_file_path).context(ReadFileSnafu {
-                    path: self.mirrors_file_path.to_path_buf(),
-                })?;
-
-                let mirrors: HashMap<Box<str>, Mirror> =
-                    serde_yaml::from_slice(&f).context(ParseYamlSnafu {
-                        path: self.mirrors_file_path.to_path_buf(),
-                    })?;
+                let mut mirrors = read_mirrors_file(&self.mirrors_file_path)?;
+
+                // 自定义镜像源可以覆盖发行版提供的同名镜像源
+                // 有问题的自定义镜像源只会被跳过，以免影响发行版提供的镜像源
+                for path in custom_mirror_files(&self.custom_mirrors_dir) {
+                    let custom = match read_mirrors_file(&path) {
+                        Ok(custom) => custom,
+                        Err(e) => {
+                            warn!("{e}");
+                            continue;
+                        }
+                    };
+
+                    for (name, mirror) in custom {
+                        if let Err(e) = validate_mirror(&path, &name, &mirror) {
+                            warn!("{e}");
+                            continue;
+                        }
+
+                        debug!("Load custom mirror {name} from {}", path.display());
+                        mirrors.insert(name, mirror);
+                    }
+                }
 
                 Ok(mirrors)
             })
@@ -272,6 +299,71 @@ impl MirrorManager {
     }
 }
 
+fn read_mirrors_file(path: &Path) -> Result<HashMap<Box<str>, Mirror>, MirrorError> {
+    let f = fs::read(path).context(ReadFileSnafu {
+        path: path.to_path_buf(),
+    })?;
+
+    let mirrors = serde_yaml::from_slice(&f).context(ParseYamlSnafu {
+        path: path.to_path_buf(),
+    })?;
+
+    Ok(mirrors)
+}
+
+/// `*.yml` and `*.yaml` files in the custom mirrors directory, sorted by file name
+fn custom_mirror_files(dir: &Path) -> Vec<PathBuf> {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) => {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("Failed to read {}: {e}", dir.display());
+            }
+            return vec![];
+        }
+    };
+
+    let mut files = read_dir
+        .filter_map(|x| x.ok())
+        .map(|x| x.path())
+        .filter(|x| {
+            x.is_file()
+                && x.extension()
+                    .is_some_and(|ext| ext == "yml" || ext == "yaml")
+        })
+        .collect::<Vec<_>>();
+
+    files.sort();
+
+    files
+}
+
+fn validate_mirror(path: &Path, name: &str, mirror: &Mirror) -> Result<(), MirrorError> {
+    let err = |reason: &str| MirrorError::InvalidMirror {
+        path: path.to_path_buf(),
+        mirror_name: name.into(),
+        reason: reason.into(),
+    };
+
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(err("mirror name must not be empty or contain spaces"));
+    }
+
+    let url = Url::parse(&mirror.url).map_err(|e| err(&e.to_string()))?;
+
+    match url.scheme() {
+        "http" | "https" => {
+            if url.host_str().is_none() {
+                return Err(err("url has no host"));
+            }
+        }
+        "file" => {}
+        x => return Err(err(&format!("unsupported url scheme: {x}"))),
+    }
+
+    Ok(())
+}
+
 fn create_default_status(path: &Path) -> Result<Status, MirrorError> {
     debug!("Creating status file ... ");
     fs::create_dir_all(path.parent().unwrap()).context(CreateFileSnafu {
@@ -287,3 +379,49 @@ fn create_default_status(path: &Path) -> Result<Status, MirrorError> {
 
     Ok(status)
 }
+
+#[test]
+fn test_skip_bad_custom_mirrors() {
+    let rootfs = std::env::temp_dir().join(format!("oma-mirror-test-{}", std::process::id()));
+    let mirrors_dir = rootfs.join("etc/oma/mirrors.d");
+    let mirrors_file = rootfs.join("usr/share/distro-repository-data/mirrors.yml");
+
+    fs::create_dir_all(&mirrors_dir).unwrap();
+    fs::create_dir_all(mirrors_file.parent().unwrap()).unwrap();
+
+    fs::write(
+        &mirrors_file,
+        "origin:\n  desc: Origin\n  url: https://repo.aosc.io/\n",
+    )
+    .unwrap();
+    fs::write(mirrors_dir.join("broken.yml"), "lab: [").unwrap();
+    fs::write(
+        mirrors_dir.join("lab.yml"),
+        "lab:\n  desc: Lab\n  url: https://mirror.lab/aosc/\nbad:\n  desc: Bad\n  url: ftp://mirror.lab/\n",
+    )
+    .unwrap();
+
+    let mm = MirrorManager::new(rootfs.clone()).unwrap();
+    let mut names = mm.mirrors_iter().unwrap().map(|x| x.0).collect::<Vec<_>>();
+    names.sort_unstable();
+
+    assert_eq!(names, vec!["lab", "origin"]);
+
+    fs::remove_dir_all(rootfs).unwrap();
+}
+
+#[test]
+fn test_validate_mirror() {
+    let mirror = |url: &str| Mirror {
+        desc: Box::from("Lab"),
+        url: Box::from(url),
+    };
+
+    let path = Path::new("/etc/oma/mirrors.d/lab.yml");
+
+    assert!(validate_mirror(path, "lab", &mirror("https://mirror.lab/aosc/")).is_ok());
+    assert!(validate_mirror(path, "lab", &mirror("file:///srv/aosc/")).is_ok());
+    assert!(validate_mirror(path, "lab", &mirror("ftp://mirror.lab/aosc/")).is_err());
+    assert!(validate_mirror(path, "lab", &mirror("mirror.lab/aosc")).is_err());
+    assert!(validate_mirror(path, "lab mirror", &mirror("https://mirror.lab/")).is_err());
+}
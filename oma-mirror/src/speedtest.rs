@@ -0,0 +1,278 @@
+use std::time::Duration;
+
+use faster_hex::hex_string;
+use futures::{stream, StreamExt};
+use reqwest::{Client, Url};
+use sha2::{Digest, Sha256};
+use snafu::{OptionExt, ResultExt, Snafu};
+use tokio::{
+    net::{lookup_host, TcpStream},
+    time::{timeout_at, Instant},
+};
+use tracing::debug;
+
+use crate::Mirror;
+
+const TEST_FILE: &str = ".repotest";
+const TEST_FILE_SHA256: &str = "1e2a82e7babb443b2b26b61ce5dd2bd25b06b30422b42ee709fddd2cc3ffe231";
+
+#[derive(Debug, Snafu)]
+pub enum SpeedtestError {
+    #[snafu(display("{source}"))]
+    Request { source: reqwest::Error },
+    #[snafu(display("Timed out before the mirror responded"))]
+    Timeout,
+    #[snafu(display("Checksum verification failed"))]
+    ChecksumMismatch,
+    #[snafu(display("Invalid mirror URL {url}"))]
+    InvalidUrl { url: String },
+    #[snafu(display("Failed to connect to {host}"))]
+    Connect {
+        host: String,
+        source: std::io::Error,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct SpeedtestConfig {
+    /// Per-mirror timeout, the mirror is scored by the partial file downloaded when it is reached
+    pub timeout: Duration,
+    /// How many mirrors to test at the same time
+    pub concurrency: usize,
+}
+
+impl Default for SpeedtestConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(15),
+            concurrency: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MirrorScore {
+    /// TCP handshake time on a fresh connection, excluding DNS lookup
+    pub latency: Duration,
+    /// Bytes of the test file downloaded
+    pub downloaded: u64,
+    /// Time spent on downloading the body
+    pub download_time: Duration,
+    /// The whole test file was downloaded and verified
+    pub complete: bool,
+}
+
+impl MirrorScore {
+    /// Throughput in bytes per second
+    pub fn throughput(&self) -> u64 {
+        let secs = self.download_time.as_secs_f64();
+
+        if secs == 0.0 {
+            return 0;
+        }
+
+        (self.downloaded as f64 / secs) as u64
+    }
+}
+
+/// Test mirrors concurrently, `callback` is called when each mirror is done
+///
+/// Local (`file://`) mirrors are skipped, as their throughput is the disk speed.
+/// Returns the results sorted from the fastest to the slowest, failed mirrors at the end.
+pub async fn speedtest<'a>(
+    client: &Client,
+    mirrors: impl IntoIterator<Item = (&'a str, &'a Mirror)>,
+    config: &SpeedtestConfig,
+    callback: impl Fn(&str, &Result<MirrorScore, SpeedtestError>),
+) -> Vec<(&'a str, Result<MirrorScore, SpeedtestError>)> {
+    let mut tasks = stream::iter(remote_mirrors(mirrors))
+        .map(|(name, mirror)| async move {
+            (name, test_mirror(client, &mirror.url, config.timeout).await)
+        })
+        .buffer_unordered(config.concurrency.max(1));
+
+    let mut res = vec![];
+
+    while let Some((name, score)) = tasks.next().await {
+        callback(name, &score);
+        res.push((name, score));
+    }
+
+    sort_scores(&mut res);
+
+    res
+}
+
+fn remote_mirrors<'a>(
+    mirrors: impl IntoIterator<Item = (&'a str, &'a Mirror)>,
+) -> impl Iterator<Item = (&'a str, &'a Mirror)> {
+    mirrors.into_iter().filter(|(name, mirror)| {
+        let local = mirror.url.starts_with("file://");
+        if local {
+            debug!("Skipping local mirror {name}");
+        }
+        !local
+    })
+}
+
+fn sort_scores(scores: &mut [(&str, Result<MirrorScore, SpeedtestError>)]) {
+    scores.sort_by(|(_, a), (_, b)| match (a, b) {
+        (Ok(a), Ok(b)) => b
+            .throughput()
+            .cmp(&a.throughput())
+            .then(a.latency.cmp(&b.latency)),
+        (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+        (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+        (Err(_), Err(_)) => std::cmp::Ordering::Equal,
+    });
+}
+
+async fn test_mirror(
+    client: &Client,
+    url: &str,
+    timeout: Duration,
+) -> Result<MirrorScore, SpeedtestError> {
+    let url = if url.ends_with('/') {
+        format!("{url}{TEST_FILE}")
+    } else {
+        format!("{url}/{TEST_FILE}")
+    };
+
+    let deadline = Instant::now() + timeout;
+
+    let latency = timeout_at(deadline, connect_latency(&url))
+        .await
+        .map_err(|_| SpeedtestError::Timeout)??;
+
+    let resp = timeout_at(deadline, client.get(&url).send())
+        .await
+        .map_err(|_| SpeedtestError::Timeout)?
+        .and_then(|x| x.error_for_status())
+        .context(RequestSnafu)?;
+
+    let body_start = Instant::now();
+
+    let mut body = resp.bytes_stream();
+    let mut sha256 = Sha256::new();
+    let mut downloaded = 0;
+
+    let complete = loop {
+        match timeout_at(deadline, body.next()).await {
+            Ok(Some(chunk)) => {
+                let chunk = chunk.context(RequestSnafu)?;
+                sha256.update(&chunk);
+                downloaded += chunk.len() as u64;
+            }
+            Ok(None) => break true,
+            // 超时后用已经下载的部分计算速度，避免慢速镜像源拖慢整个测试
+            Err(_) => break false,
+        }
+    };
+
+    let download_time = body_start.elapsed();
+
+    debug!("{url}: latency {latency:?}, downloaded {downloaded} bytes in {download_time:?}");
+
+    if complete && hex_string(&sha256.finalize()) != TEST_FILE_SHA256 {
+        return Err(SpeedtestError::ChecksumMismatch);
+    }
+
+    Ok(MirrorScore {
+        latency,
+        downloaded,
+        download_time,
+        complete,
+    })
+}
+
+/// 用新的连接测量延迟，`HTTP_CLIENT` 的连接池会复用已经建立的连接
+async fn connect_latency(url: &str) -> Result<Duration, SpeedtestError> {
+    let parsed = Url::parse(url).ok().context(InvalidUrlSnafu { url })?;
+    let host = parsed.host_str().context(InvalidUrlSnafu { url })?;
+    let port = parsed
+        .port_or_known_default()
+        .context(InvalidUrlSnafu { url })?;
+
+    let addr = lookup_host((host, port))
+        .await
+        .context(ConnectSnafu { host })?
+        .next()
+        .context(InvalidUrlSnafu { url })?;
+
+    let start = Instant::now();
+    TcpStream::connect(addr)
+        .await
+        .context(ConnectSnafu { host })?;
+
+    Ok(start.elapsed())
+}
+
+#[test]
+fn test_sort_scores() {
+    let score = |downloaded, latency| {
+        Ok(MirrorScore {
+            latency: Duration::from_millis(latency),
+            downloaded,
+            download_time: Duration::from_secs(1),
+            complete: true,
+        })
+    };
+
+    let mut scores = vec![
+        ("failed", Err(SpeedtestError::Timeout)),
+        ("slow", score(1024, 10)),
+        ("fast", score(4096, 100)),
+        ("fast-low-latency", score(4096, 50)),
+    ];
+
+    sort_scores(&mut scores);
+
+    assert_eq!(
+        scores.iter().map(|x| x.0).collect::<Vec<_>>(),
+        vec!["fast-low-latency", "fast", "slow", "failed"]
+    );
+}
+
+#[test]
+fn test_remote_mirrors() {
+    let mirror = |url: &str| Mirror {
+        desc: Box::from(""),
+        url: Box::from(url),
+    };
+
+    let mirrors = [
+        ("origin", mirror("https://repo.aosc.io/")),
+        ("local", mirror("file:///mnt/repo/")),
+        ("http", mirror("http://mirrors.example.com/anthon/")),
+    ];
+
+    assert_eq!(
+        remote_mirrors(mirrors.iter().map(|(n, m)| (*n, m)))
+            .map(|x| x.0)
+            .collect::<Vec<_>>(),
+        vec!["origin", "http"]
+    );
+}
+
+#[tokio::test]
+async fn test_connect_latency() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    assert!(
+        connect_latency(&format!("http://127.0.0.1:{port}/.repotest"))
+            .await
+            .is_ok()
+    );
+
+    drop(listener);
+
+    assert!(matches!(
+        connect_latency(&format!("http://127.0.0.1:{port}/.repotest")).await,
+        Err(SpeedtestError::Connect { .. })
+    ));
+    assert!(matches!(
+        connect_latency("file:///mnt/repo/.repotest").await,
+        Err(SpeedtestError::InvalidUrl { .. })
+    ));
+}
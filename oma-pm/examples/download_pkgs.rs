@@ -42,6 +42,8 @@ fn main() -> Result<(), OmaAptError> {
             network_thread: None,
             download_dir: Some(Path::new("test")),
             auth: &AuthConfig::system("/").unwrap(),
+            speed_limit: None,
+            mirrors: &[],
         },
         false,
         &pm,
@@ -118,6 +118,8 @@ fn main() -> Result<(), OmaAptError> {
         CommitDownloadConfig {
             network_thread: None,
             auth: &AuthConfig::system("/").unwrap(),
+            speed_limit: None,
+            mirrors: &[],
         },
         &pm,
         Box::new(MyInstallProgressManager),
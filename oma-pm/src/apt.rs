@@ -5,6 +5,10 @@ use std::{
     io::{self, ErrorKind, Write},
     path::{Path, PathBuf},
     process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 
 use ahash::HashSet;
@@ -36,6 +40,7 @@ use oma_utils::{
     dpkg::{get_selections, is_hold, DpkgError},
     human_bytes::HumanBytes,
 };
+use serde::Serialize;
 
 pub use oma_apt::config::Config as AptConfig;
 use tokio::runtime::Runtime;
@@ -45,10 +50,12 @@ pub use oma_pm_operation_type::*;
 use zbus::{Connection, ConnectionBuilder};
 
 use crate::{
+    changelog,
     dbus::{change_status, OmaBus, Status},
     matches::MatcherError,
+    pin::{read_pins, Pin, PinError},
     pkginfo::{OmaPackage, OmaPackageWithoutVersion, PtrIsNone},
-    progress::{InstallProgressArgs, InstallProgressManager, OmaAptInstallProgress},
+    progress::{InstallProgressArgs, InstallProgressManager, OmaAptInstallProgress, TermLog},
 };
 
 const TIME_FORMAT: &str = "%H:%M:%S on %Y-%m-%d";
@@ -87,6 +94,16 @@ pub struct OmaApt {
     connection: Option<Connection>,
     unmet: Vec<Vec<BrokenPackage>>,
     archive_dir: OnceCell<PathBuf>,
+    upgrading: AtomicBool,
+    dpkg_output: Arc<Mutex<Vec<u8>>>,
+}
+
+/// A newer version is available but not used because of a pin
+#[derive(Debug, Clone, Serialize)]
+pub struct PinBlocked {
+    pub pin: Pin,
+    pub candidate: Option<String>,
+    pub newest: String,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -145,6 +162,10 @@ pub enum OmaAptError {
     ChecksumError(#[from] ChecksumError),
     #[error("Blocking installation due to features markers.")]
     Features,
+    #[error("Package {0} has no changelog.")]
+    NoChangelog(String),
+    #[error("Failed to download changelog from {0}: {1}")]
+    FailedGetChangelog(String, oma_fetch::reqwest::Error),
 }
 
 pub type OmaAptResult<T> = Result<T, OmaAptError>;
@@ -171,11 +192,17 @@ pub struct DownloadConfig<'a> {
     pub network_thread: Option<usize>,
     pub download_dir: Option<&'a Path>,
     pub auth: &'a AuthConfig,
+    pub speed_limit: Option<u64>,
+    /// Enabled mirrors, packages are fetched from the others if one of them is down
+    pub mirrors: &'a [String],
 }
 
 pub struct CommitDownloadConfig<'a> {
     pub network_thread: Option<usize>,
     pub auth: &'a AuthConfig,
+    pub speed_limit: Option<u64>,
+    /// Enabled mirrors, packages are fetched from the others if one of them is down
+    pub mirrors: &'a [String],
 }
 
 impl OmaApt {
@@ -218,6 +245,8 @@ impl OmaApt {
             connection: conn,
             unmet: vec![],
             archive_dir: OnceCell::new(),
+            upgrading: AtomicBool::new(false),
+            dpkg_output: Arc::new(Mutex::new(vec![])),
         })
     }
 
@@ -370,6 +399,7 @@ impl OmaApt {
     /// Set apt manager status as upgrade
     pub fn upgrade(&self, mode: Upgrade) -> OmaAptResult<()> {
         self.cache.upgrade(mode)?;
+        self.upgrading.store(true, Ordering::Relaxed);
 
         Ok(())
     }
@@ -421,6 +451,8 @@ impl OmaApt {
             network_thread,
             download_dir,
             auth,
+            speed_limit,
+            mirrors,
         } = config;
 
         let mut download_list = vec![];
@@ -487,6 +519,8 @@ impl OmaApt {
                 download_dir.unwrap_or(Path::new(".")),
                 progress_manager,
                 auth,
+                speed_limit,
+                mirrors,
             )
             .await
         })?;
@@ -494,6 +528,57 @@ impl OmaApt {
         Ok(res)
     }
 
+    /// Get changelog entries of the package newer than the installed version
+    ///
+    /// Read from the .deb in archive cache first, otherwise download from the changelogs URL of repository
+    pub fn changelog(&self, client: &Client, pkg: &OmaPackage) -> OmaAptResult<String> {
+        let raw_pkg = unsafe { pkg.raw_pkg.unique() }
+            .make_safe()
+            .ok_or(OmaAptError::PtrIsNone(PtrIsNone))?;
+        let version = unsafe { pkg.version_raw.unique() }
+            .make_safe()
+            .ok_or(OmaAptError::PtrIsNone(PtrIsNone))?;
+
+        let pkg = Package::new(&self.cache, raw_pkg);
+        let ver = Version::new(version, &self.cache);
+        let installed = pkg.installed().map(|x| x.version().to_string());
+
+        let deb = self
+            .get_archive_dir()
+            .join(deb_filename(pkg.name(), ver.version(), ver.arch()));
+
+        let changelog = if deb.is_file() {
+            changelog::read_from_deb(&deb, pkg.name())
+                .map_err(|e| OmaAptError::FailedOperateDirOrFile(deb.display().to_string(), e))?
+        } else {
+            None
+        };
+
+        let changelog = match changelog {
+            Some(changelog) => changelog,
+            None => {
+                let uri = changelog::changelog_uri(&self.cache, &self.config, &ver)
+                    .ok_or_else(|| OmaAptError::NoChangelog(pkg.fullname(true)))?;
+
+                debug!("Download changelog from {uri}");
+
+                self.tokio
+                    .block_on(async {
+                        client
+                            .get(&uri)
+                            .send()
+                            .await?
+                            .error_for_status()?
+                            .text()
+                            .await
+                    })
+                    .map_err(|e| OmaAptError::FailedGetChangelog(uri, e))?
+            }
+        };
+
+        Ok(changelog::newer_entries(&changelog, installed.as_deref()))
+    }
+
     /// Set apt manager status as remove
     pub fn remove(
         &mut self,
@@ -557,6 +642,11 @@ impl OmaApt {
         self.config.get_architectures()
     }
 
+    /// Start recording the dpkg terminal log of the next commit
+    pub fn term_log(&self) -> TermLog {
+        TermLog::new(&self.config, Arc::clone(&self.dpkg_output))
+    }
+
     /// Commit changes
     pub fn commit(
         self,
@@ -566,11 +656,6 @@ impl OmaApt {
         install_progress_manager: Box<dyn InstallProgressManager>,
         op: OmaOperation,
     ) -> OmaAptResult<()> {
-        let CommitDownloadConfig {
-            network_thread,
-            auth,
-        } = config;
-
         let v = op;
         let v_str = v.to_string();
 
@@ -581,32 +666,7 @@ impl OmaApt {
             return Ok(());
         }
 
-        let download_pkg_list = v.install;
-
-        let path = self.get_archive_dir();
-
-        let conn = self.connection.clone();
-        let (success, failed) = self.tokio.block_on(async move {
-            if let Some(conn) = conn {
-                change_status(&conn, "Downloading").await.ok();
-            }
-
-            Self::download_pkgs(
-                client,
-                download_pkg_list,
-                network_thread,
-                path,
-                download_progress_manager,
-                auth,
-            )
-            .await
-        })?;
-
-        if !failed.is_empty() {
-            return Err(OmaAptError::FailedToDownload(failed.len(), failed));
-        }
-
-        debug!("Success: {success:?}");
+        self.download_archives(client, config, download_progress_manager, v.install)?;
 
         let mut no_progress = AcquireProgress::quiet();
 
@@ -634,6 +694,7 @@ impl OmaApt {
             config: self.config,
             tokio: self.tokio,
             connection: self.connection,
+            output: self.dpkg_output,
         };
 
         let mut progress =
@@ -694,6 +755,120 @@ impl OmaApt {
         Ok(())
     }
 
+    /// Only download packages of the operation to archive dir, do not install
+    pub fn commit_download_only(
+        &self,
+        client: &Client,
+        config: CommitDownloadConfig<'_>,
+        download_progress_manager: &dyn DownloadProgressControl,
+        op: &OmaOperation,
+    ) -> OmaAptResult<()> {
+        if self.dry_run {
+            debug!("op: {op:?}");
+            return Ok(());
+        }
+
+        self.download_archives(
+            client,
+            config,
+            download_progress_manager,
+            op.install.clone(),
+        )
+    }
+
+    fn download_archives(
+        &self,
+        client: &Client,
+        config: CommitDownloadConfig<'_>,
+        download_progress_manager: &dyn DownloadProgressControl,
+        download_pkg_list: Vec<InstallEntry>,
+    ) -> OmaAptResult<()> {
+        let CommitDownloadConfig {
+            network_thread,
+            auth,
+            speed_limit,
+            mirrors,
+        } = config;
+
+        let path = self.get_archive_dir();
+
+        let conn = self.connection.clone();
+        let (success, failed) = self.tokio.block_on(async move {
+            if let Some(conn) = conn {
+                change_status(&conn, "Downloading").await.ok();
+            }
+
+            Self::download_pkgs(
+                client,
+                download_pkg_list,
+                network_thread,
+                path,
+                download_progress_manager,
+                auth,
+                speed_limit,
+                mirrors,
+            )
+            .await
+        })?;
+
+        if !failed.is_empty() {
+            return Err(OmaAptError::FailedToDownload(failed.len(), failed));
+        }
+
+        debug!("Success: {success:?}");
+
+        Ok(())
+    }
+
+    /// Mark the changes of a resolved operation (e.g. recorded by offline upgrade) again
+    pub fn mark_operation(&mut self, op: &OmaOperation) -> OmaAptResult<()> {
+        for entry in &op.install {
+            let unavailable = || {
+                OmaAptError::PkgUnavailable(
+                    entry.name().to_string(),
+                    entry.new_version().to_string(),
+                )
+            };
+
+            let pkg = self.cache.get(entry.name()).ok_or_else(unavailable)?;
+            let ver = pkg
+                .get_version(entry.new_version())
+                .ok_or_else(unavailable)?;
+
+            ver.set_candidate();
+
+            // 依赖已经全部记录在 op 中，因此不需要再自动安装依赖
+            let is_marked = if *entry.op() == InstallOperation::ReInstall {
+                pkg.mark_reinstall(true)
+            } else {
+                pkg.mark_install(false, !entry.automatic())
+            };
+
+            if !is_marked {
+                return Err(unavailable());
+            }
+
+            pkg.protect();
+            self.select_pkgs.insert(pkg.index());
+        }
+
+        for entry in &op.remove {
+            let pkg = self
+                .cache
+                .get(entry.name())
+                .ok_or_else(|| OmaAptError::MarkPkgNotInstalled(entry.name().to_string()))?;
+
+            if !pkg.mark_delete(entry.details().contains(&RemoveTag::Purge)) {
+                return Err(OmaAptError::MarkPkgNotInstalled(entry.name().to_string()));
+            }
+
+            pkg.protect();
+            self.select_pkgs.insert(pkg.index());
+        }
+
+        Ok(())
+    }
+
     pub fn fix_broken(&mut self, fix_resolver: bool, fix_dpkg_status: bool) -> OmaAptResult<()> {
         if fix_resolver {
             self.cache.fix_broken();
@@ -787,6 +962,52 @@ impl OmaApt {
         Ok(())
     }
 
+    /// Packages whose newer versions are not used because of the pins
+    pub fn pin_blocked_upgrades(&self, pins: &[Pin]) -> Vec<PinBlocked> {
+        pins.iter()
+            .filter_map(|pin| {
+                let pkg = self.cache.get(&pin.package)?;
+                let cand = pkg.candidate();
+                let newest = pkg.versions().filter(|x| x.is_downloadable()).max()?;
+
+                if cand.as_ref().is_some_and(|x| *x >= newest)
+                    || pkg.installed().is_some_and(|x| x >= newest)
+                {
+                    return None;
+                }
+
+                Some(PinBlocked {
+                    pin: pin.clone(),
+                    candidate: cand.map(|x| x.version().to_string()),
+                    newest: newest.version().to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// Pins blocking the upgrade of packages being upgraded or installed in this operation
+    pub fn blocked_by_pins(&self) -> Result<Vec<PinBlocked>, PinError> {
+        let sysroot = self.config.get("Dir").unwrap_or_else(|| "/".to_string());
+        let pins = read_pins(sysroot)?;
+
+        // 只关心本次升级或指定安装的软件包
+        let upgrading = self.upgrading.load(Ordering::Relaxed);
+
+        let blocked = self
+            .pin_blocked_upgrades(&pins)
+            .into_iter()
+            .filter(|blocked| {
+                upgrading
+                    || self
+                        .cache
+                        .get(&blocked.pin.package)
+                        .is_some_and(|x| self.select_pkgs.contains(&x.index()))
+            })
+            .collect();
+
+        Ok(blocked)
+    }
+
     fn resolve_inner(&mut self, no_fixbroken: bool) -> Result<(), OmaAptError> {
         if let Err(e) = self.cache.resolve(!no_fixbroken) {
             debug!("{e:#?}");
@@ -845,6 +1066,7 @@ impl OmaApt {
     }
 
     /// Download packages (inner)
+    #[allow(clippy::too_many_arguments)]
     async fn download_pkgs(
         client: &Client,
         download_pkg_list: Vec<InstallEntry>,
@@ -852,6 +1074,8 @@ impl OmaApt {
         download_dir: &Path,
         progress_manager: &dyn DownloadProgressControl,
         auth_config: &AuthConfig,
+        speed_limit: Option<u64>,
+        mirrors: &[String],
     ) -> OmaAptResult<(Vec<Summary>, Vec<DownloadError>)> {
         if download_pkg_list.is_empty() {
             progress_manager.all_done();
@@ -931,6 +1155,8 @@ impl OmaApt {
             .maybe_threads(network_thread)
             .progress_manager(progress_manager)
             .total_size(total_size)
+            .maybe_speed_limit(speed_limit)
+            .mirrors(mirrors)
             .build();
 
         let res = downloader.start_download().await;
@@ -1011,6 +1237,25 @@ impl OmaApt {
         pkgs: Vec<OmaPackage>,
         auto: bool,
         dry_run: bool,
+    ) -> OmaAptResult<Vec<(String, bool)>> {
+        let res = self.set_install_status(pkgs, auto)?;
+
+        if dry_run {
+            return Ok(res);
+        }
+
+        self.cache
+            .commit(&mut AcquireProgress::quiet(), &mut InstallProgress::apt())
+            .map_err(|e| OmaAptError::CommitErr(e.to_string()))?;
+
+        Ok(res)
+    }
+
+    /// Mark version status (auto/manual) without committing, the marks are written by the next commit
+    pub fn set_install_status(
+        &self,
+        pkgs: Vec<OmaPackage>,
+        auto: bool,
     ) -> OmaAptResult<Vec<(String, bool)>> {
         let mut res = vec![];
         for pkg in pkgs {
@@ -1051,14 +1296,6 @@ impl OmaApt {
             }
         }
 
-        if dry_run {
-            return Ok(res);
-        }
-
-        self.cache
-            .commit(&mut AcquireProgress::quiet(), &mut InstallProgress::apt())
-            .map_err(|e| OmaAptError::CommitErr(e.to_string()))?;
-
         Ok(res)
     }
 
@@ -1638,10 +1875,10 @@ fn broken_pkg(cache: &Cache, pkg: &Package, now: bool) -> Vec<Vec<BrokenPackage>
 
 /// trans filename to apt style file name
 fn apt_style_filename(entry: &InstallEntry) -> String {
-    let package = entry.name_without_arch();
-    let version = entry.new_version();
-    let arch = entry.arch();
+    deb_filename(entry.name_without_arch(), entry.new_version(), entry.arch())
+}
 
+fn deb_filename(package: &str, version: &str, arch: &str) -> String {
     let version = version.replace(':', "%3a");
 
     format!("{package}_{version}_{arch}.deb").replace("%2b", "+")
@@ -0,0 +1,271 @@
+use std::{
+    cmp::Ordering,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use flate2::read::GzDecoder;
+use oma_apt::{cache::Cache, config::Config as AptConfig, util::cmp_versions, Version};
+use tracing::debug;
+
+/// Changelog URL of the version
+///
+/// Like apt, the URL template comes from the `Changelogs` field of the Release file of the
+/// repository, then `Acquire::Changelogs::URI::Origin::<Origin>`, then the known defaults of
+/// Debian and Ubuntu. `@CHANGEPATH@` in the template is replaced by the path of the source package.
+pub fn changelog_uri(cache: &Cache, config: &AptConfig, ver: &Version) -> Option<String> {
+    let src_pkg = ver.source_name();
+    let mut src_ver = ver.source_version().to_string();
+    let mut section = ver.section().ok()?.to_string();
+
+    if let Ok(src_records) = cache.source_records() {
+        while let Some(record) = src_records.lookup(src_pkg.to_string(), false) {
+            let record_version = record.version();
+
+            if cmp_versions(&record_version, &src_ver) != Ordering::Less {
+                src_ver = record_version;
+                section = record.section();
+                break;
+            }
+        }
+    }
+
+    let template = ver.package_files().find_map(|file| {
+        let from_release = file
+            .filename()
+            .zip(file.component())
+            .and_then(|(filename, component)| release_path(filename, component))
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|release| release_changelogs(&release).map(|x| x.to_string()));
+
+        from_release.or_else(|| {
+            let origin = file.origin()?;
+
+            config
+                .get(&format!("Acquire::Changelogs::URI::Origin::{origin}"))
+                .or_else(|| default_template(origin).map(|x| x.to_string()))
+        })
+    })?;
+
+    changelog_url(&template, &section, src_pkg, &src_ver)
+}
+
+/// Expand the changelog URL template with the path of the source package
+fn changelog_url(template: &str, section: &str, src_pkg: &str, src_ver: &str) -> Option<String> {
+    // Release 中写 `Changelogs: no` 表示不提供 changelog
+    if template == "no" || !template.contains("@CHANGEPATH@") {
+        return None;
+    }
+
+    let prefix = if src_pkg.starts_with("lib") {
+        format!("lib{}", src_pkg.chars().nth(3)?)
+    } else {
+        src_pkg.chars().next()?.to_string()
+    };
+
+    let component = match section.split_once('/') {
+        Some((component, _)) => component,
+        None => "main",
+    };
+
+    // 去掉 epoch
+    let src_ver = src_ver.split_once(':').map(|x| x.1).unwrap_or(src_ver);
+
+    Some(template.replace(
+        "@CHANGEPATH@",
+        &format!("{component}/{prefix}/{src_pkg}/{src_pkg}_{src_ver}"),
+    ))
+}
+
+fn default_template(origin: &str) -> Option<&'static str> {
+    match origin {
+        "Debian" => {
+            Some("https://metadata.ftp-master.debian.org/changelogs/@CHANGEPATH@_changelog")
+        }
+        "Ubuntu" => Some("https://changelogs.ubuntu.com/changelogs/pool/@CHANGEPATH@/changelog"),
+        _ => None,
+    }
+}
+
+/// The (In)Release file next to a Packages file in the lists directory
+///
+/// e.g. `example.org_debs_dists_stable_main_binary-amd64_Packages` -> `example.org_debs_dists_stable_InRelease`
+fn release_path(filename: &str, component: &str) -> Option<PathBuf> {
+    let (prefix, _) = filename.rsplit_once(&format!("_{component}_"))?;
+
+    ["InRelease", "Release"]
+        .into_iter()
+        .map(|name| PathBuf::from(format!("{prefix}_{name}")))
+        .find(|path| path.is_file())
+}
+
+/// The `Changelogs` field of a Release file
+fn release_changelogs(release: &str) -> Option<&str> {
+    release
+        .lines()
+        .find_map(|line| line.strip_prefix("Changelogs:"))
+        .map(|x| x.trim())
+}
+
+/// Read `changelog.Debian.gz` (or `changelog.gz` of native package) from .deb file
+pub fn read_from_deb(deb: &Path, pkg_name: &str) -> io::Result<Option<String>> {
+    let mut child = Command::new("dpkg-deb")
+        .arg("--fsys-tarfile")
+        .arg(deb)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "Failed to get stdout"))?;
+
+    let doc_dir = Path::new("usr/share/doc").join(pkg_name);
+    let names = [
+        doc_dir.join("changelog.Debian.gz"),
+        doc_dir.join("changelog.gz"),
+    ];
+
+    let mut archive = tar::Archive::new(stdout);
+    let mut res = None;
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?.into_owned();
+        let path = path.strip_prefix(".").unwrap_or(&path);
+
+        if names.iter().any(|x| x == path) {
+            debug!("Found changelog {} in {}", path.display(), deb.display());
+            let mut s = String::new();
+            GzDecoder::new(entry).read_to_string(&mut s)?;
+            res = Some(s);
+            break;
+        }
+    }
+
+    drop(archive);
+
+    // 找到 changelog 后不需要 dpkg-deb 继续输出
+    child.kill().ok();
+    child.wait()?;
+
+    Ok(res)
+}
+
+/// Only keep the changelog entries newer than `installed` version
+pub fn newer_entries(changelog: &str, installed: Option<&str>) -> String {
+    let Some(installed) = installed else {
+        return changelog.to_string();
+    };
+
+    let mut res = String::new();
+
+    for line in changelog.lines() {
+        if entry_version(line).is_some_and(|ver| cmp_versions(ver, installed) != Ordering::Greater)
+        {
+            break;
+        }
+
+        res.push_str(line);
+        res.push('\n');
+    }
+
+    res
+}
+
+/// 每个条目的第一行形如 `package (version) distribution(s); urgency=urgency`
+fn entry_version(line: &str) -> Option<&str> {
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let (_, rest) = line.split_once(' ')?;
+    let (version, _) = rest.strip_prefix('(')?.split_once(')')?;
+
+    Some(version)
+}
+
+#[test]
+fn test_entry_version() {
+    assert_eq!(
+        entry_version("apt (2.7.14) unstable; urgency=medium"),
+        Some("2.7.14")
+    );
+    assert_eq!(
+        entry_version("bash (5.2.21-2.1) unstable; urgency=medium"),
+        Some("5.2.21-2.1")
+    );
+    assert_eq!(entry_version("  * Fix some bugs (Closes: #1)"), None);
+    assert_eq!(
+        entry_version(" -- Someone <someone@example.org>  Mon, 01 Jan 2024 00:00:00 +0000"),
+        None
+    );
+}
+
+#[test]
+fn test_changelog_url() {
+    let aosc = "https://packages.aosc.io/changelog/@CHANGEPATH@";
+
+    assert_eq!(
+        changelog_url(aosc, "utils", "bash", "5.2.21-2").as_deref(),
+        Some("https://packages.aosc.io/changelog/main/b/bash/bash_5.2.21-2")
+    );
+    assert_eq!(
+        changelog_url(aosc, "contrib/libs", "libxml2", "1:2.12.6").as_deref(),
+        Some("https://packages.aosc.io/changelog/contrib/libx/libxml2/libxml2_2.12.6")
+    );
+    assert_eq!(
+        changelog_url(
+            default_template("Debian").unwrap(),
+            "admin",
+            "apt",
+            "2.7.14"
+        )
+        .as_deref(),
+        Some("https://metadata.ftp-master.debian.org/changelogs/main/a/apt/apt_2.7.14_changelog")
+    );
+    assert_eq!(changelog_url("no", "admin", "apt", "2.7.14"), None);
+    assert_eq!(default_template("AOSC"), None);
+}
+
+#[test]
+fn test_release_changelogs() {
+    let release = "-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA512
+
+Origin: AOSC
+Suite: stable
+Changelogs: https://packages.aosc.io/changelog/@CHANGEPATH@
+SHA256:
+ 0123 100 main/binary-amd64/Packages
+-----BEGIN PGP SIGNATURE-----
+";
+
+    assert_eq!(
+        release_changelogs(release),
+        Some("https://packages.aosc.io/changelog/@CHANGEPATH@")
+    );
+    assert_eq!(release_changelogs("Origin: AOSC\nSuite: stable\n"), None);
+}
+
+#[test]
+fn test_release_path() {
+    let dir = std::env::temp_dir().join(format!("oma-changelog-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let prefix = dir.join("repo.aosc.io_debs_dists_stable");
+    let list = format!("{}_main_binary-amd64_Packages", prefix.display());
+
+    assert_eq!(release_path(&list, "main"), None);
+
+    let release = PathBuf::from(format!("{}_InRelease", prefix.display()));
+    fs::write(&release, "").unwrap();
+
+    assert_eq!(release_path(&list, "main"), Some(release));
+    assert_eq!(release_path(&list, "contrib"), None);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
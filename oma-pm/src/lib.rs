@@ -1,11 +1,14 @@
 pub mod apt;
 pub mod matches;
+pub mod pin;
 pub mod pkginfo;
 pub mod progress;
 pub mod search;
 pub use oma_apt::error::AptErrors;
 pub use oma_apt::PkgCurrentState;
+pub use oma_apt::Version;
 pub use search::PackageStatus;
+mod changelog;
 mod dbus;
 
 #[cfg(test)]
@@ -0,0 +1,178 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+/// APT preferences file managed by `oma pin` and `oma unpin`
+pub const PINS_FILE: &str = "etc/apt/preferences.d/oma-pins";
+
+const HEADER: &str = "# This file is managed by oma, use `oma pin' and `oma unpin' to change it.\n";
+
+#[derive(Debug, thiserror::Error)]
+pub enum PinError {
+    #[error("Failed to read {0}")]
+    ReadFile(String, #[source] io::Error),
+    #[error("Failed to write {0}")]
+    WriteFile(String, #[source] io::Error),
+    #[error("Invalid pin in {0}: {1}")]
+    InvalidPin(String, String),
+}
+
+/// What a package is pinned to, the value of the `Pin` field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PinTarget {
+    /// A version, globs like `1.2*` are allowed
+    Version(String),
+    /// Fields of the Release file, e.g. `o=AOSC` or `a=stable`
+    Release(String),
+    /// Hostname of the repository
+    Origin(String),
+}
+
+impl Display for PinTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PinTarget::Version(v) => write!(f, "version {v}"),
+            PinTarget::Release(r) => write!(f, "release {r}"),
+            PinTarget::Origin(o) => write!(f, "origin \"{o}\""),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Pin {
+    pub package: String,
+    pub target: PinTarget,
+    pub priority: i32,
+}
+
+impl Display for Pin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Package: {}", self.package)?;
+        writeln!(f, "Pin: {}", self.target)?;
+        writeln!(f, "Pin-Priority: {}", self.priority)
+    }
+}
+
+pub fn pins_path(sysroot: impl AsRef<Path>) -> PathBuf {
+    sysroot.as_ref().join(PINS_FILE)
+}
+
+/// Read the pins, no pins if the file does not exist
+pub fn read_pins(sysroot: impl AsRef<Path>) -> Result<Vec<Pin>, PinError> {
+    let path = pins_path(sysroot);
+
+    let s = match fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(PinError::ReadFile(path.display().to_string(), e)),
+    };
+
+    parse_pins(&s).map_err(|e| PinError::InvalidPin(path.display().to_string(), e))
+}
+
+/// Write the pins, the file is removed if there is no pin left
+pub fn write_pins(sysroot: impl AsRef<Path>, pins: &[Pin]) -> Result<(), PinError> {
+    let path = pins_path(sysroot);
+    let err = |e| PinError::WriteFile(path.display().to_string(), e);
+
+    if pins.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(err(e)),
+            _ => Ok(()),
+        };
+    }
+
+    let s = pins
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(err)?;
+    }
+
+    fs::write(&path, format!("{HEADER}\n{s}")).map_err(err)
+}
+
+fn parse_pins(s: &str) -> Result<Vec<Pin>, String> {
+    let mut pins = vec![];
+
+    for stanza in s.split("\n\n") {
+        let mut package = None;
+        let mut target = None;
+        let mut priority = None;
+
+        for line in stanza.lines().map(|x| x.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((k, v)) = line.split_once(':') else {
+                return Err(line.to_string());
+            };
+
+            let v = v.trim();
+
+            match k.trim() {
+                "Package" => package = Some(v.to_string()),
+                "Pin" => {
+                    let (typ, value) = v.split_once(' ').ok_or_else(|| line.to_string())?;
+                    let value = value.trim().to_string();
+
+                    target = Some(match typ {
+                        "version" => PinTarget::Version(value),
+                        "release" => PinTarget::Release(value),
+                        "origin" => PinTarget::Origin(value.trim_matches('"').to_string()),
+                        _ => return Err(line.to_string()),
+                    });
+                }
+                "Pin-Priority" => priority = Some(v.parse().map_err(|_| line.to_string())?),
+                // 保留 Explanation 等字段不报错
+                _ => {}
+            }
+        }
+
+        match (package, target, priority) {
+            (Some(package), Some(target), Some(priority)) => pins.push(Pin {
+                package,
+                target,
+                priority,
+            }),
+            (None, None, None) => {}
+            _ => return Err(stanza.trim().to_string()),
+        }
+    }
+
+    Ok(pins)
+}
+
+#[test]
+fn test_parse_pins() {
+    let pins = vec![
+        Pin {
+            package: "foo".to_string(),
+            target: PinTarget::Version("1.2*".to_string()),
+            priority: 1001,
+        },
+        Pin {
+            package: "bar".to_string(),
+            target: PinTarget::Release("o=AOSC".to_string()),
+            priority: 990,
+        },
+    ];
+
+    let s = pins
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    assert_eq!(parse_pins(&format!("{HEADER}\n{s}")).unwrap(), pins);
+    assert!(parse_pins("Package: foo\nPin-Priority: 100\n").is_err());
+}
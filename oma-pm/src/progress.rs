@@ -1,6 +1,19 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    os::fd::RawFd,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
 use crate::{apt::AptConfig, dbus::change_status};
 use oma_apt::progress::DynInstallProgress;
 use tokio::runtime::Runtime;
+use tracing::debug;
 use zbus::Connection;
 
 pub use oma_apt::util::{get_apt_progress_string, terminal_height, terminal_width};
@@ -16,6 +29,7 @@ pub(crate) struct InstallProgressArgs {
     pub config: AptConfig,
     pub tokio: Runtime,
     pub connection: Option<Connection>,
+    pub output: Arc<Mutex<Vec<u8>>>,
 }
 
 pub(crate) struct OmaAptInstallProgress {
@@ -23,6 +37,7 @@ pub(crate) struct OmaAptInstallProgress {
     tokio: Runtime,
     connection: Option<Connection>,
     pm: Box<dyn InstallProgressManager>,
+    capture: Option<OutputCapture>,
 }
 
 pub trait InstallProgressManager {
@@ -38,21 +53,29 @@ impl OmaAptInstallProgress {
             config,
             tokio,
             connection,
+            output,
         } = args;
 
         if pm.no_interactive() {
             std::env::set_var("DEBIAN_FRONTEND", "noninteractive");
         }
 
-        if !pm.use_pty() {
+        // 不使用 pty 时 apt 不会写入 term.log，因此需要自行截取 dpkg 的输出
+        let capture = if !pm.use_pty() {
             config.set("Dpkg::Use-Pty", "false");
-        }
+            OutputCapture::new(output)
+                .inspect_err(|e| debug!("Failed to capture dpkg output: {e}"))
+                .ok()
+        } else {
+            None
+        };
 
         Self {
             config,
             tokio,
             connection,
             pm,
+            capture,
         }
     }
 }
@@ -67,8 +90,16 @@ impl DynInstallProgress for OmaAptInstallProgress {
     ) {
         let conn = &self.connection;
 
-        self.pm
-            .status_change(&pkgname, steps_done, total_steps, &self.config);
+        // oma 自身的输出不属于 dpkg 日志
+        match &self.capture {
+            Some(capture) => capture.paused(|| {
+                self.pm
+                    .status_change(&pkgname, steps_done, total_steps, &self.config)
+            }),
+            None => self
+                .pm
+                .status_change(&pkgname, steps_done, total_steps, &self.config),
+        }
 
         self.tokio.block_on(async move {
             if let Some(conn) = conn {
@@ -81,3 +112,277 @@ impl DynInstallProgress for OmaAptInstallProgress {
 
     fn error(&mut self, _pkgname: String, _steps_done: u64, _total_steps: u64, _error: String) {}
 }
+
+/// Redirect stdout and stderr into a pipe while dpkg runs
+///
+/// dpkg inherits the redirected descriptors, everything it prints is
+/// forwarded to the original stdout and copied into the output buffer.
+struct OutputCapture {
+    saved: [RawFd; 2],
+    write: RawFd,
+    stop: Arc<AtomicBool>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl OutputCapture {
+    fn new(output: Arc<Mutex<Vec<u8>>>) -> io::Result<Self> {
+        let mut fds = [0; 2];
+        cvt(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) })?;
+        let [read, write] = fds;
+
+        let saved = [dup(libc::STDOUT_FILENO)?, dup(libc::STDERR_FILENO)?];
+
+        flush();
+        redirect(write)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop.clone();
+        let forward = saved[0];
+
+        let reader = std::thread::spawn(move || {
+            read_pipe(read, forward, &output, &stop_clone);
+            unsafe { libc::close(read) };
+        });
+
+        Ok(Self {
+            saved,
+            write,
+            stop,
+            reader: Some(reader),
+        })
+    }
+
+    /// Run `f` with the original stdout and stderr
+    fn paused<F: FnOnce()>(&self, f: F) {
+        flush();
+        restore(self.saved);
+        f();
+        flush();
+        redirect(self.write).ok();
+    }
+}
+
+impl Drop for OutputCapture {
+    fn drop(&mut self) {
+        flush();
+        restore(self.saved);
+
+        unsafe { libc::close(self.write) };
+
+        // postinst 启动的守护进程可能仍持有管道的写入端，因此不能等到 EOF
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(reader) = self.reader.take() {
+            reader.join().ok();
+        }
+
+        for fd in self.saved {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+fn read_pipe(read: RawFd, forward: RawFd, output: &Mutex<Vec<u8>>, stop: &AtomicBool) {
+    let mut buf = [0u8; 4096];
+
+    loop {
+        let mut pfd = libc::pollfd {
+            fd: read,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        let stopping = stop.load(Ordering::Relaxed);
+        let ready = unsafe { libc::poll(&mut pfd, 1, if stopping { 0 } else { 100 }) };
+
+        if ready < 0 {
+            if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return;
+        }
+
+        if ready == 0 {
+            if stopping {
+                return;
+            }
+            continue;
+        }
+
+        let n = unsafe { libc::read(read, buf.as_mut_ptr().cast(), buf.len()) };
+
+        if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+            continue;
+        }
+
+        if n <= 0 {
+            return;
+        }
+
+        let chunk = &buf[..n as usize];
+        write_all(forward, chunk);
+
+        if let Ok(mut output) = output.lock() {
+            output.extend_from_slice(chunk);
+        }
+    }
+}
+
+fn write_all(fd: RawFd, mut buf: &[u8]) {
+    while !buf.is_empty() {
+        let n = unsafe { libc::write(fd, buf.as_ptr().cast(), buf.len()) };
+
+        if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+            continue;
+        }
+
+        if n <= 0 {
+            return;
+        }
+
+        buf = &buf[n as usize..];
+    }
+}
+
+fn flush() {
+    io::stdout().flush().ok();
+    io::stderr().flush().ok();
+}
+
+fn redirect(fd: RawFd) -> io::Result<()> {
+    cvt(unsafe { libc::dup2(fd, libc::STDOUT_FILENO) })?;
+    cvt(unsafe { libc::dup2(fd, libc::STDERR_FILENO) })?;
+
+    Ok(())
+}
+
+fn restore(saved: [RawFd; 2]) {
+    unsafe {
+        libc::dup2(saved[0], libc::STDOUT_FILENO);
+        libc::dup2(saved[1], libc::STDERR_FILENO);
+    }
+}
+
+fn dup(fd: RawFd) -> io::Result<RawFd> {
+    cvt(unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) })
+}
+
+fn cvt(res: libc::c_int) -> io::Result<libc::c_int> {
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+/// The dpkg terminal log of a commit
+///
+/// apt only writes dpkg output to `Dir::Log::Terminal` when `Dpkg::Use-Pty`
+/// is enabled, otherwise the output captured by [`OmaAptInstallProgress`]
+/// is used.
+pub struct TermLog {
+    path: PathBuf,
+    offset: u64,
+    captured: Arc<Mutex<Vec<u8>>>,
+}
+
+impl TermLog {
+    /// Remember where the log ends now, call this before commit
+    pub(crate) fn new(config: &AptConfig, captured: Arc<Mutex<Vec<u8>>>) -> Self {
+        let path = PathBuf::from(config.file("Dir::Log::Terminal", "/dev/null"));
+        let offset = path.metadata().map(|x| x.len()).unwrap_or(0);
+
+        if let Ok(mut captured) = captured.lock() {
+            captured.clear();
+        }
+
+        Self {
+            path,
+            offset,
+            captured,
+        }
+    }
+
+    /// Read the log of the commit started after [`TermLog::new`]
+    pub fn read(&self) -> Option<String> {
+        if let Some(log) = self
+            .captured
+            .lock()
+            .ok()
+            .filter(|x| !x.is_empty())
+            .map(|x| String::from_utf8_lossy(&x).into_owned())
+        {
+            return Some(log);
+        }
+
+        let mut f = File::open(&self.path).ok()?;
+        let len = f.metadata().ok()?.len();
+
+        // term.log 可能在此期间被 logrotate 轮转
+        let offset = if len < self.offset { 0 } else { self.offset };
+        f.seek(SeekFrom::Start(offset)).ok()?;
+
+        let mut buf = vec![];
+        f.read_to_end(&mut buf).ok()?;
+
+        let log = String::from_utf8_lossy(&buf);
+
+        if log.trim().is_empty() {
+            return None;
+        }
+
+        Some(log.into_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use super::*;
+
+    struct NoPty;
+
+    impl InstallProgressManager for NoPty {
+        fn status_change(&self, _: &str, _: u64, _: u64, _: &AptConfig) {}
+
+        fn no_interactive(&self) -> bool {
+            true
+        }
+
+        fn use_pty(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_term_log_without_pty() {
+        let config = AptConfig::new();
+        config.set("Dir::Log::Terminal", "/dev/null");
+
+        let output = Arc::new(Mutex::new(vec![]));
+        let term_log = TermLog::new(&config, output.clone());
+
+        let args = InstallProgressArgs {
+            config,
+            tokio: tokio::runtime::Builder::new_multi_thread().build().unwrap(),
+            connection: None,
+            output,
+        };
+
+        let progress = OmaAptInstallProgress::new(args, Box::new(NoPty));
+
+        // 模拟 dpkg 子进程继承的输出
+        Command::new("sh")
+            .arg("-c")
+            .arg("echo 'Setting up foo'; echo 'postinst failed' >&2")
+            .status()
+            .unwrap();
+
+        drop(progress);
+
+        let log = term_log.read().unwrap();
+        assert!(log.contains("Setting up foo"));
+        assert!(log.contains("postinst failed"));
+    }
+}
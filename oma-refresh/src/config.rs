@@ -34,6 +34,7 @@ pub struct ChecksumDownloadEntry {
     pub item: ChecksumItem,
     pub keep_compress: bool,
     pub msg: String,
+    pub pdiff_index: Option<ChecksumItem>,
 }
 
 pub struct FilterDownloadList<'a> {
@@ -162,6 +163,7 @@ pub fn fiilter_download_list(f: FilterDownloadList) -> SmallVec<[ChecksumDownloa
                         item: i.clone(),
                         keep_compress: x.1,
                         msg: x.2.clone(),
+                        pdiff_index: None,
                     }
                 }
             } else {
@@ -171,13 +173,20 @@ pub fn fiilter_download_list(f: FilterDownloadList) -> SmallVec<[ChecksumDownloa
                         item: i.clone(),
                         keep_compress: x.1,
                         msg: x.2.clone(),
+                        pdiff_index: None,
                     },
                 );
             }
         }
     }
 
-    for (_, i) in map {
+    for (name, mut i) in map {
+        // 如果 InRelease 中有 pdiff 索引，则记录下来，以便后续尝试增量更新
+        if !i.keep_compress {
+            let index_name = format!("{name}.diff/Index");
+            i.pdiff_index = checksums.iter().find(|x| x.name == index_name).cloned();
+        }
+
         v.push(i);
     }
 
@@ -46,7 +46,7 @@ use oma_utils::dpkg::dpkg_arch;
 #[cfg(feature = "aosc")]
 use reqwest::StatusCode;
 
-use smallvec::SmallVec;
+use smallvec::{smallvec, SmallVec};
 use sysinfo::{Pid, System};
 use tokio::{
     fs::{self},
@@ -61,6 +61,7 @@ use crate::{
         file_is_compress, split_ext_and_filename, verify_inrelease, ChecksumItem, InRelease,
         InReleaseChecksum, InReleaseError,
     },
+    pdiff::{patch_file, PdiffError, PdiffIndex},
     sourceslist::{sources_lists, OmaSourceEntry, OmaSourceEntryFrom},
     util::DatabaseFilenameReplacer,
 };
@@ -167,6 +168,10 @@ pub struct OmaRefresh<'a> {
     topic_msg: &'a str,
     progress_manager: &'a dyn HandleRefresh,
     auth_config: &'a AuthConfig,
+    speed_limit: Option<u64>,
+    /// Enabled mirrors, if one of them is down the files are fetched from the others
+    #[builder(default)]
+    mirrors: &'a [String],
 }
 
 enum RepoType {
@@ -305,6 +310,8 @@ impl<'a> OmaRefresh<'a> {
             .download_list(tasks)
             .progress_manager(progress_manager.as_download_progress_control())
             .set_permission(0o644)
+            .maybe_speed_limit(self.speed_limit)
+            .mirrors(self.mirrors)
             .build()
             .start_download()
             .await;
@@ -315,7 +322,7 @@ impl<'a> OmaRefresh<'a> {
 
         let config_tree = get_config(self.apt_config);
 
-        let (tasks, total) = self
+        let (tasks, total, patched) = self
             .collect_all_release_entry(
                 all_inrelease,
                 &sourcelist,
@@ -329,6 +336,9 @@ impl<'a> OmaRefresh<'a> {
             download_list.push(i.filename.to_string());
         }
 
+        // 通过 pdiff 增量更新的文件也不能被当作无用文件删除
+        download_list.extend(patched);
+
         let download_dir = self.download_dir.clone();
         let remove_task =
             tokio::spawn(async move { remove_unused_db(download_dir, download_list).await });
@@ -339,6 +349,8 @@ impl<'a> OmaRefresh<'a> {
             .threads(self.threads)
             .progress_manager(progress_manager.as_download_progress_control())
             .set_permission(0o644)
+            .maybe_speed_limit(self.speed_limit)
+            .mirrors(self.mirrors)
             .total_size(total)
             .build()
             .start_download()
@@ -678,9 +690,10 @@ impl<'a> OmaRefresh<'a> {
         replacer: &DatabaseFilenameReplacer,
         sources_map: &AHashMap<String, Vec<OmaSourceEntry<'a>>>,
         config_tree: &[(String, String)],
-    ) -> Result<(Vec<DownloadEntry>, u64)> {
+    ) -> Result<(Vec<DownloadEntry>, u64, Vec<String>)> {
         let mut total = 0;
         let mut tasks = vec![];
+        let mut patched = vec![];
         for inrelease_summary in all_inrelease {
             // 源数据确保是存在的，所以直接 unwrap
             let ose_list = sources_map.get(&inrelease_summary.filename).unwrap();
@@ -752,6 +765,13 @@ impl<'a> OmaRefresh<'a> {
 
                 let filter_checksums = fiilter_download_list(f);
 
+                let filter_checksums = if self.apt_config.bool("Acquire::PDiffs", true) {
+                    self.apply_pdiffs(filter_checksums, ose, &inrelease, replacer, &mut patched)
+                        .await?
+                } else {
+                    filter_checksums
+                };
+
                 get_all_need_db_from_config(filter_checksums, &mut total, checksums, &mut handle);
 
                 for i in &self.flat_repo_no_release {
@@ -776,7 +796,304 @@ impl<'a> OmaRefresh<'a> {
             }
         }
 
-        Ok((tasks, total))
+        Ok((tasks, total, patched))
+    }
+
+    /// Try to update local database files with pdiff, return the entries that still need to be downloaded
+    ///
+    /// The diff indexes and patches of all lists in the repository are fetched in one batch,
+    /// lists which already match the InRelease are left alone.
+    async fn apply_pdiffs(
+        &self,
+        entries: SmallVec<[ChecksumDownloadEntry; 32]>,
+        source_index: &OmaSourceEntry<'_>,
+        inrelease: &InRelease<'_>,
+        replacer: &DatabaseFilenameReplacer,
+        patched: &mut Vec<String>,
+    ) -> Result<SmallVec<[ChecksumDownloadEntry; 32]>> {
+        if !matches!(source_index.from()?, OmaSourceEntryFrom::Http) {
+            return Ok(entries);
+        }
+
+        let (checksum_type, checksums) = inrelease.checksum_type_and_list();
+        let dist_url = source_index.dist_path().trim_end_matches('/');
+
+        let mut res = smallvec![];
+        let mut pending = vec![];
+
+        for c in entries {
+            let Some(index_item) = &c.pdiff_index else {
+                res.push(c);
+                continue;
+            };
+
+            let target = if file_is_compress(&c.item.name) {
+                split_ext_and_filename(&c.item.name).1
+            } else {
+                c.item.name.clone()
+            };
+
+            let filename = replacer.replace(&format!("{}/{}", dist_url, target))?;
+            let local = self.download_dir.join(&filename);
+
+            // 本地没有旧的数据库文件，没法打补丁
+            if !local.is_file() {
+                res.push(c);
+                continue;
+            }
+
+            // 本地文件已是最新，无需请求 diff 索引
+            let up_to_date = {
+                let expected = checksums
+                    .iter()
+                    .find(|x| x.name == target)
+                    .and_then(|x| to_checksum(checksum_type, &x.checksum).ok());
+                let local = local.clone();
+
+                spawn_blocking(move || {
+                    expected.is_some_and(|x| x.cmp_file(&local).unwrap_or(false))
+                })
+                .await
+                .unwrap()
+            };
+
+            if up_to_date {
+                debug!("{filename} is already up to date");
+                res.push(c);
+                continue;
+            }
+
+            let index_hash = match to_checksum(checksum_type, &index_item.checksum) {
+                Ok(hash) => hash,
+                Err(e) => {
+                    debug!("Bad pdiff index checksum for {filename}: {e}");
+                    res.push(c);
+                    continue;
+                }
+            };
+
+            let msg = source_index.get_human_download_url(Some(&format!("{} (diff)", c.msg)))?;
+
+            pending.push(PendingPdiff {
+                index_url: format!("{}/{}", dist_url, index_item.name),
+                index_hash,
+                entry: c,
+                target,
+                filename,
+                msg,
+                patches: vec![],
+                current: None,
+            });
+        }
+
+        if pending.is_empty() {
+            return Ok(res);
+        }
+
+        // 不使用 apt 的 partial 目录，以免与同时运行的 apt 冲突
+        let tempdir = tempfile::Builder::new()
+            .prefix("oma-pdiff")
+            .tempdir()
+            .map_err(|e| RefreshError::FailedToOperateDirOrFile("pdiff".to_string(), e))?;
+        let partial_dir = tempdir.path();
+
+        let index_tasks = pending
+            .iter()
+            .map(|p| {
+                DownloadEntry::builder()
+                    .source(pdiff_source(source_index, p.index_url.clone()))
+                    .filename(format!("{}.diff_Index", p.filename))
+                    .dir(partial_dir.to_path_buf())
+                    .allow_resume(false)
+                    .msg(p.msg.clone())
+                    .hash(p.index_hash.clone())
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        let fetched = self.download_pdiff_files(index_tasks).await;
+
+        let mut patch_tasks = vec![];
+        let mut ready = vec![];
+
+        for mut p in pending {
+            let index_filename = format!("{}.diff_Index", p.filename);
+
+            if !fetched.contains(&index_filename) {
+                debug!("Failed to fetch pdiff index for {}", p.filename);
+                res.push(p.entry);
+                continue;
+            }
+
+            match self
+                .pdiff_patch_tasks(&mut p, source_index, dist_url, partial_dir)
+                .await
+            {
+                Ok(tasks) if !tasks.is_empty() => {
+                    patch_tasks.extend(tasks);
+                    ready.push(p);
+                }
+                Ok(_) => {
+                    // InRelease 与 diff 索引不一致，重新下载完整的文件
+                    debug!("pdiff index of {} has nothing to apply", p.filename);
+                    res.push(p.entry);
+                }
+                Err(e) => {
+                    debug!("Failed to read pdiff index for {}: {e}", p.filename);
+                    res.push(p.entry);
+                }
+            }
+        }
+
+        let fetched = self.download_pdiff_files(patch_tasks).await;
+
+        for p in ready {
+            let all_fetched = p.patches.iter().all(|x| {
+                x.file_name()
+                    .is_some_and(|x| fetched.contains(&*x.to_string_lossy()))
+            });
+
+            let result = if all_fetched {
+                self.patch_local(&p).await
+            } else {
+                Err(PdiffError::MissingPatch(p.filename.clone()))
+            };
+
+            match result {
+                Ok(()) => {
+                    debug!("{} is updated by pdiff", p.filename);
+                    patched.push(p.filename);
+                }
+                Err(e) => {
+                    debug!(
+                        "Failed to apply pdiff for {}: {e}, fallback to full download",
+                        p.filename
+                    );
+                    res.push(p.entry);
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Read the fetched diff index of a list and return the patches it needs
+    async fn pdiff_patch_tasks(
+        &self,
+        p: &mut PendingPdiff,
+        source_index: &OmaSourceEntry<'_>,
+        dist_url: &str,
+        partial_dir: &Path,
+    ) -> std::result::Result<Vec<DownloadEntry>, PdiffError> {
+        let index_path = partial_dir.join(format!("{}.diff_Index", p.filename));
+        let index = fs::read_to_string(&index_path).await.map_err(|e| {
+            PdiffError::FailedToOperateDirOrFile(index_path.display().to_string(), e)
+        })?;
+
+        let index = PdiffIndex::new(&index)?;
+
+        let local_hash = {
+            let local = self.download_dir.join(&p.filename);
+            spawn_blocking(move || Checksum::from_file_sha256(&local))
+                .await
+                .unwrap()?
+        };
+
+        let mut tasks = vec![];
+
+        for patch in index.patches_for(&local_hash)? {
+            let patch_filename = format!("{}.diff_{}", p.filename, patch.name);
+
+            let task = DownloadEntry::builder()
+                .source(pdiff_source(
+                    source_index,
+                    format!("{}/{}.diff/{}.gz", dist_url, p.target, patch.name),
+                ))
+                .filename(patch_filename.clone())
+                .dir(partial_dir.to_path_buf())
+                .allow_resume(false)
+                .msg(p.msg.clone())
+                .file_type(CompressFile::Gzip)
+                .hash(Checksum::from_sha256_str(&patch.checksum)?)
+                .build();
+
+            tasks.push(task);
+            p.patches.push(partial_dir.join(patch_filename));
+        }
+
+        p.current = Some(index.current_checksum()?);
+
+        Ok(tasks)
+    }
+
+    async fn patch_local(&self, p: &PendingPdiff) -> std::result::Result<(), PdiffError> {
+        let local = self.download_dir.join(&p.filename);
+        let patches = p.patches.clone();
+        let current = p.current.clone().ok_or(PdiffError::BrokenIndex)?;
+
+        spawn_blocking(move || patch_file(&local, &patches, &current))
+            .await
+            .unwrap()
+    }
+
+    /// Download pdiff files in one batch, return the file names which are fetched
+    async fn download_pdiff_files(&self, tasks: Vec<DownloadEntry>) -> HashSet<String> {
+        if tasks.is_empty() {
+            return HashSet::default();
+        }
+
+        DownloadManager::builder()
+            .client(self.client)
+            .download_list(tasks)
+            .threads(self.threads)
+            .progress_manager(self.progress_manager.as_download_progress_control())
+            .maybe_speed_limit(self.speed_limit)
+            .mirrors(self.mirrors)
+            .build()
+            .start_download()
+            .await
+            .into_iter()
+            .filter_map(|res| {
+                res.inspect_err(|e| debug!("Failed to download pdiff file: {e}"))
+                    .ok()
+            })
+            .map(|summary| summary.filename)
+            .collect()
+    }
+}
+
+/// A list which is updated with pdiff
+struct PendingPdiff {
+    entry: ChecksumDownloadEntry,
+    target: String,
+    filename: String,
+    msg: String,
+    index_url: String,
+    index_hash: Checksum,
+    patches: Vec<PathBuf>,
+    current: Option<Checksum>,
+}
+
+fn pdiff_source(source_index: &OmaSourceEntry<'_>, url: String) -> Vec<DownloadSource> {
+    vec![DownloadSource {
+        url,
+        source_type: DownloadSourceType::Http {
+            auth: source_index
+                .auth
+                .as_ref()
+                .map(|auth| (auth.user.clone(), auth.password.clone())),
+        },
+    }]
+}
+
+fn to_checksum(
+    checksum_type: &InReleaseChecksum,
+    hex: &str,
+) -> std::result::Result<Checksum, ChecksumError> {
+    match checksum_type {
+        InReleaseChecksum::Sha256 => Checksum::from_sha256_str(hex),
+        InReleaseChecksum::Sha512 => Checksum::from_sha512_str(hex),
+        InReleaseChecksum::Md5 => Checksum::from_md5_str(hex),
     }
 }
 
@@ -1,5 +1,8 @@
 mod config;
 pub mod db;
 pub mod inrelease;
+mod pdiff;
 mod sourceslist;
 mod util;
+
+pub use config::get_config;
@@ -0,0 +1,327 @@
+use std::{
+    fs::{self, Permissions},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use oma_fetch::checksum::{Checksum, ChecksumError};
+use tracing::debug;
+
+use crate::inrelease::ChecksumItem;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PdiffError {
+    #[error("Bad pdiff Index file")]
+    BrokenIndex,
+    #[error("Local file is not in pdiff history")]
+    NotInHistory,
+    #[error("Missing patch: {0}")]
+    MissingPatch(String),
+    #[error("Bad ed command: {0}")]
+    BadCommand(String),
+    #[error("ed command line {0} out of range")]
+    OutOfRange(usize),
+    #[error("Patched file checksum mismatch")]
+    ChecksumMismatch,
+    #[error(transparent)]
+    ChecksumError(#[from] ChecksumError),
+    #[error(transparent)]
+    FetcherError(#[from] oma_fetch::DownloadError),
+    #[error("Failed to operate dir or file {0}: {1}")]
+    FailedToOperateDirOrFile(String, std::io::Error),
+}
+
+/// `Packages.diff/Index` (a.k.a pdiff index)
+#[derive(Debug)]
+pub struct PdiffIndex {
+    current: (String, u64),
+    history: Vec<ChecksumItem>,
+    patches: Vec<ChecksumItem>,
+    merged: bool,
+}
+
+impl PdiffIndex {
+    pub fn new(input: &str) -> Result<Self, PdiffError> {
+        let debcontrol = oma_debcontrol::parse_str(input).map_err(|_| PdiffError::BrokenIndex)?;
+        let index = debcontrol.first().ok_or(PdiffError::BrokenIndex)?;
+
+        let mut current = None;
+        let mut history = vec![];
+        let mut patches = vec![];
+        let mut merged = false;
+
+        for i in &index.fields {
+            match i.name {
+                "SHA256-Current" => {
+                    let (checksum, size) = i
+                        .value
+                        .trim()
+                        .split_once(|c: char| c.is_ascii_whitespace())
+                        .ok_or(PdiffError::BrokenIndex)?;
+
+                    let size = size
+                        .trim()
+                        .parse::<u64>()
+                        .map_err(|_| PdiffError::BrokenIndex)?;
+
+                    current = Some((checksum.to_string(), size));
+                }
+                "SHA256-History" => history = parse_checksum_lines(&i.value)?,
+                "SHA256-Patches" => patches = parse_checksum_lines(&i.value)?,
+                "X-Patch-Precedence" => merged = i.value.trim() == "merged",
+                _ => continue,
+            }
+        }
+
+        Ok(Self {
+            current: current.ok_or(PdiffError::BrokenIndex)?,
+            history,
+            patches,
+            merged,
+        })
+    }
+
+    pub fn current_checksum(&self) -> Result<Checksum, PdiffError> {
+        Ok(Checksum::from_sha256_str(&self.current.0)?)
+    }
+
+    /// Get the patches (name and uncompressed checksum) needed to turn the local file into the current one
+    pub fn patches_for(&self, local: &Checksum) -> Result<Vec<&ChecksumItem>, PdiffError> {
+        if &self.current_checksum()? == local {
+            return Ok(vec![]);
+        }
+
+        let pos = self
+            .history
+            .iter()
+            .position(|x| Checksum::from_sha256_str(&x.checksum).is_ok_and(|x| &x == local))
+            .ok_or(PdiffError::NotInHistory)?;
+
+        // merged 模式下，每个 patch 都可以直接从对应的历史版本更新到最新
+        let need = if self.merged {
+            &self.history[pos..=pos]
+        } else {
+            &self.history[pos..]
+        };
+
+        need.iter()
+            .map(|h| {
+                self.patches
+                    .iter()
+                    .find(|p| p.name == h.name)
+                    .ok_or_else(|| PdiffError::MissingPatch(h.name.clone()))
+            })
+            .collect()
+    }
+}
+
+/// Apply downloaded patches to the local file in order, and verify the result
+pub fn patch_file(path: &Path, patches: &[PathBuf], current: &Checksum) -> Result<(), PdiffError> {
+    let content = fs::read(path)
+        .map_err(|e| PdiffError::FailedToOperateDirOrFile(path.display().to_string(), e))?;
+
+    let patches = patches
+        .iter()
+        .map(|p| {
+            fs::read(p)
+                .map_err(|e| PdiffError::FailedToOperateDirOrFile(p.display().to_string(), e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut lines = split_lines(&content);
+
+    for p in &patches {
+        apply_ed_patch(&mut lines, p)?;
+    }
+
+    let res = join_lines(&lines);
+
+    let mut validator = current.get_validator();
+    validator.update(&res);
+
+    if !validator.finish() {
+        return Err(PdiffError::ChecksumMismatch);
+    }
+
+    // 先写入临时文件再替换，避免中途失败留下损坏的数据库文件
+    let tmp = path.with_extension("pdiff");
+
+    fs::write(&tmp, &res)
+        .and_then(|_| fs::set_permissions(&tmp, Permissions::from_mode(0o644)))
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| PdiffError::FailedToOperateDirOrFile(tmp.display().to_string(), e))?;
+
+    Ok(())
+}
+
+fn parse_checksum_lines(s: &str) -> Result<Vec<ChecksumItem>, PdiffError> {
+    s.trim()
+        .lines()
+        .map(ChecksumItem::from_str)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| PdiffError::BrokenIndex)
+}
+
+/// Apply ed-style patch (`diff --ed` output) to lines
+pub fn apply_ed_patch<'a>(lines: &mut Vec<&'a [u8]>, patch: &'a [u8]) -> Result<(), PdiffError> {
+    let mut patch_lines = split_lines(patch).into_iter();
+    let mut current: usize = 0;
+
+    while let Some(cmd) = patch_lines.next() {
+        let cmd_str = String::from_utf8_lossy(cmd);
+        debug!("pdiff ed command: {cmd_str}");
+
+        if cmd == b"s/.//" {
+            let line = current
+                .checked_sub(1)
+                .and_then(|x| lines.get_mut(x))
+                .ok_or(PdiffError::OutOfRange(current))?;
+
+            if !line.is_empty() {
+                *line = &line[1..];
+            }
+
+            continue;
+        }
+
+        if cmd == b"w" || cmd == b"q" {
+            continue;
+        }
+
+        let (&op, addr) = cmd
+            .split_last()
+            .ok_or_else(|| PdiffError::BadCommand(cmd_str.to_string()))?;
+
+        let (start, end) = if addr.is_empty() {
+            (current, current)
+        } else {
+            parse_range(addr).ok_or_else(|| PdiffError::BadCommand(cmd_str.to_string()))?
+        };
+
+        if start > end || end > lines.len() {
+            return Err(PdiffError::OutOfRange(end));
+        }
+
+        match op {
+            b'a' => {
+                let insert = read_insert_lines(&mut patch_lines);
+                let count = insert.len();
+                lines.splice(end..end, insert);
+                current = end + count;
+            }
+            b'c' => {
+                let insert = read_insert_lines(&mut patch_lines);
+                let count = insert.len();
+                let start = start.checked_sub(1).ok_or(PdiffError::OutOfRange(start))?;
+                lines.splice(start..end, insert);
+                current = start + count;
+            }
+            b'd' => {
+                let start = start.checked_sub(1).ok_or(PdiffError::OutOfRange(start))?;
+                lines.drain(start..end);
+                current = (start + 1).min(lines.len());
+            }
+            _ => return Err(PdiffError::BadCommand(cmd_str.to_string())),
+        }
+    }
+
+    Ok(())
+}
+
+fn read_insert_lines<'a>(patch_lines: &mut impl Iterator<Item = &'a [u8]>) -> Vec<&'a [u8]> {
+    patch_lines.take_while(|x| *x != b".").collect()
+}
+
+fn parse_range(addr: &[u8]) -> Option<(usize, usize)> {
+    let addr = std::str::from_utf8(addr).ok()?;
+
+    match addr.split_once(',') {
+        Some((start, end)) => Some((start.parse().ok()?, end.parse().ok()?)),
+        None => {
+            let n = addr.parse().ok()?;
+            Some((n, n))
+        }
+    }
+}
+
+pub fn split_lines(input: &[u8]) -> Vec<&[u8]> {
+    let input = input.strip_suffix(b"\n").unwrap_or(input);
+
+    if input.is_empty() {
+        return vec![];
+    }
+
+    input.split(|x| *x == b'\n').collect()
+}
+
+pub fn join_lines(lines: &[&[u8]]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(lines.iter().map(|x| x.len() + 1).sum());
+
+    for i in lines {
+        res.extend_from_slice(i);
+        res.push(b'\n');
+    }
+
+    res
+}
+
+#[test]
+fn test_apply_ed_patch() {
+    let file = b"Package: a\nVersion: 1\n\nPackage: b\nVersion: 1\n\nPackage: c\nVersion: 1\n";
+    let patch = b"7,8c\nPackage: c\nVersion: 2\n.\n4,6d\n2a\nArchitecture: all\n.\n";
+
+    let mut lines = split_lines(file);
+    apply_ed_patch(&mut lines, patch).unwrap();
+
+    assert_eq!(
+        join_lines(&lines),
+        b"Package: a\nVersion: 1\nArchitecture: all\n\nPackage: c\nVersion: 2\n"
+    );
+}
+
+#[test]
+fn test_apply_ed_patch_dot_line() {
+    let file = b"a\nb\n";
+    let patch = b"1a\n..\n.\ns/.//\na\nc\n.\n";
+
+    let mut lines = split_lines(file);
+    apply_ed_patch(&mut lines, patch).unwrap();
+
+    assert_eq!(join_lines(&lines), b"a\n.\nc\nb\n");
+}
+
+#[test]
+fn test_pdiff_index() {
+    let index = r#"SHA256-Current: 6e8f6b1d2fb9e9fd1bb7c1cb53a7f2c9ab0c0ad9c7e4d7ab3fd2c4f7fbbd2e71 100
+SHA256-History:
+ 1111111111111111111111111111111111111111111111111111111111111111 90 2024-11-01-0814.12
+ 2222222222222222222222222222222222222222222222222222222222222222 95 2024-11-01-1414.12
+SHA256-Patches:
+ 3333333333333333333333333333333333333333333333333333333333333333 10 2024-11-01-0814.12
+ 4444444444444444444444444444444444444444444444444444444444444444 12 2024-11-01-1414.12
+SHA256-Download:
+ 5555555555555555555555555555555555555555555555555555555555555555 8 2024-11-01-0814.12.gz
+ 6666666666666666666666666666666666666666666666666666666666666666 9 2024-11-01-1414.12.gz
+"#;
+
+    let index = PdiffIndex::new(index).unwrap();
+    assert_eq!(index.current.1, 100);
+
+    let local = Checksum::from_sha256_str(
+        "1111111111111111111111111111111111111111111111111111111111111111",
+    )
+    .unwrap();
+    let patches = index.patches_for(&local).unwrap();
+
+    assert_eq!(
+        patches.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+        vec!["2024-11-01-0814.12", "2024-11-01-1414.12"]
+    );
+
+    let local = Checksum::from_sha256_str(
+        "7777777777777777777777777777777777777777777777777777777777777777",
+    )
+    .unwrap();
+    assert!(index.patches_for(&local).is_err());
+}
@@ -2,11 +2,15 @@ use std::{
     borrow::Cow,
     io,
     path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use oma_mirror::MirrorManager;
-use reqwest::Client;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use reqwest::{
+    header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Client, StatusCode,
+};
+use serde::{Deserialize, Serialize};
 use tokio::fs;
 use tracing::{debug, warn};
 use url::Url;
@@ -67,6 +71,22 @@ pub struct Topic {
     pub draft: Option<bool>,
 }
 
+/// Cached topic manifests, revalidated with ETag/Last-Modified on refresh
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct ManifestCache {
+    /// Unix timestamp of the last successful refresh
+    updated: u64,
+    mirrors: Vec<CachedManifest>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct CachedManifest {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    topics: serde_json::Value,
+}
+
 impl PartialEq for Topic {
     fn eq(&self, other: &Self) -> bool {
         self.name == other.name
@@ -81,6 +101,9 @@ pub struct TopicManager<'a> {
     arch: &'a str,
     atm_state_path: PathBuf,
     atm_source_list_path: PathBuf,
+    manifest_cache_path: PathBuf,
+    closed: Vec<Topic>,
+    closed_path: PathBuf,
     dry_run: bool,
     enabled_mirrors: Vec<Box<str>>,
 }
@@ -88,6 +111,8 @@ pub struct TopicManager<'a> {
 impl<'a> TopicManager<'a> {
     const ATM_STATE_PATH_SUFFIX: &'a str = "var/lib/atm/state";
     const ATM_SOURCE_LIST_PATH_SUFFIX: &'a str = "etc/apt/sources.list.d/atm.list";
+    const ATM_MANIFEST_CACHE_PATH_SUFFIX: &'a str = "var/lib/atm/manifest.json";
+    const ATM_CLOSED_PATH_SUFFIX: &'a str = "var/lib/atm/closed";
 
     pub async fn new(
         client: &'a Client,
@@ -133,15 +158,29 @@ impl<'a> TopicManager<'a> {
             }
         };
 
+        let closed_path = sysroot.as_ref().join(Self::ATM_CLOSED_PATH_SUFFIX);
+
+        // 只记录待处理的已关闭 topic，文件损坏时直接忽略
+        let closed = match fs::read(&closed_path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                debug!("Closed topics file is corrupted: {e}");
+                vec![]
+            }),
+            Err(_) => vec![],
+        };
+
         Ok(Self {
             enabled,
             all: vec![],
+            closed,
+            closed_path,
             client,
             arch,
             atm_state_path,
             dry_run,
             enabled_mirrors: enabled_mirror(sysroot.as_ref().to_path_buf()).await?,
             atm_source_list_path: sysroot.as_ref().join(Self::ATM_SOURCE_LIST_PATH_SUFFIX),
+            manifest_cache_path: sysroot.as_ref().join(Self::ATM_MANIFEST_CACHE_PATH_SUFFIX),
         })
     }
 
@@ -153,6 +192,34 @@ impl<'a> TopicManager<'a> {
         &self.enabled
     }
 
+    pub fn enabled_mirrors(&self) -> &[Box<str>] {
+        &self.enabled_mirrors
+    }
+
+    /// Topics closed upstream and disabled by [`scan_closed_topic`], but not yet handled
+    pub fn closed_topics(&self) -> &[Topic] {
+        &self.closed
+    }
+
+    /// Mark all closed topics as handled
+    pub async fn clear_closed(&mut self) -> Result<()> {
+        self.closed.clear();
+        self.write_closed().await
+    }
+
+    async fn write_closed(&self) -> Result<()> {
+        if self.dry_run {
+            debug!("closed topics: {:?}", self.closed);
+            return Ok(());
+        }
+
+        let s = serde_json::to_vec(&self.closed).map_err(|_| OmaTopicsError::FailedSer)?;
+
+        fs::write(&self.closed_path, s).await.map_err(|e| {
+            OmaTopicsError::FailedToOperateDirOrFile(self.closed_path.display().to_string(), e)
+        })
+    }
+
     /// Get all new topics
     pub async fn refresh(&mut self) -> Result<()> {
         let urls = self
@@ -167,11 +234,72 @@ impl<'a> TopicManager<'a> {
             })
             .collect::<Vec<_>>();
 
-        self.all = refresh_innter(self.client, urls, self.arch).await?;
+        let cache = self.read_cache().await;
+
+        let mut tasks = vec![];
+        for url in urls {
+            let cached = cache
+                .as_ref()
+                .and_then(|x| x.mirrors.iter().find(|x| x.url == url));
+            tasks.push(fetch_manifest(self.client, url, cached));
+        }
+
+        let mirrors = futures::future::try_join_all(tasks).await?;
+
+        self.all = merge_topics(&mirrors, self.arch)?;
+
+        let cache = ManifestCache {
+            updated: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_secs())
+                .unwrap_or_default(),
+            mirrors,
+        };
+
+        if !self.dry_run {
+            // 缓存只用于离线查看，写入失败不影响刷新结果
+            if let Err(e) = self.write_cache(&cache).await {
+                debug!("Failed to write topic manifest cache: {e}");
+            }
+        }
 
         Ok(())
     }
 
+    /// Load topics from the manifest cache, return the Unix timestamp of the cached data
+    pub async fn refresh_from_cache(&mut self) -> Result<Option<u64>> {
+        let Some(cache) = self.read_cache().await else {
+            return Ok(None);
+        };
+
+        self.all = merge_topics(&cache.mirrors, self.arch)?;
+
+        Ok(Some(cache.updated))
+    }
+
+    async fn read_cache(&self) -> Option<ManifestCache> {
+        let bytes = fs::read(&self.manifest_cache_path).await.ok()?;
+
+        match serde_json::from_slice(&bytes) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                debug!("Topic manifest cache is corrupted: {e}");
+                None
+            }
+        }
+    }
+
+    async fn write_cache(&self, cache: &ManifestCache) -> Result<()> {
+        let s = serde_json::to_vec(cache).map_err(|_| OmaTopicsError::FailedSer)?;
+
+        fs::write(&self.manifest_cache_path, s).await.map_err(|e| {
+            OmaTopicsError::FailedToOperateDirOrFile(
+                self.manifest_cache_path.display().to_string(),
+                e,
+            )
+        })
+    }
+
     /// Enable select topic
     pub fn add(&mut self, topic: &str) -> Result<()> {
         debug!("oma will opt_in: {}", topic);
@@ -311,34 +439,76 @@ async fn create_empty_state(atm_state_path: &Path) -> Result<Vec<Topic>> {
     Ok(v)
 }
 
-async fn get<T: DeserializeOwned>(client: &Client, url: String) -> Result<T> {
-    let url = Url::parse(&url).map_err(OmaTopicsError::ParseUrl)?;
+async fn fetch_manifest(
+    client: &Client,
+    url: String,
+    cached: Option<&CachedManifest>,
+) -> Result<CachedManifest> {
+    let parsed = Url::parse(&url).map_err(OmaTopicsError::ParseUrl)?;
 
-    let schema = url.scheme();
+    let schema = parsed.scheme();
 
     match schema {
         "file" => {
-            let path = url.path();
+            let path = parsed.path();
             let bytes = fs::read(path)
                 .await
                 .map_err(|e| OmaTopicsError::OpenFile(path.to_string(), e))?;
-            serde_json::from_slice(&bytes)
-                .map_err(|e| OmaTopicsError::ReadFile(path.to_string(), e))
+            let topics = serde_json::from_slice(&bytes)
+                .map_err(|e| OmaTopicsError::ReadFile(path.to_string(), e))?;
+
+            Ok(CachedManifest {
+                url,
+                etag: None,
+                last_modified: None,
+                topics,
+            })
         }
         x if x.starts_with("http") => {
-            let res = client
-                .get(url)
-                .send()
-                .await?
-                .error_for_status()?
-                .json::<T>()
-                .await?;
-            Ok(res)
+            let mut req = client.get(parsed);
+
+            if let Some(cached) = cached {
+                if let Some(etag) = &cached.etag {
+                    req = req.header(IF_NONE_MATCH, etag);
+                }
+
+                if let Some(last_modified) = &cached.last_modified {
+                    req = req.header(IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+
+            let resp = req.send().await?.error_for_status()?;
+
+            if resp.status() == StatusCode::NOT_MODIFIED {
+                if let Some(cached) = cached {
+                    debug!("{url} is not modified, using cache");
+                    return Ok(cached.clone());
+                }
+            }
+
+            let headers = resp.headers();
+            let etag = header_str(headers, ETAG);
+            let last_modified = header_str(headers, LAST_MODIFIED);
+            let topics = resp.json::<serde_json::Value>().await?;
+
+            Ok(CachedManifest {
+                url,
+                etag,
+                last_modified,
+                topics,
+            })
         }
-        _ => Err(OmaTopicsError::UnsupportedProtocol(url.to_string())),
+        _ => Err(OmaTopicsError::UnsupportedProtocol(url)),
     }
 }
 
+fn header_str(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|x| x.to_str().ok())
+        .map(|x| x.to_string())
+}
+
 async fn check(client: &Client, url: &str) -> Result<bool> {
     let url = Url::parse(url).map_err(OmaTopicsError::ParseUrl)?;
 
@@ -359,17 +529,16 @@ fn url_with_suffix(url: &str) -> Cow<str> {
     }
 }
 
-async fn refresh_innter(client: &Client, urls: Vec<String>, arch: &str) -> Result<Vec<Topic>> {
+fn merge_topics(mirrors: &[CachedManifest], arch: &str) -> Result<Vec<Topic>> {
     let mut json: Vec<Topic> = vec![];
-    let mut tasks = vec![];
+    let mut res = vec![];
 
-    for url in urls {
-        let v = get::<Vec<Topic>>(client, url);
-        tasks.push(v);
+    for i in mirrors {
+        let topics: Vec<Topic> = serde_json::from_value(i.topics.clone())
+            .map_err(|e| OmaTopicsError::ReadFile(i.url.clone(), e))?;
+        res.push(topics);
     }
 
-    let res = futures::future::try_join_all(tasks).await?;
-
     for i in res {
         for j in i {
             match json.iter().position(|x| x.name == j.name) {
@@ -401,6 +570,8 @@ async fn refresh_innter(client: &Client, urls: Vec<String>, arch: &str) -> Resul
 }
 
 /// Scan all close topics from upstream and disable it
+///
+/// The closed topics are kept in [`TopicManager::closed_topics`] until they are handled.
 pub async fn scan_closed_topic(
     tm: &mut TopicManager<'_>,
     comment: &str,
@@ -415,12 +586,17 @@ pub async fn scan_closed_topic(
     for i in enabled {
         if all.iter().all(|x| x.name != i.name) {
             let d = tm.remove(&i.name)?;
-            res.push(d.name);
+            res.push(d.name.clone());
+
+            if !tm.closed.contains(&d) {
+                tm.closed.push(d);
+            }
         }
     }
 
     if !res.is_empty() {
         tm.write_enabled(comment, message_cb).await?;
+        tm.write_closed().await?;
     }
 
     Ok(res)
@@ -2,8 +2,14 @@ use logind_zbus::{
     manager::{InhibitType, ManagerProxy},
     session::SessionProxy,
 };
+use std::collections::HashMap;
+
 use tracing::debug;
-use zbus::{proxy, zvariant::OwnedFd, Result as zResult};
+use zbus::{
+    proxy,
+    zvariant::{OwnedFd, Value},
+    Result as zResult,
+};
 
 pub use zbus::Connection;
 
@@ -21,6 +27,8 @@ pub enum OmaDbusError {
     FailedGetOmaStatus(zbus::Error),
     #[error("Failed to get session state")]
     SessionState(zbus::Error),
+    #[error("Failed to check authorization")]
+    FailedCheckAuthorization(zbus::Error),
 }
 
 pub type OmaDbusResult<T> = Result<T, OmaDbusError>;
@@ -36,6 +44,23 @@ trait UPower {
     fn on_battery(&self) -> zResult<bool>;
 }
 
+#[proxy(
+    interface = "org.freedesktop.PolicyKit1.Authority",
+    default_service = "org.freedesktop.PolicyKit1",
+    default_path = "/org/freedesktop/PolicyKit1/Authority"
+)]
+trait PolkitAuthority {
+    /// CheckAuthorization method
+    fn check_authorization(
+        &self,
+        subject: &(&str, HashMap<&str, Value<'_>>),
+        action_id: &str,
+        details: HashMap<&str, &str>,
+        flags: u32,
+        cancellation_id: &str,
+    ) -> zResult<(bool, bool, HashMap<String, String>)>;
+}
+
 #[proxy(
     interface = "io.aosc.Oma1",
     default_service = "io.aosc.Oma",
@@ -119,3 +144,39 @@ pub async fn is_using_battery(conn: &Connection) -> OmaDbusResult<bool> {
         .await
         .map_err(OmaDbusError::FailedGetBatteryStatus)
 }
+
+/// Ask polkit whether the D-Bus client `sender` (unique bus name) is allowed to do `action_id`
+///
+/// polkit may ask the user to authenticate.
+pub async fn check_authorization(
+    conn: &Connection,
+    sender: &str,
+    action_id: &str,
+) -> OmaDbusResult<bool> {
+    // AllowUserInteraction
+    const ALLOW_USER_INTERACTION: u32 = 1;
+
+    let proxy = PolkitAuthorityProxy::new(conn)
+        .await
+        .map_err(|e| OmaDbusError::FailedCreateProxy("polkit", e))?;
+
+    let subject = (
+        "system-bus-name",
+        HashMap::from([("name", Value::from(sender))]),
+    );
+
+    let (authorized, _, _) = proxy
+        .check_authorization(
+            &subject,
+            action_id,
+            HashMap::new(),
+            ALLOW_USER_INTERACTION,
+            "",
+        )
+        .await
+        .map_err(OmaDbusError::FailedCheckAuthorization)?;
+
+    debug!("{sender} authorized for {action_id}: {authorized}");
+
+    Ok(authorized)
+}
@@ -59,6 +59,11 @@ pub fn command_builder() -> Command {
         .action(ArgAction::SetTrue)
         .help("Set output format as JSON");
 
+    let transaction_json = Arg::new("json")
+        .long("json")
+        .action(ArgAction::SetTrue)
+        .help("Print the pending operation, progress and result as JSON lines instead of asking for confirmation");
+
     let remove_config = Arg::new("remove_config")
         .long("remove-config")
         .visible_alias("purge")
@@ -104,7 +109,35 @@ pub fn command_builder() -> Command {
                 .action(ArgAction::Count)
                 .hide(true)
         )
+        .arg(
+            Arg::new("progress_fd")
+                .long("progress-fd")
+                .help("Write progress events as JSON lines to file descriptor")
+                .long_help("Write download, refresh and dpkg progress events as JSON lines to the given file descriptor instead of showing progress bars, for GUI frontends")
+                .value_parser(clap::value_parser!(i32))
+                .action(ArgAction::Set)
+                .global(true)
+                .conflicts_with("progress_socket")
+        )
+        .arg(
+            Arg::new("progress_socket")
+                .long("progress-socket")
+                .help("Write progress events as JSON lines to Unix socket")
+                .long_help("Connect to the given Unix socket and write download, refresh and dpkg progress events as JSON lines to it instead of showing progress bars, for GUI frontends")
+                .action(ArgAction::Set)
+                .global(true)
+        )
         .arg(Arg::new("no_check_dbus").long("no-check-dbus").long_help("Run oma do not check dbus").action(ArgAction::SetTrue).global(true))
+        .arg(
+            Arg::new("speed_limit")
+                .long("speed-limit")
+                .help("Limit download speed (bytes/sec)")
+                .long_help("Limit total download speed of metadata and packages (bytes/sec), 0 means unlimited. Overrides speed_limit in /etc/oma.toml")
+                .value_parser(clap::value_parser!(u64))
+                .action(ArgAction::Set)
+                .global(true)
+                .num_args(1)
+        )
         .arg(
             Arg::new("version")
                 .long("version")
@@ -175,7 +208,8 @@ pub fn command_builder() -> Command {
                 .arg(force_yes.clone().requires("packages"))
                 .arg(force_confnew.clone().requires("packages"))
                 .arg(&remove_config)
-                .arg(&dry_run);
+                .arg(&dry_run)
+                .arg(&transaction_json);
 
             if cfg!(feature = "aosc") {
                 cmd = cmd.arg(&no_refresh_topics);
@@ -194,8 +228,10 @@ pub fn command_builder() -> Command {
                 .arg(&force_yes)
                 .arg(force_confnew)
                 .arg(&dry_run)
+                .arg(&transaction_json)
                 .arg(Arg::new("autoremove").long("autoremove").help("Auto remove unnecessary package(s)").action(ArgAction::SetTrue))
-                .arg(&remove_config);
+                .arg(&remove_config)
+                .arg(Arg::new("offline").long("offline").help("Download package(s) only, then install them at next boot").action(ArgAction::SetTrue));
             if cfg!(feature = "aosc") {
                 cmd = cmd.arg(&no_refresh_topics);
             }
@@ -212,6 +248,17 @@ pub fn command_builder() -> Command {
             cmd
         }
         )
+        .subcommand(
+            Command::new("offline-upgrade")
+                .hide(true)
+                .about("Install the upgrade prepared by `oma upgrade --offline' (used by oma-offline-upgrade.service)")
+        )
+        .subcommand(
+            Command::new("auto-upgrade")
+                .about("Upgrade packages allowed by the [auto_upgrade] policy in oma.toml without asking")
+                .long_about("Upgrade packages allowed by the [auto_upgrade] policy in oma.toml without asking (used by oma-auto-upgrade.timer)")
+                .arg(&dry_run)
+        )
         .subcommand(
             Command::new("download")
                 .about("Download package(s) from the repository")
@@ -243,19 +290,21 @@ pub fn command_builder() -> Command {
                 .arg(no_autoremove.clone().requires("packages"))
                 .arg(&fix_broken)
                 .arg(&remove_config)
-                .arg(&dry_run),
+                .arg(&dry_run)
+                .arg(&transaction_json),
         )
         .subcommand(
             Command::new("purge")
                 .about("purge (like apt purge) the specified package(s)")
                 .hide(true)
                 .arg(pkgs.clone().required(true).help("Package(s) to purge"))
-                .arg(yes.requires("packages"))
+                .arg(yes.clone().requires("packages"))
                 .arg(force_yes.requires("packages"))
                 .arg(no_autoremove.requires("packages"))
                 .arg(fix_broken)
                 .arg(&dry_run)
-                .arg(&force_unsafe_io),
+                .arg(&force_unsafe_io)
+                .arg(&transaction_json),
         )
         .subcommand({
             let mut cmd = Command::new("refresh")
@@ -268,6 +317,11 @@ pub fn command_builder() -> Command {
 
             cmd
         })
+        .subcommand(
+            Command::new("changelog")
+            .about("Show changelog of the specified package(s) newer than the installed version")
+            .arg(pkgs.clone().required(true))
+        )
         .subcommand(
             Command::new("show")
             .visible_alias("info")
@@ -328,6 +382,8 @@ pub fn command_builder() -> Command {
                 )
                 .arg(Arg::new("no_pager").long("no-pager").visible_alias("println").help("Set output mode as current println mode").action(ArgAction::SetTrue).requires("pattern"))
                 .arg(Arg::new("bin").long("bin").help("Search binary of package(s)").action(ArgAction::SetTrue).requires("pattern"))
+                .arg(Arg::new("regex").long("regex").help("Treat pattern as a regular expression matching the full path").action(ArgAction::SetTrue).requires("pattern").conflicts_with("glob"))
+                .arg(Arg::new("glob").long("glob").help("Treat pattern as a glob matching the full path (e.g. '/usr/lib/*/libfoo.so.*')").action(ArgAction::SetTrue).requires("pattern"))
         )
         .subcommand(
             Command::new("fix-broken")
@@ -366,13 +422,64 @@ pub fn command_builder() -> Command {
                 .arg(pkgs.clone().required(true).requires("action").help("Package(s) to mark status for"))
                 .arg(&dry_run))
         .subcommand(
-            Command::new("command-not-found").hide(true).arg(
-                Arg::new("package")
-                    .help("Package name")
-                    .action(ArgAction::Set)
-                    .num_args(0..=1)
-                    .required(true),
-            ),
+            Command::new("pin")
+                .about("Pin a package to a version or repository, or list pins")
+                .long_about("Pin a package to a version or repository in /etc/apt/preferences.d/oma-pins, list pins and their effect on the candidate versions if no package is given")
+                .arg(Arg::new("package").help("Package to pin").num_args(1).action(ArgAction::Set).requires("target"))
+                .arg(
+                    Arg::new("target")
+                        .help("Version (globs allowed), origin, suite or hostname of a repository, or a release pin like `o=AOSC'")
+                        .num_args(1)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("priority")
+                        .long("priority")
+                        .short('p')
+                        .help("Pin priority")
+                        .num_args(1)
+                        .default_value("1001")
+                        .allow_negative_numbers(true)
+                        .value_parser(clap::value_parser!(i32))
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("unpin")
+                .about("Remove the pin(s) of package(s)")
+                .arg(pkgs.clone().required(true).help("Package(s) to unpin")),
+        )
+        .subcommand(
+            Command::new("command-not-found")
+                .hide(true)
+                .arg(
+                    Arg::new("package")
+                        .help("Package name")
+                        .action(ArgAction::Set)
+                        .num_args(0..=1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("args")
+                        .help("Arguments of the command, used to re-run it after installing")
+                        .action(ArgAction::Append)
+                        .num_args(0..)
+                        .allow_hyphen_values(true)
+                        .trailing_var_arg(true),
+                )
+                .arg(
+                    Arg::new("install")
+                        .long("install")
+                        .help("Offer to install the top match and re-run the command")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Output candidates in json format, search errors are printed as an error object with exit code 1")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("install"),
+                ),
         )
         .subcommand(
             Command::new("list")
@@ -449,10 +556,82 @@ pub fn command_builder() -> Command {
         .subcommand(
             Command::new("history")
                         .visible_alias("log")
-                        .about("Show a history/log of package changes in the system"))
+                        .about("Show a history/log of package changes in the system")
+                        .subcommand(
+                            Command::new("show")
+                                .about("Show details of a history entry")
+                                .arg(
+                                    Arg::new("id")
+                                        .required(true)
+                                        .value_parser(clap::value_parser!(i64))
+                                        .help("ID of the history entry (shown in `oma history`)"),
+                                )
+                                .arg(
+                                    Arg::new("log")
+                                        .long("log")
+                                        .action(ArgAction::SetTrue)
+                                        .help("Show dpkg output of this operation"),
+                                ),
+                        ))
+        .subcommand(
+            Command::new("apply")
+                .about("Converge the system to the declarative state in /etc/oma/state.toml")
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .action(ArgAction::SetTrue)
+                        .help("Only check for drift, exit with non-zero status if the system differs from the state file"),
+                )
+                .arg(&dry_run),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export manually installed packages, holds, topics, mirrors and sources to a manifest")
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .num_args(1)
+                        .action(ArgAction::Set)
+                        .help("Write manifest to file instead of stdout"),
+                )
+                .arg(&json),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Restore system state from a manifest created by `oma export`")
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .num_args(1)
+                        .action(ArgAction::Set)
+                        .help("Manifest file (TOML or JSON)"),
+                )
+                .arg(&dry_run),
+        )
         .subcommand(
             Command::new("undo")
-                        .about("Undo system changes operation"))
+                        .about("Undo system changes operation")
+                        .arg(
+                            Arg::new("to")
+                                .long("to")
+                                .num_args(1)
+                                .action(ArgAction::Set)
+                                .value_name("ID|DATE")
+                                .help("Undo all operations after the given history ID or date (YYYY-MM-DD [HH:MM:SS])"),
+                        )
+                        .arg(&yes))
+        .subcommand(
+            Command::new("daemon")
+                .hide(true)
+                .about("Run the oma D-Bus service (io.aosc.Oma.Daemon)")
+                .arg(
+                    Arg::new("session")
+                        .long("session")
+                        .help("Use the session bus and skip polkit authorization, for testing")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
         .subcommand(
         Command::new("pkgnames")
                 .hide(true)
@@ -500,7 +679,25 @@ pub fn command_builder() -> Command {
                         .action(ArgAction::Append)
                         .num_args(1..),
                 )
+                .arg(
+                    Arg::new("list")
+                        .long("list")
+                        .short('l')
+                        .help("List available topics, use cached data when offline")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["opt_in", "opt_out"]),
+                )
                 .arg(&dry_run)
+                .subcommand(
+                    Command::new("show")
+                        .about("Show packages of a topic compared with the stable versions")
+                        .arg(
+                            Arg::new("name")
+                                .required(true)
+                                .help("Topic name")
+                                .action(ArgAction::Set),
+                        ),
+                )
         ).subcommand(
             Command::new("mirror")
                     .visible_alias("mirrors")
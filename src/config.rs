@@ -15,6 +15,7 @@ const DEFAULT_CONFIG: &str = include_str!("../data/config/oma-debian.toml");
 pub struct Config {
     pub general: Option<GeneralConfig>,
     pub network: Option<NetworkConfig>,
+    pub auto_upgrade: Option<AutoUpgradeConfig>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -31,18 +32,78 @@ pub struct GeneralConfig {
     pub search_contents_println: bool,
     #[serde(default = "GeneralConfig::default_search_engine")]
     pub search_engine: String,
+    #[serde(default = "GeneralConfig::default_topic_expiry")]
+    pub topic_expiry: TopicExpiry,
+}
+
+/// What to do with packages from a topic which has been closed upstream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TopicExpiry {
+    /// Keep the installed topic versions
+    Keep,
+    /// Downgrade to the stable versions without asking
+    Downgrade,
+    /// Show the downgrade and ask for confirmation
+    Ask,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct NetworkConfig {
     #[serde(default = "NetworkConfig::default_network_thread")]
     pub network_threads: usize,
+    #[serde(default = "NetworkConfig::default_speed_limit")]
+    pub speed_limit: u64,
+}
+
+/// Policy of `oma auto-upgrade`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AutoUpgradeConfig {
+    /// Only upgrade to versions from these origins (`Origin` in the Release file), empty means any
+    #[serde(default)]
+    pub origins: Vec<String>,
+    /// Only upgrade to versions from these sections (suite or component), empty means any
+    #[serde(default)]
+    pub sections: Vec<String>,
+    /// Local time windows (`HH:MM-HH:MM`) in which nothing will be upgraded
+    #[serde(default)]
+    pub blackout_windows: Vec<String>,
+    #[serde(default = "AutoUpgradeConfig::default_max_download_size")]
+    pub max_download_size: u64,
+    #[serde(default = "AutoUpgradeConfig::default_skip_on_battery")]
+    pub skip_on_battery: bool,
+}
+
+impl Default for AutoUpgradeConfig {
+    fn default() -> Self {
+        Self {
+            origins: vec![],
+            sections: vec![],
+            blackout_windows: vec![],
+            max_download_size: Self::default_max_download_size(),
+            skip_on_battery: Self::default_skip_on_battery(),
+        }
+    }
+}
+
+impl AutoUpgradeConfig {
+    pub const fn default_max_download_size() -> u64 {
+        0
+    }
+
+    pub const fn default_skip_on_battery() -> bool {
+        true
+    }
 }
 
 impl NetworkConfig {
     pub const fn default_network_thread() -> usize {
         4
     }
+
+    pub const fn default_speed_limit() -> u64 {
+        0
+    }
 }
 
 impl GeneralConfig {
@@ -66,6 +127,10 @@ impl GeneralConfig {
         false
     }
 
+    pub const fn default_topic_expiry() -> TopicExpiry {
+        TopicExpiry::Ask
+    }
+
     pub fn default_search_engine() -> String {
         if cfg!(feature = "aosc") {
             String::from("indicium")
@@ -95,6 +160,20 @@ impl Config {
             .unwrap_or_else(NetworkConfig::default_network_thread)
     }
 
+    pub fn speed_limit(&self) -> Option<u64> {
+        let limit = self
+            .network
+            .as_ref()
+            .map(|x| x.speed_limit)
+            .unwrap_or_else(NetworkConfig::default_speed_limit);
+
+        if limit == 0 {
+            None
+        } else {
+            Some(limit)
+        }
+    }
+
     pub fn no_check_dbus(&self) -> bool {
         self.general
             .as_ref()
@@ -123,10 +202,25 @@ impl Config {
             .unwrap_or_else(GeneralConfig::default_search_contents_println)
     }
 
+    #[cfg(feature = "aosc")]
+    pub fn topic_expiry(&self) -> TopicExpiry {
+        self.general
+            .as_ref()
+            .map(|x| x.topic_expiry)
+            .unwrap_or_else(GeneralConfig::default_topic_expiry)
+    }
+
     pub fn search_engine(&self) -> Cow<String> {
         self.general
             .as_ref()
             .map(|x| Cow::Borrowed(&x.search_engine))
             .unwrap_or_else(|| Cow::Owned(GeneralConfig::default_search_engine()))
     }
+
+    pub fn auto_upgrade(&self) -> Cow<AutoUpgradeConfig> {
+        self.auto_upgrade
+            .as_ref()
+            .map(Cow::Borrowed)
+            .unwrap_or_default()
+    }
 }
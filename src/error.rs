@@ -13,6 +13,7 @@ use oma_history::HistoryError;
 #[cfg(feature = "aosc")]
 use oma_mirror::MirrorError;
 
+use oma_pm::pin::PinError;
 use oma_pm::search::OmaSearchError;
 use oma_pm::AptErrors;
 use oma_pm::{apt::OmaAptError, matches::MatcherError};
@@ -210,6 +211,19 @@ impl From<MirrorError> for OutputError {
                 description: fl!("failed-to-create-file", p = path.display().to_string()),
                 source: Some(Box::new(source)),
             },
+            MirrorError::InvalidMirror {
+                path,
+                mirror_name,
+                reason,
+            } => Self {
+                description: fl!(
+                    "invalid-custom-mirror",
+                    mirror = mirror_name.as_ref(),
+                    p = path.display().to_string(),
+                    reason = reason.as_ref()
+                ),
+                source: None,
+            },
         }
     }
 }
@@ -245,6 +259,10 @@ impl From<OmaDbusError> for OutputError {
                 description: value.to_string(),
                 source: None,
             },
+            OmaDbusError::FailedCheckAuthorization(e) => Self {
+                description: value.to_string(),
+                source: Some(Box::new(e)),
+            },
         }
     }
 }
@@ -686,6 +704,14 @@ impl From<OmaContentsError> for OutputError {
                 description: format!("Illegal file: {path}"),
                 source: None,
             },
+            OmaContentsError::IndexNotAvailable => Self {
+                description: "Contents index does not exist or is outdated".to_string(),
+                source: None,
+            },
+            OmaContentsError::InvalidPattern(pattern, e) => Self {
+                description: fl!("invalid-search-pattern", pattern = pattern),
+                source: Some(Box::new(e)),
+            },
         }
     }
 }
@@ -878,6 +904,14 @@ pub fn oma_apt_error_to_output(err: OmaAptError) -> OutputError {
             description: fl!("dpkg-triggers-only-a-non-zero"),
             source: Some(Box::new(e)),
         },
+        OmaAptError::NoChangelog(pkg) => OutputError {
+            description: fl!("no-changelog", name = pkg),
+            source: None,
+        },
+        OmaAptError::FailedGetChangelog(url, e) => OutputError {
+            description: fl!("failed-to-get-changelog", url = url),
+            source: Some(Box::new(e)),
+        },
     }
 }
 
@@ -937,6 +971,10 @@ fn oma_download_error(e: DownloadError) -> OutputError {
             description: e.to_string(),
             source: None,
         },
+        DownloadError::RangeNotSupported(_) => OutputError {
+            description: e.to_string(),
+            source: None,
+        },
     }
 }
 
@@ -1001,6 +1039,22 @@ fn oma_database_error(e: MatcherError) -> OutputError {
     }
 }
 
+impl From<PinError> for OutputError {
+    fn from(value: PinError) -> Self {
+        debug!("{:?}", value);
+        match value {
+            PinError::ReadFile(p, e) | PinError::WriteFile(p, e) => Self {
+                description: fl!("failed-to-operate-path", p = p),
+                source: Some(Box::new(e)),
+            },
+            PinError::InvalidPin(p, s) => Self {
+                description: fl!("invalid-pin", p = p, pin = s),
+                source: None,
+            },
+        }
+    }
+}
+
 impl From<HistoryError> for OutputError {
     fn from(value: HistoryError) -> Self {
         debug!("{:?}", value);
@@ -0,0 +1,329 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    os::{fd::FromRawFd, unix::net::UnixStream},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use oma_fetch::DownloadProgressControl;
+use oma_pm::{
+    apt::{AptConfig, OmaOperation, PinBlocked},
+    progress::InstallProgressManager,
+};
+use oma_refresh::db::{HandleRefresh, HandleTopicsControl};
+use serde::Serialize;
+
+use crate::error::OutputError;
+
+type DashMap<K, V> = dashmap::DashMap<K, V, ahash::random_state::RandomState>;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+static SINK: OnceLock<Sink> = OnceLock::new();
+
+struct Sink {
+    writer: Mutex<Box<dyn Write + Send>>,
+    /// `--json`: the events replace the normal output on stdout
+    stdout: bool,
+}
+
+/// Machine-readable events, one JSON object per line
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    Operation(&'a OmaOperation),
+    /// A newer version of the package is not used because of its pin
+    PinBlocked(&'a PinBlocked),
+    DownloadStart {
+        total_size: u64,
+    },
+    DownloadProgress {
+        downloaded: u64,
+        total_size: u64,
+    },
+    FileStart {
+        index: usize,
+        name: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        size: Option<u64>,
+    },
+    FileProgress {
+        index: usize,
+        downloaded: u64,
+    },
+    FileDone {
+        index: usize,
+    },
+    DownloadDone {
+        file: &'a str,
+    },
+    DownloadRetry {
+        file: &'a str,
+        times: usize,
+    },
+    DownloadError {
+        error: &'a str,
+    },
+    InstallProgress {
+        package: &'a str,
+        steps_done: u64,
+        total_steps: u64,
+    },
+    TopicScanning,
+    TopicClosed {
+        topic: &'a str,
+    },
+    TopicNotInMirror {
+        topic: &'a str,
+        mirror: &'a str,
+    },
+    RunInvokeScript,
+    Result {
+        success: bool,
+        code: i32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+/// Send events to stdout, everything else written to stdout goes to stderr from now on
+pub fn init_stdout() -> io::Result<()> {
+    // dpkg 等子进程会继承 stdout，所以把原来的 stdout 复制一份专门输出事件，再把 stdout 指向 stderr
+    let fd = unsafe { libc::fcntl(libc::STDOUT_FILENO, libc::F_DUPFD_CLOEXEC, 3) };
+
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    init(Box::new(unsafe { File::from_raw_fd(fd) }), true);
+
+    Ok(())
+}
+
+/// Send events to a file descriptor opened by the caller, e.g. a pipe to a GUI frontend
+pub fn init_fd(fd: i32) -> io::Result<()> {
+    // 不让 dpkg 等子进程继承这个 fd
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    init(Box::new(unsafe { File::from_raw_fd(fd) }), false);
+
+    Ok(())
+}
+
+/// Send events to the Unix socket listened by a frontend
+pub fn init_socket(path: impl AsRef<Path>) -> io::Result<()> {
+    let stream = UnixStream::connect(path)?;
+    init(Box::new(stream), false);
+
+    Ok(())
+}
+
+fn init(writer: Box<dyn Write + Send>, stdout: bool) {
+    SINK.set(Sink {
+        writer: Mutex::new(writer),
+        stdout,
+    })
+    .ok();
+}
+
+/// Events are written somewhere, progress should be reported with [`EventProgress`]
+pub fn enabled() -> bool {
+    SINK.get().is_some()
+}
+
+/// `--json` mode, nothing can be asked on the terminal
+pub fn is_stdout() -> bool {
+    SINK.get().is_some_and(|x| x.stdout)
+}
+
+pub fn emit(event: &Event) {
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+
+    let Ok(mut line) = serde_json::to_string(event) else {
+        return;
+    };
+
+    line.push('\n');
+
+    let mut writer = sink.writer.lock().unwrap();
+    writer.write_all(line.as_bytes()).ok();
+    writer.flush().ok();
+}
+
+pub fn emit_result(res: &Result<i32, OutputError>) {
+    let event = match res {
+        Ok(code) => Event::Result {
+            success: *code == 0,
+            code: *code,
+            error: None,
+        },
+        Err(e) => Event::Result {
+            success: false,
+            code: 1,
+            error: Some(match &e.source {
+                Some(source) => format!("{}: {source}", e.description),
+                None => e.description.clone(),
+            }),
+        },
+    };
+
+    emit(&event);
+}
+
+/// Report download, refresh and dpkg progress as events
+pub struct EventProgress {
+    timer: RwLock<Instant>,
+    total_size: AtomicU64,
+    files: DashMap<usize, (u64, Instant)>,
+}
+
+impl Default for EventProgress {
+    fn default() -> Self {
+        Self {
+            timer: RwLock::new(Instant::now()),
+            total_size: AtomicU64::new(0),
+            files: DashMap::with_hasher(ahash::RandomState::new()),
+        }
+    }
+}
+
+impl EventProgress {
+    fn file_progress(&self, index: usize, update: impl FnOnce(u64) -> u64) {
+        let mut entry = self.files.entry(index).or_insert((0, Instant::now()));
+        let (downloaded, last) = entry.value_mut();
+        *downloaded = update(*downloaded);
+
+        // 避免输出过多的进度事件
+        if last.elapsed() >= PROGRESS_INTERVAL {
+            *last = Instant::now();
+            emit(&Event::FileProgress {
+                index,
+                downloaded: *downloaded,
+            });
+        }
+    }
+}
+
+impl DownloadProgressControl for EventProgress {
+    fn checksum_mismatch_retry(&self, _index: usize, filename: &str, times: usize) {
+        emit(&Event::DownloadRetry {
+            file: filename,
+            times,
+        });
+    }
+
+    fn global_progress_set(&self, num: &AtomicU64) {
+        if self.timer.read().unwrap().elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+
+        emit(&Event::DownloadProgress {
+            downloaded: num.load(Ordering::SeqCst),
+            total_size: self.total_size.load(Ordering::SeqCst),
+        });
+
+        *self.timer.write().unwrap() = Instant::now();
+    }
+
+    fn progress_done(&self, index: usize) {
+        self.files.remove(&index);
+        emit(&Event::FileDone { index });
+    }
+
+    fn new_progress_spinner(&self, index: usize, msg: &str) {
+        emit(&Event::FileStart {
+            index,
+            name: msg,
+            size: None,
+        });
+    }
+
+    fn new_progress_bar(&self, index: usize, msg: &str, size: u64) {
+        emit(&Event::FileStart {
+            index,
+            name: msg,
+            size: Some(size),
+        });
+    }
+
+    fn progress_inc(&self, index: usize, num: u64) {
+        self.file_progress(index, |x| x + num);
+    }
+
+    fn progress_set(&self, index: usize, num: u64) {
+        self.file_progress(index, |_| num);
+    }
+
+    fn failed_to_get_source_next_url(&self, _index: usize, err: &str) {
+        emit(&Event::DownloadError { error: err });
+    }
+
+    fn download_done(&self, _index: usize, msg: &str) {
+        emit(&Event::DownloadDone { file: msg });
+    }
+
+    fn all_done(&self) {
+        let total_size = self.total_size.load(Ordering::SeqCst);
+
+        emit(&Event::DownloadProgress {
+            downloaded: total_size,
+            total_size,
+        });
+    }
+
+    fn new_global_progress_bar(&self, total_size: u64) {
+        self.total_size.store(total_size, Ordering::SeqCst);
+        emit(&Event::DownloadStart { total_size });
+    }
+}
+
+impl HandleTopicsControl for EventProgress {
+    fn scanning_topic(&self) {
+        emit(&Event::TopicScanning);
+    }
+
+    fn closing_topic(&self, topic: &str) {
+        emit(&Event::TopicClosed { topic });
+    }
+
+    fn topic_not_in_mirror(&self, topic: &str, mirror: &str) {
+        emit(&Event::TopicNotInMirror { topic, mirror });
+    }
+}
+
+impl HandleRefresh for EventProgress {
+    fn run_invoke_script(&self) {
+        emit(&Event::RunInvokeScript);
+    }
+}
+
+impl InstallProgressManager for EventProgress {
+    fn status_change(&self, pkgname: &str, steps_done: u64, total_steps: u64, _config: &AptConfig) {
+        emit(&Event::InstallProgress {
+            package: pkgname,
+            steps_done,
+            total_steps,
+        });
+    }
+
+    fn no_interactive(&self) -> bool {
+        true
+    }
+
+    fn use_pty(&self) -> bool {
+        false
+    }
+}
@@ -10,6 +10,7 @@ use std::time::Duration;
 mod args;
 mod config;
 mod error;
+mod event;
 mod install_progress;
 mod lang;
 mod pb;
@@ -33,12 +34,15 @@ use oma_console::writer::{writeln_inner, MessageType, Writer};
 use oma_console::WRITER;
 use oma_console::{due_to, OmaLayer};
 
-use oma_pm::apt::Upgrade;
+use oma_contents::searcher::QueryKind;
+use oma_fetch::ProxyConfig;
+use oma_pm::apt::{AptConfig, Upgrade};
+use oma_refresh::get_config;
 
 use oma_utils::dbus::{create_dbus_connection, get_another_oma_status, OmaDbusError};
 use oma_utils::oma::{terminal_ring, unlock_oma};
 use oma_utils::OsRelease;
-use reqwest::Client;
+use reqwest::{Client, Proxy};
 use rustix::stdio::stdout;
 use subcommand::utils::LockError;
 use tokio::runtime::Runtime;
@@ -54,10 +58,13 @@ use std::sync::atomic::{AtomicBool, Ordering};
 
 use oma_console::console;
 
+#[cfg(feature = "aosc")]
+use crate::config::TopicExpiry;
 use crate::config::{Config, GeneralConfig};
 #[cfg(feature = "egg")]
 use crate::egg::ailurus;
 use crate::error::Chain;
+use crate::subcommand::command_not_found::CnfArgs;
 #[cfg(feature = "aosc")]
 use crate::subcommand::topics::TopicArgs;
 use crate::subcommand::*;
@@ -74,11 +81,15 @@ static RT: LazyLock<Runtime> = LazyLock::new(|| {
         .build()
         .expect("Failed to init async runtime")
 });
+static PROXY_CONFIG: OnceLock<ProxyConfig> = OnceLock::new();
 static HTTP_CLIENT: LazyLock<Client> = LazyLock::new(|| {
-    Client::builder()
-        .user_agent(APP_USER_AGENT)
-        .build()
-        .unwrap()
+    let mut builder = Client::builder().user_agent(APP_USER_AGENT);
+
+    if let Some(proxy) = apt_proxy() {
+        builder = builder.proxy(proxy);
+    }
+
+    builder.build().unwrap()
 });
 
 #[derive(Debug, Default)]
@@ -124,8 +135,11 @@ pub struct UpgradeArgs {
     autoremove: bool,
     force_unsafe_io: bool,
     remove_config: bool,
+    offline: bool,
     #[cfg(not(feature = "aosc"))]
     mode: UpgradeMode,
+    #[cfg(feature = "aosc")]
+    topic_expiry: TopicExpiry,
 }
 
 #[derive(Debug, Default)]
@@ -142,6 +156,7 @@ pub struct RemoveArgs {
 pub struct OmaArgs {
     dry_run: bool,
     network_thread: usize,
+    speed_limit: Option<u64>,
     no_progress: bool,
     no_check_dbus: bool,
     protect_essentials: bool,
@@ -175,6 +190,32 @@ fn main() {
         Some(Ok(Some(true)))
     );
 
+    // 事务性命令的 --json：stdout 只输出 JSON 事件
+    if let Some((x, args)) = matches.subcommand() {
+        if ["install", "upgrade", "remove", "purge"].contains(&x) && args.get_flag("json") {
+            if let Err(e) = event::init_stdout() {
+                eprintln!("Failed to initialize JSON output: {e}");
+                exit(1);
+            }
+        }
+    }
+
+    // --progress-fd 和 --progress-socket：供前端使用的进度事件流
+    if !event::enabled() {
+        let res = if let Some(fd) = matches.get_one::<i32>("progress_fd") {
+            Some(event::init_fd(*fd))
+        } else {
+            matches
+                .get_one::<String>("progress_socket")
+                .map(event::init_socket)
+        };
+
+        if let Some(Err(e)) = res {
+            eprintln!("Failed to open progress event stream: {e}");
+            exit(1);
+        }
+    }
+
     // Init debug flag
     let debug = if matches.get_flag("debug")
         || matches!(
@@ -260,7 +301,13 @@ fn main() {
         }
     }
 
-    let code = match run_subcmd(matches, dry_run, no_progress, no_color) {
+    let res = run_subcmd(matches, dry_run, no_progress, no_color);
+
+    if event::enabled() {
+        event::emit_result(&res);
+    }
+
+    let code = match res {
         Ok(exit_code) => {
             unlock_oma().ok();
             exit_code
@@ -415,6 +462,11 @@ fn run_subcmd(
     let oma_args = OmaArgs {
         dry_run,
         network_thread: config.network_thread(),
+        speed_limit: matches
+            .get_one::<u64>("speed_limit")
+            .copied()
+            .or_else(|| config.speed_limit())
+            .filter(|limit| *limit > 0),
         no_progress,
         no_check_dbus,
         protect_essentials: config
@@ -425,6 +477,10 @@ fn run_subcmd(
         another_apt_options: apt_options.map(|x| x.to_string()).collect::<Vec<_>>(),
     };
 
+    PROXY_CONFIG
+        .set(apt_proxy_config(&oma_args.another_apt_options))
+        .ok();
+
     let exit_code = match matches.subcommand() {
         Some(("install", args)) => {
             let input = pkgs_getter(args).unwrap_or_default();
@@ -434,7 +490,7 @@ fn run_subcmd(
                 install_dbg: args.get_flag("install_dbg"),
                 reinstall: args.get_flag("reinstall"),
                 no_fixbroken: !args.get_flag("fix_broken"),
-                yes: args.get_flag("yes"),
+                yes: args.get_flag("yes") || args.get_flag("json"),
                 force_yes: args.get_flag("force_yes"),
                 force_confnew: args.get_flag("force_confnew"),
                 install_recommends: args.get_flag("install_recommends"),
@@ -453,7 +509,7 @@ fn run_subcmd(
             let pkgs_unparse = pkgs_getter(args).unwrap_or_default();
 
             let args = UpgradeArgs {
-                yes: args.get_flag("yes"),
+                yes: args.get_flag("yes") || args.get_flag("json"),
                 force_yes: args.get_flag("force_yes"),
                 force_confnew: args.get_flag("force_confnew"),
                 sysroot,
@@ -461,6 +517,7 @@ fn run_subcmd(
                 autoremove: args.get_flag("autoremove"),
                 force_unsafe_io: args.get_flag("force_unsafe_io"),
                 remove_config: args.get_flag("remove_config"),
+                offline: args.get_flag("offline"),
                 #[cfg(not(feature = "aosc"))]
                 mode: {
                     if args.get_flag("no_remove") {
@@ -469,10 +526,16 @@ fn run_subcmd(
                         UpgradeMode::Full
                     }
                 },
+                #[cfg(feature = "aosc")]
+                topic_expiry: config.topic_expiry(),
             };
 
             upgrade::execute(pkgs_unparse, args, oma_args)?
         }
+        Some(("offline-upgrade", _)) => offline::execute(oma_args, sysroot)?,
+        Some(("auto-upgrade", _)) => {
+            auto_upgrade::execute(oma_args, sysroot, &config.auto_upgrade())?
+        }
         Some(("download", args)) => {
             let keyword = pkgs_getter(args).unwrap_or_default();
             let keyword = keyword.iter().map(|x| x.as_str()).collect::<Vec<_>>();
@@ -489,7 +552,7 @@ fn run_subcmd(
             let input = input.iter().map(|x| x.as_str()).collect::<Vec<_>>();
 
             let args = RemoveArgs {
-                yes: args.get_flag("yes"),
+                yes: args.get_flag("yes") || args.get_flag("json"),
                 remove_config: match args.try_get_one::<bool>("remove_config") {
                     Ok(Some(b)) => *b,
                     Ok(None) if x == "purge" => true,
@@ -504,8 +567,18 @@ fn run_subcmd(
 
             remove::execute(input, args, oma_args)?
         }
-        Some(("refresh", args)) => {
-            refresh::execute(oma_args, sysroot, no_refresh_topics(&config, args))?
+        Some(("refresh", args)) => refresh::execute(
+            oma_args,
+            sysroot,
+            no_refresh_topics(&config, args),
+            #[cfg(feature = "aosc")]
+            config.topic_expiry(),
+        )?,
+        Some(("changelog", args)) => {
+            let input = pkgs_getter(args).unwrap_or_default();
+            let input = input.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+
+            changelog::execute(input, sysroot, oma_args.another_apt_options, no_progress)?
         }
         Some(("show", args)) => {
             let input = pkgs_getter(args).unwrap_or_default();
@@ -551,13 +624,22 @@ fn run_subcmd(
             let arg = if x == "files" { "package" } else { "pattern" };
             let pkg = args.get_one::<String>(arg).unwrap();
             let is_bin = args.get_flag("bin");
+            let kind = if x == "files" {
+                QueryKind::Plain
+            } else if args.get_flag("regex") {
+                QueryKind::Regex
+            } else if args.get_flag("glob") {
+                QueryKind::Glob
+            } else {
+                QueryKind::Plain
+            };
             let println = config.search_contents_println()
                 || !stdout().is_terminal()
                 || !stderr().is_terminal()
                 || !stdin().is_terminal()
                 || args.get_flag("no_pager");
 
-            contents_find::execute(x, is_bin, pkg, no_progress, sysroot, println)?
+            contents_find::execute(x, is_bin, pkg, kind, no_progress, sysroot, println)?
         }
         Some(("fix-broken", _)) => fix_broken::execute(oma_args, sysroot)?,
         Some(("pick", args)) => {
@@ -586,9 +668,29 @@ fn run_subcmd(
                 no_progress,
             )?
         }
-        Some(("command-not-found", args)) => {
-            command_not_found::execute(args.get_one::<String>("package").unwrap())?
-        }
+        Some(("pin", args)) => match (
+            args.get_one::<String>("package"),
+            args.get_one::<String>("target"),
+        ) {
+            (Some(pkg), Some(target)) => pin::execute_pin(
+                pkg,
+                target,
+                *args.get_one::<i32>("priority").unwrap(),
+                sysroot,
+                oma_args.another_apt_options,
+            )?,
+            _ => pin::execute_list(sysroot, oma_args.another_apt_options)?,
+        },
+        Some(("unpin", args)) => pin::execute_unpin(pkgs_getter(args).unwrap(), sysroot)?,
+        Some(("command-not-found", args)) => command_not_found::execute(CnfArgs {
+            query: args.get_one::<String>("package").unwrap(),
+            args: args
+                .get_many::<String>("args")
+                .map(|x| x.map(|x| x.to_owned()).collect::<Vec<_>>())
+                .unwrap_or_default(),
+            json: args.get_flag("json"),
+            interactive: args.get_flag("install"),
+        })?,
         Some(("list", args)) => {
             let pkgs = pkgs_getter(args).unwrap_or_default();
             let all = args.get_flag("all");
@@ -635,8 +737,31 @@ fn run_subcmd(
             )?
         }
         Some(("clean", _)) => clean::execute(no_progress, sysroot, oma_args.another_apt_options)?,
-        Some(("history", _)) => subcommand::history::execute_history(sysroot)?,
-        Some(("undo", _)) => history::execute_undo(oma_args, sysroot)?,
+        Some(("history", args)) => match args.subcommand() {
+            Some(("show", args)) => history::execute_history_show(
+                *args.get_one::<i64>("id").unwrap(),
+                args.get_flag("log"),
+                sysroot,
+            )?,
+            _ => subcommand::history::execute_history(sysroot)?,
+        },
+        Some(("apply", args)) => apply::execute(oma_args, sysroot, args.get_flag("check"))?,
+        Some(("export", args)) => manifest::execute_export(
+            sysroot,
+            args.get_one::<String>("output").map(|x| x.as_str()),
+            args.get_flag("json"),
+            oma_args.another_apt_options,
+        )?,
+        Some(("import", args)) => {
+            manifest::execute_import(args.get_one::<String>("file").unwrap(), oma_args, sysroot)?
+        }
+        Some(("undo", args)) => history::execute_undo(
+            oma_args,
+            sysroot,
+            args.get_one::<String>("to").map(|x| x.as_str()),
+            args.get_flag("yes"),
+        )?,
+        Some(("daemon", args)) => daemon::execute(args.get_flag("session"))?,
         #[cfg(feature = "aosc")]
         Some(("topics", args)) => {
             let opt_in = args
@@ -651,22 +776,39 @@ fn run_subcmd(
 
             let network_thread = config.network_thread();
 
-            let args = TopicArgs {
-                opt_in,
-                opt_out,
-                dry_run,
-                network_thread,
-                no_progress,
-                no_check_dbus,
-                sysroot,
-            };
+            let mut builder = Client::builder().user_agent(APP_USER_AGENT);
 
-            let client = Client::builder()
-                .user_agent(APP_USER_AGENT)
-                .build()
-                .unwrap();
+            if let Some(proxy) = apt_proxy() {
+                builder = builder.proxy(proxy);
+            }
+
+            let client = builder.build().unwrap();
 
-            topics::execute(args, client, oma_args)?
+            if let Some(("show", args)) = args.subcommand() {
+                let name = args.get_one::<String>("name").unwrap();
+
+                topics::execute_show(
+                    name,
+                    client,
+                    sysroot,
+                    no_progress,
+                    oma_args.another_apt_options,
+                )?
+            } else if args.get_flag("list") {
+                topics::execute_list(client, sysroot, no_progress)?
+            } else {
+                let args = TopicArgs {
+                    opt_in,
+                    opt_out,
+                    dry_run,
+                    network_thread,
+                    no_progress,
+                    no_check_dbus,
+                    sysroot,
+                };
+
+                topics::execute(args, client, oma_args)?
+            }
         }
         Some(("pkgnames", args)) => {
             let keyword = args.get_one::<String>("keyword").map(|x| x.as_str());
@@ -679,6 +821,7 @@ fn run_subcmd(
             no_progress,
             dry_run,
             network_thread: oma_args.network_thread,
+            speed_limit: oma_args.speed_limit,
             no_check_dbus,
             another_apt_options: oma_args.another_apt_options,
         })?,
@@ -691,12 +834,14 @@ fn run_subcmd(
                     no_progress,
                     !args.get_flag("no_refresh_topics"),
                     oma_args.network_thread,
+                    oma_args.speed_limit,
                     args.get_flag("no_refresh"),
                 )?,
                 Some(("sort-mirrors", _)) => mirror::set_order(
                     no_progress,
                     !args.get_flag("no_refresh_topics"),
                     oma_args.network_thread,
+                    oma_args.speed_limit,
                     args.get_flag("no_refresh"),
                 )?,
                 Some(("set", sub_args)) => {
@@ -710,6 +855,7 @@ fn run_subcmd(
                         no_progress,
                         !args.get_flag("no_refresh_topics"),
                         oma_args.network_thread,
+                        oma_args.speed_limit,
                         args.get_flag("no_refresh"),
                         names,
                         mirror::Operate::Set,
@@ -726,6 +872,7 @@ fn run_subcmd(
                         no_progress,
                         !args.get_flag("no_refresh_topics"),
                         oma_args.network_thread,
+                        oma_args.speed_limit,
                         args.get_flag("no_refresh"),
                         names,
                         mirror::Operate::Add,
@@ -742,6 +889,7 @@ fn run_subcmd(
                         no_progress,
                         !args.get_flag("no_refresh_topics"),
                         oma_args.network_thread,
+                        oma_args.speed_limit,
                         args.get_flag("no_refresh"),
                         names,
                         mirror::Operate::Remove,
@@ -752,6 +900,7 @@ fn run_subcmd(
                     sub_args.get_flag("set_fastest"),
                     !args.get_flag("no_refresh_topics"),
                     oma_args.network_thread,
+                    oma_args.speed_limit,
                     args.get_flag("no_refresh"),
                 )?,
                 _ => unreachable!(),
@@ -788,6 +937,24 @@ fn no_refresh_topics(config: &Config, args: &ArgMatches) -> bool {
     config.no_refresh_topics() || args.get_flag("no_refresh_topics")
 }
 
+/// Read proxy settings (`Acquire::http(s)::Proxy`) from the apt config of the command
+///
+/// Same as [`oma_pm::apt::OmaApt`], `-o` options override the config files.
+fn apt_proxy_config(another_apt_options: &[String]) -> ProxyConfig {
+    let mut config_tree = get_config(&AptConfig::new());
+
+    for kv in another_apt_options {
+        let (k, v) = kv.split_once('=').unwrap_or((kv, ""));
+        config_tree.push((k.to_string(), v.to_string()));
+    }
+
+    ProxyConfig::from_apt_config(&config_tree)
+}
+
+fn apt_proxy() -> Option<Proxy> {
+    PROXY_CONFIG.get().cloned()?.into_reqwest_proxy()
+}
+
 #[inline]
 fn color_formatter() -> &'static OmaColorFormat {
     COLOR_FORMATTER.get().unwrap()
@@ -0,0 +1,249 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use apt_auth_config::AuthConfig;
+use oma_console::success;
+use oma_history::SummaryType;
+use oma_pm::{
+    apt::{AptConfig, OmaApt, OmaAptArgs},
+    matches::PackagesMatcher,
+    pkginfo::OmaPackage,
+};
+use oma_utils::dpkg::{dpkg_arch, get_selections};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::{
+    error::OutputError,
+    fl,
+    utils::{dbus_check, root},
+    OmaArgs, HTTP_CLIENT,
+};
+
+use super::utils::{handle_no_result, lock_oma, no_check_dbus_warn, CommitRequest};
+
+const STATE_PATH: &str = "etc/oma/state.toml";
+
+/// Declarative system state (`/etc/oma/state.toml`)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct State {
+    /// Packages must be installed
+    present: Vec<String>,
+    /// Packages (or globs) must not be installed
+    absent: Vec<String>,
+    /// Packages must be held at the installed version
+    held: Vec<String>,
+    /// Packages must be installed at the given version, and will be held
+    pinned: BTreeMap<String, String>,
+}
+
+pub fn execute(oma_args: OmaArgs, sysroot: String, check: bool) -> Result<i32, OutputError> {
+    if !check {
+        root()?;
+        lock_oma()?;
+    }
+
+    let OmaArgs {
+        dry_run,
+        network_thread,
+        speed_limit,
+        no_progress,
+        no_check_dbus,
+        protect_essentials: protect_essential,
+        another_apt_options,
+    } = oma_args;
+
+    let state = read_state(&sysroot)?;
+
+    let oma_apt_args = OmaAptArgs::builder()
+        .sysroot(sysroot.clone())
+        .another_apt_options(another_apt_options)
+        .build();
+
+    let mut apt = OmaApt::new(vec![], oma_apt_args.clone(), dry_run, AptConfig::new())?;
+    let arch = dpkg_arch(&sysroot)?;
+    let matcher = PackagesMatcher::builder()
+        .cache(&apt.cache)
+        .filter_candidate(true)
+        .native_arch(&arch)
+        .build();
+
+    let mut drift = vec![];
+    let mut install = vec![];
+    let mut remove = vec![];
+
+    let (pkgs, no_result) =
+        matcher.match_pkgs_and_versions(state.present.iter().map(|x| x.as_str()))?;
+
+    handle_no_result(&sysroot, no_result, no_progress)?;
+
+    for pkg in pkgs {
+        let name = pkg.raw_pkg.fullname(true);
+
+        if !apt.cache.get(&name).is_some_and(|x| x.is_installed()) {
+            drift.push(fl!("apply-drift-missing", name = name));
+            install.push(pkg);
+        }
+    }
+
+    for (name, version) in &state.pinned {
+        let pkg = apt.cache.get(name).ok_or_else(|| OutputError {
+            description: fl!("apply-pkg-not-found", name = name.as_str()),
+            source: None,
+        })?;
+
+        let current = pkg.installed().map(|x| x.version().to_string());
+
+        if current.as_deref() == Some(version.as_str()) {
+            continue;
+        }
+
+        let ver = pkg.get_version(version).ok_or_else(|| OutputError {
+            description: fl!(
+                "apply-version-not-found",
+                name = name.as_str(),
+                version = version.as_str()
+            ),
+            source: None,
+        })?;
+
+        drift.push(fl!(
+            "apply-drift-version",
+            name = name.as_str(),
+            version = version.as_str(),
+            current = current.unwrap_or_else(|| "-".to_string())
+        ));
+
+        install.push(OmaPackage::new(&ver, &pkg).map_err(|e| OutputError {
+            description: e.to_string(),
+            source: None,
+        })?);
+    }
+
+    for glob in &state.absent {
+        for pkg in matcher.match_pkgs_from_glob(glob)? {
+            let name = pkg.raw_pkg.fullname(true);
+
+            if state.present.contains(&name) || state.pinned.contains_key(&name) {
+                return Err(OutputError {
+                    description: fl!("apply-conflict", name = name),
+                    source: None,
+                });
+            }
+
+            if apt.cache.get(&name).is_some_and(|x| x.is_installed()) {
+                drift.push(fl!("apply-drift-absent", name = name));
+                remove.push(pkg);
+            }
+        }
+    }
+
+    // pinned 的软件包同样需要 hold 住，以免升级时被更新
+    let held = state
+        .held
+        .iter()
+        .chain(state.pinned.keys())
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let selections = get_selections(&sysroot)?;
+    let mut hold = vec![];
+
+    for name in held {
+        let installed = apt.cache.get(&name).is_some_and(|x| x.is_installed());
+        let will_install = install.iter().any(|x| x.raw_pkg.fullname(true) == name);
+
+        if !installed && !will_install {
+            return Err(OutputError {
+                description: fl!("apply-held-not-installed", name = name),
+                source: None,
+            });
+        }
+
+        if !selections
+            .iter()
+            .any(|(pkg, status)| *pkg == name && status == "hold")
+        {
+            drift.push(fl!("apply-drift-hold", name = name.as_str()));
+            hold.push(name);
+        }
+    }
+
+    if drift.is_empty() {
+        success!("{}", fl!("apply-no-drift"));
+        return Ok(0);
+    }
+
+    if check {
+        for i in &drift {
+            warn!("{i}");
+        }
+
+        info!("{}", fl!("apply-drift-found", count = drift.len()));
+
+        return Ok(1);
+    }
+
+    let fds = if !no_check_dbus {
+        Some(dbus_check(false)?)
+    } else {
+        no_check_dbus_warn();
+        None
+    };
+
+    apt.remove(remove, false, true)?;
+    apt.install(&install, false)?;
+
+    let auth_config = AuthConfig::system(&sysroot)?;
+
+    let request = CommitRequest {
+        apt,
+        dry_run,
+        request_type: SummaryType::Changes,
+        no_fixbroken: false,
+        network_thread,
+        speed_limit,
+        no_progress,
+        sysroot: sysroot.clone(),
+        fix_dpkg_status: true,
+        protect_essential,
+        client: &HTTP_CLIENT,
+        yes: false,
+        remove_config: false,
+        auth_config: &auth_config,
+    };
+
+    let code = request.run()?;
+
+    if code != 0 || hold.is_empty() {
+        drop(fds);
+        return Ok(code);
+    }
+
+    // hold 只能设置给已安装的软件包，因此在提交后重新读取状态
+    let apt = OmaApt::new(vec![], oma_apt_args, dry_run, AptConfig::new())?;
+
+    for (pkg, is_set) in apt.mark_version_status(&hold, true, dry_run)? {
+        if is_set {
+            success!("{}", fl!("set-to-hold", name = pkg));
+        }
+    }
+
+    drop(fds);
+
+    Ok(0)
+}
+
+fn read_state(sysroot: &str) -> Result<State, OutputError> {
+    let path = Path::new(sysroot).join(STATE_PATH);
+
+    let s = fs::read_to_string(&path).map_err(|e| OutputError {
+        description: fl!("failed-to-operate-path", p = path.display().to_string()),
+        source: Some(Box::new(e)),
+    })?;
+
+    toml::from_str(&s).map_err(|e| OutputError {
+        description: fl!("failed-to-parse-file", p = path.display().to_string()),
+        source: Some(Box::new(e)),
+    })
+}
@@ -0,0 +1,438 @@
+use apt_auth_config::AuthConfig;
+use chrono::{Local, NaiveTime};
+use oma_console::indicatif::HumanBytes;
+use oma_console::success;
+use oma_fetch::DownloadProgressControl;
+use oma_history::{connect_db, create_db_file, write_history_entry, SummaryType};
+use oma_pm::apt::{AptConfig, CommitDownloadConfig, FilterMode, OmaApt, OmaAptArgs, SummarySort};
+use oma_pm::pkginfo::OmaPackage;
+use oma_pm::Version;
+use oma_utils::dbus::{create_dbus_connection, is_using_battery, take_wake_lock};
+use oma_utils::dpkg::{get_selections, is_hold};
+use std::collections::HashSet;
+use tracing::{debug, info, warn};
+
+use crate::config::AutoUpgradeConfig;
+use crate::error::OutputError;
+use crate::event::{self, Event, EventProgress};
+use crate::install_progress::NoInstallProgressManager;
+use crate::pb::NoProgressBar;
+use crate::utils::root;
+use crate::{fl, OmaArgs, HTTP_CLIENT, RT};
+
+use super::utils::{
+    empty_operation, enabled_mirrors, lock_oma, no_check_dbus_warn, pin_blocked_tips,
+    RefreshRequest,
+};
+
+/// Upgrade the packages allowed by the `[auto_upgrade]` policy without asking
+///
+/// Every run is recorded in the history, runs skipped or refused are recorded as an empty
+/// operation with the reason.
+pub fn execute(
+    oma_args: OmaArgs,
+    sysroot: String,
+    policy: &AutoUpgradeConfig,
+) -> Result<i32, OutputError> {
+    root()?;
+    lock_oma()?;
+
+    let OmaArgs {
+        dry_run,
+        network_thread,
+        speed_limit,
+        no_progress,
+        no_check_dbus,
+        another_apt_options,
+        ..
+    } = oma_args;
+
+    let now = Local::now().time();
+
+    for window in &policy.blackout_windows {
+        let Some(range) = parse_window(window) else {
+            return Err(OutputError {
+                description: fl!("auto-upgrade-invalid-window", window = window.as_str()),
+                source: None,
+            });
+        };
+
+        if in_window(now, range) {
+            let reason = fl!("auto-upgrade-blackout", window = window.as_str());
+            info!("{reason}");
+            record_skipped(&sysroot, dry_run, reason)?;
+            return Ok(0);
+        }
+    }
+
+    let fds = if !no_check_dbus {
+        let conn = RT.block_on(create_dbus_connection())?;
+
+        if policy.skip_on_battery && RT.block_on(is_using_battery(&conn)).unwrap_or(false) {
+            let reason = fl!("auto-upgrade-on-battery");
+            info!("{reason}");
+            record_skipped(&sysroot, dry_run, reason)?;
+            return Ok(0);
+        }
+
+        Some(RT.block_on(take_wake_lock(&conn, &fl!("changing-system"), "oma"))?)
+    } else {
+        no_check_dbus_warn();
+        None
+    };
+
+    let apt_config = AptConfig::new();
+    let auth_config = AuthConfig::system(&sysroot)?;
+    let mirrors = enabled_mirrors(&sysroot);
+
+    RefreshRequest {
+        client: &HTTP_CLIENT,
+        dry_run,
+        no_progress,
+        limit: network_thread,
+        speed_limit,
+        sysroot: &sysroot,
+        _refresh_topics: false,
+        config: &apt_config,
+        auth_config: &auth_config,
+    }
+    .run()?;
+
+    let oma_apt_args = OmaAptArgs::builder()
+        .sysroot(sysroot.clone())
+        .yes(true)
+        .another_apt_options(another_apt_options)
+        .build();
+
+    // 被 hold 的软件包不应被自动升级
+    let selections = get_selections(&sysroot)?;
+    let mut apt_config = Some(apt_config);
+    let mut excluded = HashSet::new();
+
+    let (apt, pkgs, op, disallowed) = loop {
+        let mut apt = OmaApt::new(
+            vec![],
+            oma_apt_args.clone(),
+            dry_run,
+            apt_config.take().unwrap_or_else(AptConfig::new),
+        )?;
+
+        let pkgs = apt
+            .filter_pkgs(&[FilterMode::Upgradable])?
+            .filter_map(|pkg| {
+                let name = pkg.fullname(true);
+
+                if excluded.contains(&name) {
+                    return None;
+                }
+
+                if is_hold(&name, &selections) {
+                    debug!("{name} is held");
+                    return None;
+                }
+
+                let cand = pkg.candidate()?;
+
+                if !is_version_allowed(policy, &cand) {
+                    debug!("{name} {} is not allowed by policy", cand.version());
+                    return None;
+                }
+
+                OmaPackage::new(&cand, &pkg).ok()
+            })
+            .collect::<Vec<_>>();
+
+        if pkgs.is_empty() {
+            let reason = fl!("auto-upgrade-nothing");
+            info!("{reason}");
+            record_skipped(&sysroot, dry_run, reason)?;
+            return Ok(0);
+        }
+
+        apt.install(&pkgs, false)?;
+        apt.resolve(false, false)?;
+
+        // 自动升级不应删除任何软件包，也不处理 Essential 与 feature 相关的提示
+        let op = apt.summary(SummarySort::Operation, |_| false, |_| false)?;
+
+        // 依赖可能来自策略不允许的源
+        let disallowed = op
+            .install
+            .iter()
+            .filter(|entry| {
+                !apt.cache
+                    .get(entry.name())
+                    .and_then(|pkg| pkg.get_version(entry.new_version()))
+                    .is_some_and(|ver| is_version_allowed(policy, &ver))
+            })
+            .map(|entry| entry.name().to_string())
+            .collect::<HashSet<_>>();
+
+        let dropped = pulled_in_by(&apt, &pkgs, &disallowed);
+
+        if disallowed.is_empty() || dropped.is_empty() {
+            break (apt, pkgs, op, disallowed);
+        }
+
+        // 只跳过引入这些依赖的软件包，然后重新计算
+        for (pkg, deps) in dropped {
+            warn!(
+                "{}",
+                fl!(
+                    "auto-upgrade-dropped",
+                    pkg = pkg.as_str(),
+                    deps = deps.join(", ")
+                )
+            );
+            excluded.insert(pkg);
+        }
+    };
+
+    pin_blocked_tips(&apt);
+
+    if event::enabled() {
+        event::emit(&Event::Operation(&op));
+    }
+
+    let typ = SummaryType::AutoUpgrade(
+        pkgs.iter()
+            .map(|x| format!("{} {}", x.raw_pkg.fullname(true), x.version_raw.version()))
+            .collect::<Vec<_>>(),
+    );
+
+    let start_time = Local::now().timestamp();
+
+    let refuse = if !disallowed.is_empty() {
+        // 无法确定是哪个软件包引入了这些依赖
+        let mut disallowed = disallowed.into_iter().collect::<Vec<_>>();
+        disallowed.sort();
+
+        Some(fl!(
+            "auto-upgrade-not-allowed",
+            len = disallowed.len(),
+            pkgs = disallowed.join(", ")
+        ))
+    } else if !op.remove.is_empty() {
+        Some(fl!("auto-upgrade-would-remove", len = op.remove.len()))
+    } else if policy.max_download_size != 0 && op.total_download_size > policy.max_download_size {
+        Some(fl!(
+            "auto-upgrade-too-large",
+            size = HumanBytes(op.total_download_size).to_string(),
+            limit = HumanBytes(policy.max_download_size).to_string()
+        ))
+    } else {
+        None
+    };
+
+    if let Some(reason) = refuse {
+        warn!("{reason}");
+
+        // 什么都没有做，记录空的操作，以免回滚时误操作
+        write_history_entry(
+            empty_operation(),
+            typ,
+            {
+                let db = create_db_file(&sysroot)?;
+                connect_db(db, true)?
+            },
+            dry_run,
+            start_time,
+            Some(reason),
+            None,
+        )?;
+
+        return Ok(0);
+    }
+
+    apt.check_disk_size(&op)?;
+
+    let op_after = op.clone();
+    let len = op.install.len();
+    let term_log = apt.term_log();
+
+    let progress_manager: &dyn DownloadProgressControl = if event::enabled() {
+        &EventProgress::default()
+    } else {
+        &NoProgressBar::default()
+    };
+
+    let res = apt.commit(
+        &HTTP_CLIENT,
+        CommitDownloadConfig {
+            network_thread: Some(network_thread),
+            auth: &auth_config,
+            speed_limit,
+            mirrors: &mirrors,
+        },
+        progress_manager,
+        if event::enabled() {
+            Box::new(EventProgress::default())
+        } else {
+            Box::new(NoInstallProgressManager)
+        },
+        op,
+    );
+
+    write_history_entry(
+        op_after,
+        typ,
+        {
+            let db = create_db_file(&sysroot)?;
+            connect_db(db, true)?
+        },
+        dry_run,
+        start_time,
+        res.as_ref().err().map(|e| e.to_string()),
+        term_log.read(),
+    )?;
+
+    drop(fds);
+    res?;
+
+    success!("{}", fl!("auto-upgrade-done", len = len));
+
+    Ok(0)
+}
+
+/// Record a run which has done nothing in the history
+fn record_skipped(sysroot: &str, dry_run: bool, reason: String) -> Result<(), OutputError> {
+    write_history_entry(
+        empty_operation(),
+        SummaryType::AutoUpgrade(vec![]),
+        {
+            let db = create_db_file(sysroot)?;
+            connect_db(db, true)?
+        },
+        dry_run,
+        Local::now().timestamp(),
+        Some(reason),
+        None,
+    )?;
+
+    Ok(())
+}
+
+/// Requested packages which pull in any of the disallowed packages, with the packages they pull in
+///
+/// Follows Depends, Pre-Depends and Recommends through the packages marked for installation.
+fn pulled_in_by(
+    apt: &OmaApt,
+    pkgs: &[OmaPackage],
+    disallowed: &HashSet<String>,
+) -> Vec<(String, Vec<String>)> {
+    let mut res = vec![];
+
+    for pkg in pkgs {
+        let name = pkg.raw_pkg.fullname(true);
+        let Some(ver) = apt
+            .cache
+            .get(&name)
+            .and_then(|x| x.get_version(pkg.version_raw.version()))
+        else {
+            continue;
+        };
+
+        let mut seen = HashSet::from([name.clone()]);
+        let mut stack = vec![ver];
+        let mut pulled = vec![];
+
+        while let Some(ver) = stack.pop() {
+            let deps = ver
+                .dependencies()
+                .unwrap_or_default()
+                .into_iter()
+                .chain(ver.recommends().into_iter().flatten());
+
+            for dep in deps {
+                for target in dep.iter().flat_map(|x| x.all_targets()) {
+                    let parent = target.parent();
+                    let target_name = parent.fullname(true);
+
+                    let marked = parent.marked_install()
+                        || parent.marked_upgrade()
+                        || parent.marked_downgrade();
+
+                    if !marked || !seen.insert(target_name.clone()) {
+                        continue;
+                    }
+
+                    if disallowed.contains(&target_name) {
+                        pulled.push(target_name);
+                    }
+
+                    if let Some(ver) = parent.install_version() {
+                        stack.push(ver);
+                    }
+                }
+            }
+        }
+
+        if !pulled.is_empty() {
+            pulled.sort();
+            res.push((name, pulled));
+        }
+    }
+
+    res
+}
+
+/// The version is in a repository allowed by the policy
+fn is_version_allowed(policy: &AutoUpgradeConfig, ver: &Version) -> bool {
+    ver.package_files()
+        .any(|file| is_allowed(policy, file.origin(), [file.archive(), file.component()]))
+}
+
+/// A package file with the origin and sections is allowed by the policy
+fn is_allowed(
+    policy: &AutoUpgradeConfig,
+    origin: Option<&str>,
+    sections: [Option<&str>; 2],
+) -> bool {
+    let origin =
+        policy.origins.is_empty() || origin.is_some_and(|x| policy.origins.iter().any(|o| o == x));
+
+    let section = policy.sections.is_empty()
+        || sections
+            .into_iter()
+            .flatten()
+            .any(|x| policy.sections.iter().any(|s| s == x));
+
+    origin && section
+}
+
+fn parse_window(s: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = s.split_once('-')?;
+
+    Some((
+        NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?,
+        NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?,
+    ))
+}
+
+fn in_window(now: NaiveTime, (start, end): (NaiveTime, NaiveTime)) -> bool {
+    if start <= end {
+        now >= start && now < end
+    } else {
+        // 跨越午夜，例如 22:00-06:00
+        now >= start || now < end
+    }
+}
+
+#[test]
+fn test_blackout_window() {
+    let t = |s| NaiveTime::parse_from_str(s, "%H:%M").unwrap();
+
+    let day = parse_window("08:00-18:00").unwrap();
+    assert!(in_window(t("12:00"), day));
+    assert!(in_window(t("08:00"), day));
+    assert!(!in_window(t("18:00"), day));
+    assert!(!in_window(t("07:59"), day));
+
+    let night = parse_window("22:00 - 06:00").unwrap();
+    assert!(in_window(t("23:30"), night));
+    assert!(in_window(t("05:00"), night));
+    assert!(!in_window(t("12:00"), night));
+
+    assert!(parse_window("22:00").is_none());
+    assert!(parse_window("25:00-06:00").is_none());
+}
@@ -0,0 +1,128 @@
+use std::io::Write;
+
+use oma_pm::{
+    apt::{AptConfig, InstallEntry, InstallOperation, OmaApt, OmaAptArgs},
+    matches::PackagesMatcher,
+    pkginfo::OmaPackage,
+};
+use oma_utils::dpkg::dpkg_arch;
+
+use crate::{
+    console::style, error::OutputError, fl, table::oma_display_with_normal_output, HTTP_CLIENT,
+};
+
+use super::utils::handle_no_result;
+
+pub fn execute(
+    input: Vec<&str>,
+    sysroot: String,
+    another_apt_options: Vec<String>,
+    no_progress: bool,
+) -> Result<i32, OutputError> {
+    let oma_apt_args = OmaAptArgs::builder()
+        .another_apt_options(another_apt_options)
+        .sysroot(sysroot.clone())
+        .build();
+    let apt = OmaApt::new(vec![], oma_apt_args, false, AptConfig::new())?;
+
+    let arch = dpkg_arch(&sysroot)?;
+    let matcher = PackagesMatcher::builder()
+        .cache(&apt.cache)
+        .filter_candidate(true)
+        .native_arch(&arch)
+        .build();
+
+    let (pkgs, no_result) = matcher.match_pkgs_and_versions(input)?;
+
+    handle_no_result(sysroot, no_result, no_progress)?;
+
+    let mut changelogs = vec![];
+
+    for pkg in &pkgs {
+        let title = format!(
+            "{} {}",
+            pkg.raw_pkg.fullname(true),
+            pkg.version_raw.version()
+        );
+        changelogs.push((title, apt.changelog(&HTTP_CLIENT, pkg)?));
+    }
+
+    display_changelogs(changelogs, false)?;
+
+    Ok(0)
+}
+
+/// Show changelogs of the packages to be upgraded (for upgrade confirmation)
+pub fn show_upgrade_changelogs(apt: &OmaApt, install: &[InstallEntry]) -> Result<(), OutputError> {
+    let mut changelogs = vec![];
+
+    for entry in install
+        .iter()
+        .filter(|x| *x.op() == InstallOperation::Upgrade)
+    {
+        let title = format!(
+            "{} {} -> {}",
+            entry.name(),
+            entry.old_version().unwrap_or_default(),
+            entry.new_version()
+        );
+
+        let pkg = apt.cache.get(entry.name()).and_then(|pkg| {
+            Some(OmaPackage::new(
+                &pkg.get_version(entry.new_version())?,
+                &pkg,
+            ))
+        });
+
+        // 一个软件包获取更新日志失败不应该影响查看其它软件包的更新日志
+        let changelog = match pkg {
+            Some(Ok(pkg)) => apt
+                .changelog(&HTTP_CLIENT, &pkg)
+                .unwrap_or_else(|e| OutputError::from(e).to_string()),
+            _ => fl!("no-changelog", name = entry.name()),
+        };
+
+        changelogs.push((title, changelog));
+    }
+
+    display_changelogs(changelogs, true)
+}
+
+fn display_changelogs(
+    changelogs: Vec<(String, String)>,
+    is_question: bool,
+) -> Result<(), OutputError> {
+    let len = changelogs
+        .iter()
+        .map(|(_, changelog)| changelog.lines().count() + 2)
+        .sum();
+
+    let mut pager = oma_display_with_normal_output(is_question, len)?;
+    let mut out = pager.get_writer().map_err(|e| OutputError {
+        description: "Failed to get writer".to_string(),
+        source: Some(Box::new(e)),
+    })?;
+
+    for (title, changelog) in changelogs {
+        writeln!(
+            out,
+            "{}",
+            style(fl!("changelog-title", name = title)).bold()
+        )
+        .ok();
+
+        if changelog.trim().is_empty() {
+            writeln!(out, "{}\n", fl!("changelog-no-newer-entry")).ok();
+        } else {
+            writeln!(out, "{changelog}").ok();
+        }
+    }
+
+    drop(out);
+    pager.wait_for_exit().map_err(|e| OutputError {
+        description: "Failed to wait exit".to_string(),
+        source: Some(Box::new(e)),
+    })?;
+
+    Ok(())
+}
@@ -1,24 +1,190 @@
+use std::env;
 use std::error::Error;
 use std::io::stdout;
+use std::path::Path;
+use std::process::Command;
 
 use ahash::AHashMap;
+use dialoguer::{theme::ColorfulTheme, Confirm};
 use oma_console::due_to;
 use oma_console::print::Action;
-use oma_contents::searcher::{pure_search, ripgrep_search, Mode};
+use oma_contents::searcher::{search, Mode, QueryKind};
 use oma_contents::OmaContentsError;
 use oma_pm::apt::{AptConfig, OmaApt, OmaAptArgs};
-use tracing::error;
+use serde::Serialize;
+use tracing::{error, info};
 
 use crate::error::OutputError;
 use crate::table::PagerPrinter;
+use crate::utils::is_root;
 use crate::{color_formatter, fl};
 
+use super::utils::{is_terminal, CONTENTS_INDEX_PATH};
+
 const FILTER_JARO_NUM: u8 = 204;
 const APT_LIST_PATH: &str = "/var/lib/apt/lists";
 
 type IndexSet<T> = indexmap::IndexSet<T, ahash::RandomState>;
 
-pub fn execute(query: &str) -> Result<i32, OutputError> {
+#[derive(Debug, Serialize)]
+struct CnfEntry {
+    package: String,
+    path: String,
+    description: String,
+}
+
+pub struct CnfArgs<'a> {
+    /// The command which is not found
+    pub query: &'a str,
+    /// Arguments of the command, used to re-run it after installing
+    pub args: Vec<String>,
+    pub json: bool,
+    /// Offer to install the top match and re-run the command
+    pub interactive: bool,
+}
+
+pub fn execute(args: CnfArgs) -> Result<i32, OutputError> {
+    let CnfArgs {
+        query,
+        args,
+        json,
+        interactive,
+    } = args;
+
+    let entries = match find(query) {
+        Ok(entries) => entries,
+        Err(err) if json => {
+            // 与“没有找到”区分开，调用者不应把搜索失败当作空结果
+            println!(
+                "{}",
+                serde_json::json!({
+                    "error": err.to_string(),
+                    "source": err.source().map(|x| x.to_string()),
+                })
+            );
+
+            return Ok(1);
+        }
+        Err(err) => {
+            if !err.to_string().is_empty() {
+                error!("{err}");
+                if let Some(source) = err.source() {
+                    due_to!("{source}");
+                }
+            }
+
+            return Ok(127);
+        }
+    };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string(&entries.0).map_err(|e| OutputError {
+                description: e.to_string(),
+                source: None,
+            })?
+        );
+
+        return Ok(127);
+    }
+
+    let (entries, too_many) = entries;
+
+    if entries.is_empty() {
+        error!("{}", fl!("command-not-found", kw = query));
+        return Ok(127);
+    }
+
+    println!("{}\n", fl!("command-not-found-with-result", kw = query));
+
+    let top = entries[0].package.clone();
+
+    let table = entries
+        .into_iter()
+        .map(|x| {
+            (
+                color_formatter()
+                    .color_str(x.package, Action::Emphasis)
+                    .bold()
+                    .to_string(),
+                color_formatter()
+                    .color_str(x.path, Action::Secondary)
+                    .to_string(),
+                x.description,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut printer = PagerPrinter::new(stdout());
+    printer
+        .print_table(table, vec!["Name", "Path", "Description"])
+        .ok();
+
+    if too_many {
+        println!("\n{}", fl!("cnf-too-many-query"));
+        println!("{}", fl!("cnf-too-many-query-2", query = query));
+    }
+
+    if !interactive || !is_terminal() {
+        return Ok(127);
+    }
+
+    println!();
+
+    let install = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(fl!("cnf-install-prompt", pkg = top.as_str(), cmd = query))
+        .default(true)
+        .interact()
+        .unwrap_or(false);
+
+    if !install || !install_pkg(&top)? {
+        return Ok(127);
+    }
+
+    info!("{}", fl!("cnf-rerun", cmd = query));
+
+    let status = Command::new(query)
+        .args(args)
+        .status()
+        .map_err(|e| OutputError {
+            description: fl!("cnf-rerun-failed", cmd = query),
+            source: Some(Box::new(e)),
+        })?;
+
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Install the package with a new oma process, return whether it succeeded
+fn install_pkg(pkg: &str) -> Result<bool, OutputError> {
+    let exe = env::current_exe().map_err(|e| OutputError {
+        description: e.to_string(),
+        source: None,
+    })?;
+
+    // 非 root 用户在终端中优先使用 sudo，否则交给 oma install 自己提权
+    let mut cmd = if !is_root() && which::which("sudo").is_ok() {
+        let mut cmd = Command::new("sudo");
+        cmd.arg(exe);
+        cmd
+    } else {
+        Command::new(exe)
+    };
+
+    let status = cmd
+        .arg("install")
+        .arg(pkg)
+        .status()
+        .map_err(|e| OutputError {
+            description: e.to_string(),
+            source: None,
+        })?;
+
+    Ok(status.success())
+}
+
+/// Find packages providing the command, and whether there are too many results
+fn find(query: &str) -> Result<(Vec<CnfEntry>, bool), OutputError> {
     let mut res = IndexSet::with_hasher(ahash::RandomState::new());
 
     let cb = |line| {
@@ -27,109 +193,80 @@ pub fn execute(query: &str) -> Result<i32, OutputError> {
         }
     };
 
-    let search_res = if which::which("rg").is_ok() {
-        ripgrep_search(APT_LIST_PATH, Mode::BinProvides, query, cb)
-    } else {
-        pure_search(APT_LIST_PATH, Mode::BinProvides, query, cb)
-    };
+    let search_res = search(
+        APT_LIST_PATH,
+        Path::new("/").join(CONTENTS_INDEX_PATH),
+        Mode::BinProvides,
+        query,
+        QueryKind::Plain,
+        cb,
+    );
 
     match search_res {
-        Ok(()) if res.is_empty() => {
-            error!("{}", fl!("command-not-found", kw = query));
-        }
-        Ok(()) => {
-            let apt_config = AptConfig::new();
-            let oma_apt_args = OmaAptArgs::builder().build();
-            let apt = OmaApt::new(vec![], oma_apt_args, false, apt_config)?;
-
-            let mut jaro = jaro_nums(res, query);
+        Ok(()) => {}
+        Err(OmaContentsError::NoResult) => return Ok((vec![], false)),
+        Err(e) => return Err(e.into()),
+    }
 
-            let all_match = jaro
-                .iter()
-                .filter(|x| x.2 == u8::MAX)
-                .map(|x| x.to_owned())
-                .collect::<Vec<_>>();
+    if res.is_empty() {
+        return Ok((vec![], false));
+    }
 
-            if !all_match.is_empty() {
-                jaro = all_match;
-            }
+    let apt_config = AptConfig::new();
+    let oma_apt_args = OmaAptArgs::builder().build();
+    let apt = OmaApt::new(vec![], oma_apt_args, false, apt_config)?;
 
-            let mut res = vec![];
+    let mut jaro = jaro_nums(res, query);
 
-            let mut too_many = false;
+    let all_match = jaro
+        .iter()
+        .filter(|x| x.2 == u8::MAX)
+        .map(|x| x.to_owned())
+        .collect::<Vec<_>>();
 
-            let mut map: AHashMap<String, String> = AHashMap::new();
+    if !all_match.is_empty() {
+        jaro = all_match;
+    }
 
-            for (pkg, file, jaro) in jaro {
-                if res.len() == 10 {
-                    too_many = true;
-                    break;
-                }
+    let mut res = vec![];
 
-                if jaro < FILTER_JARO_NUM {
-                    break;
-                }
+    let mut too_many = false;
 
-                let desc = if let Some(desc) = map.get(&pkg) {
-                    desc.to_string()
-                } else if let Some(pkg) = apt.cache.get(&pkg) {
-                    let desc = pkg
-                        .candidate()
-                        .and_then(|x| x.summary())
-                        .unwrap_or_else(|| "no description.".to_string());
-
-                    map.insert(pkg.fullname(true), desc.to_string());
-
-                    desc
-                } else {
-                    continue;
-                };
-
-                let entry = (
-                    color_formatter()
-                        .color_str(pkg, Action::Emphasis)
-                        .bold()
-                        .to_string(),
-                    color_formatter()
-                        .color_str(file, Action::Secondary)
-                        .to_string(),
-                    desc,
-                );
-
-                res.push(entry);
-            }
+    let mut map: AHashMap<String, String> = AHashMap::new();
 
-            if res.is_empty() {
-                error!("{}", fl!("command-not-found", kw = query));
-            } else {
-                println!("{}\n", fl!("command-not-found-with-result", kw = query));
-                let mut printer = PagerPrinter::new(stdout());
-                printer
-                    .print_table(res, vec!["Name", "Path", "Description"])
-                    .ok();
-
-                if too_many {
-                    println!("\n{}", fl!("cnf-too-many-query"));
-                    println!("{}", fl!("cnf-too-many-query-2", query = query));
-                }
-            }
+    for (pkg, file, jaro) in jaro {
+        if res.len() == 10 {
+            too_many = true;
+            break;
         }
-        Err(e) => {
-            if let OmaContentsError::NoResult = e {
-                error!("{}", fl!("command-not-found", kw = query));
-            } else {
-                let err = OutputError::from(e);
-                if !err.to_string().is_empty() {
-                    error!("{err}");
-                    if let Some(source) = err.source() {
-                        due_to!("{source}");
-                    }
-                }
-            }
+
+        if jaro < FILTER_JARO_NUM {
+            break;
         }
+
+        let desc = if let Some(desc) = map.get(&pkg) {
+            desc.to_string()
+        } else if let Some(pkg) = apt.cache.get(&pkg) {
+            let desc = pkg
+                .candidate()
+                .and_then(|x| x.summary())
+                .unwrap_or_else(|| "no description.".to_string());
+
+            map.insert(pkg.fullname(true), desc.to_string());
+
+            desc
+        } else {
+            continue;
+        };
+
+        res.push(CnfEntry {
+            package: pkg,
+            path: file,
+            description: desc,
+        });
     }
 
-    Ok(127)
+    Ok((res, too_many))
 }
 
 fn jaro_nums(input: IndexSet<(String, String)>, query: &str) -> Vec<(String, String, u8)> {
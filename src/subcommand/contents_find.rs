@@ -4,7 +4,7 @@ use crate::table::oma_display_with_normal_output;
 use indexmap::IndexSet;
 use oma_console::indicatif::ProgressBar;
 use oma_console::pb::spinner_style;
-use oma_contents::searcher::Mode;
+use oma_contents::searcher::{Mode, QueryKind};
 use std::io::{stdout, Write};
 
 use super::utils::contents_search;
@@ -13,6 +13,7 @@ pub fn execute(
     mode: &str,
     is_bin: bool,
     input: &str,
+    kind: QueryKind,
     no_progress: bool,
     sysroot: String,
     no_pager: bool,
@@ -52,7 +53,7 @@ pub fn execute(
         }
     };
 
-    contents_search(sysroot, mode, input, cb)?;
+    contents_search(sysroot, mode, input, kind, cb)?;
 
     if let Some(pb) = &pb {
         pb.finish_and_clear();
@@ -0,0 +1,259 @@
+use std::{
+    env::current_exe,
+    io::{self, BufRead, BufReader},
+    os::fd::AsRawFd,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use oma_utils::dbus::check_authorization;
+use tokio::runtime::Handle;
+use tracing::{debug, info};
+use zbus::{connection, fdo, interface, message::Header, Connection, SignalContext};
+
+use crate::{error::OutputError, utils::root, RT};
+
+/// `io.aosc.Oma` is owned by every oma process changing the system, see `OmaApt`
+const SERVICE_NAME: &str = "io.aosc.Oma.Daemon";
+const OBJECT_PATH: &str = "/io/aosc/Oma";
+
+const ACTION_REFRESH: &str = "io.aosc.oma.refresh";
+const ACTION_MANAGE: &str = "io.aosc.oma.manage";
+
+/// Run the `io.aosc.Oma.Daemon` D-Bus service
+///
+/// Every job runs a new oma process with `--progress-fd`, its events are forwarded as
+/// `Progress` signals. `session` serves on the session bus without polkit, for testing.
+pub fn execute(session: bool) -> Result<i32, OutputError> {
+    if !session {
+        root()?;
+    }
+
+    RT.block_on(async move {
+        let builder = if session {
+            connection::Builder::session()
+        } else {
+            connection::Builder::system()
+        };
+
+        let _conn = builder
+            .and_then(|x| x.name(SERVICE_NAME))
+            .and_then(|x| x.serve_at(OBJECT_PATH, Manager::new(session)))
+            .map_err(dbus_error)?
+            .build()
+            .await
+            .map_err(dbus_error)?;
+
+        info!("oma D-Bus service is running");
+
+        std::future::pending::<()>().await;
+
+        Ok(0)
+    })
+}
+
+fn dbus_error(e: zbus::Error) -> OutputError {
+    OutputError {
+        description: "Failed to start D-Bus service".to_string(),
+        source: Some(Box::new(e)),
+    }
+}
+
+struct Manager {
+    session: bool,
+    next_job: AtomicU32,
+    running: Arc<Mutex<Option<u32>>>,
+}
+
+impl Manager {
+    fn new(session: bool) -> Self {
+        Self {
+            session,
+            next_job: AtomicU32::new(1),
+            running: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn authorize(
+        &self,
+        conn: &Connection,
+        header: &Header<'_>,
+        action: &str,
+    ) -> fdo::Result<()> {
+        if self.session {
+            return Ok(());
+        }
+
+        let sender = header
+            .sender()
+            .ok_or_else(|| fdo::Error::AccessDenied("Unknown sender".to_string()))?;
+
+        match check_authorization(conn, sender.as_str(), action).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(fdo::Error::AccessDenied(format!(
+                "{sender} is not authorized for {action}"
+            ))),
+            Err(e) => Err(fdo::Error::Failed(e.to_string())),
+        }
+    }
+
+    fn start_job(&self, ctxt: SignalContext<'_>, args: Vec<String>) -> fdo::Result<u32> {
+        let mut running = self.running.lock().unwrap();
+
+        if let Some(job) = *running {
+            return Err(fdo::Error::Failed(format!("Job {job} is running")));
+        }
+
+        let job = self.next_job.fetch_add(1, Ordering::SeqCst);
+        let (reader, writer) = io::pipe().map_err(io_error)?;
+
+        // 子进程需要继承管道的写端
+        if unsafe { libc::fcntl(writer.as_raw_fd(), libc::F_SETFD, 0) } < 0 {
+            return Err(io_error(io::Error::last_os_error()));
+        }
+
+        debug!("Start job {job}: {args:?}");
+
+        let mut child = Command::new(current_exe().map_err(io_error)?)
+            .arg("--progress-fd")
+            .arg(writer.as_raw_fd().to_string())
+            .args(&args)
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(io_error)?;
+
+        drop(writer);
+        *running = Some(job);
+
+        let running = self.running.clone();
+        let ctxt = ctxt.to_owned();
+        let handle = Handle::current();
+
+        tokio::task::spawn_blocking(move || {
+            for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                handle.block_on(Self::progress(&ctxt, job, &line)).ok();
+            }
+
+            let code = child.wait().ok().and_then(|x| x.code()).unwrap_or(1);
+
+            debug!("Job {job} exited with {code}");
+
+            *running.lock().unwrap() = None;
+            handle.block_on(Self::finished(&ctxt, job, code)).ok();
+        });
+
+        Ok(job)
+    }
+}
+
+#[interface(name = "io.aosc.Oma1.Manager")]
+impl Manager {
+    /// Refresh repository metadata, returns the job ID
+    async fn refresh(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<u32> {
+        self.authorize(conn, &header, ACTION_REFRESH).await?;
+        self.start_job(ctxt, vec!["refresh".to_string()])
+    }
+
+    /// Install packages, returns the job ID
+    async fn install(
+        &self,
+        packages: Vec<String>,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<u32> {
+        self.authorize(conn, &header, ACTION_MANAGE).await?;
+        self.start_job(ctxt, with_packages(&["install", "--yes"], packages))
+    }
+
+    /// Remove packages, also remove their configuration files if `purge`, returns the job ID
+    async fn remove(
+        &self,
+        packages: Vec<String>,
+        purge: bool,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<u32> {
+        self.authorize(conn, &header, ACTION_MANAGE).await?;
+
+        let cmd = if purge { "purge" } else { "remove" };
+        self.start_job(ctxt, with_packages(&[cmd, "--yes"], packages))
+    }
+
+    /// Upgrade the system, returns the job ID
+    async fn upgrade(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<u32> {
+        self.authorize(conn, &header, ACTION_MANAGE).await?;
+        self.start_job(ctxt, vec!["upgrade".to_string(), "--yes".to_string()])
+    }
+
+    /// Undo all operations after the history ID or date, same as `oma undo --to`, returns the job ID
+    async fn undo(
+        &self,
+        to: String,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<u32> {
+        self.authorize(conn, &header, ACTION_MANAGE).await?;
+
+        let args = ["undo", "--yes", "--to", &to].map(|x| x.to_string());
+        self.start_job(ctxt, args.to_vec())
+    }
+
+    /// Search packages, returns the result in the format of `oma search --json`
+    async fn search(&self, query: String) -> fdo::Result<String> {
+        let output = tokio::task::spawn_blocking(move || {
+            Command::new(current_exe()?)
+                .args(["search", "--json", "--", &query])
+                .stdin(Stdio::null())
+                .output()
+        })
+        .await
+        .map_err(|e| fdo::Error::Failed(e.to_string()))?
+        .map_err(io_error)?;
+
+        if !output.status.success() {
+            return Err(fdo::Error::Failed(
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            ));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_string())
+    }
+
+    /// An event of the job, in the format of `oma --progress-fd`
+    #[zbus(signal)]
+    async fn progress(ctxt: &SignalContext<'_>, job: u32, event: &str) -> zbus::Result<()>;
+
+    /// The job exited with `code`
+    #[zbus(signal)]
+    async fn finished(ctxt: &SignalContext<'_>, job: u32, code: i32) -> zbus::Result<()>;
+}
+
+fn with_packages(args: &[&str], packages: Vec<String>) -> Vec<String> {
+    args.iter()
+        .map(|x| x.to_string())
+        .chain(std::iter::once("--".to_string()))
+        .chain(packages)
+        .collect()
+}
+
+fn io_error(e: io::Error) -> fdo::Error {
+    fdo::Error::IOError(e.to_string())
+}
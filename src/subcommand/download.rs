@@ -11,7 +11,10 @@ use tracing::{error, info};
 
 use crate::pb::{NoProgressBar, OmaMultiProgressBar};
 use crate::utils::is_root;
-use crate::{error::OutputError, subcommand::utils::handle_no_result};
+use crate::{
+    error::OutputError,
+    subcommand::utils::{enabled_mirrors, handle_no_result},
+};
 use crate::{fl, OmaArgs, HTTP_CLIENT};
 
 pub fn execute(
@@ -22,6 +25,7 @@ pub fn execute(
     let OmaArgs {
         dry_run,
         network_thread,
+        speed_limit,
         no_progress,
         ..
     } = oma_args;
@@ -61,6 +65,8 @@ pub fn execute(
             network_thread: Some(network_thread),
             download_dir: Some(&path),
             auth: &AuthConfig::system("/")?,
+            speed_limit,
+            mirrors: &enabled_mirrors("/"),
         },
         dry_run,
         progress_manager,
@@ -17,6 +17,7 @@ pub fn execute(oma_args: OmaArgs, sysroot: String) -> Result<i32, OutputError> {
     let OmaArgs {
         dry_run,
         network_thread,
+        speed_limit,
         no_progress,
         no_check_dbus,
         protect_essentials: protect_essential,
@@ -45,6 +46,7 @@ pub fn execute(oma_args: OmaArgs, sysroot: String) -> Result<i32, OutputError> {
         request_type: SummaryType::FixBroken,
         no_fixbroken: false,
         network_thread,
+        speed_limit,
         no_progress,
         sysroot,
         fix_dpkg_status: true,
@@ -1,11 +1,12 @@
 use anyhow::anyhow;
 use apt_auth_config::AuthConfig;
-use chrono::{Local, LocalResult, TimeZone};
+use chrono::{Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
 use dialoguer::{theme::ColorfulTheme, Select};
 use oma_history::{
-    connect_db, find_history_by_id, list_history, HistoryListEntry, SummaryType, DATABASE_PATH,
+    connect_db, find_history_by_id, find_history_detail_by_id, find_term_log_by_id, list_history,
+    HistoryError, HistoryListEntry, SummaryType, DATABASE_PATH,
 };
-use oma_pm::apt::{AptConfig, InstallOperation, OmaAptArgs};
+use oma_pm::apt::{AptConfig, InstallOperation, OmaAptArgs, OmaOperation};
 use oma_pm::matches::PackagesMatcher;
 use oma_pm::pkginfo::PtrIsNone;
 use oma_pm::{
@@ -13,13 +14,17 @@ use oma_pm::{
     pkginfo::OmaPackage,
 };
 use oma_utils::dpkg::dpkg_arch;
+use tracing::{info, warn};
 
+use std::collections::HashMap;
+use std::io::Write;
 use std::path::Path;
 use std::{borrow::Cow, sync::atomic::Ordering};
 
 use crate::{
     error::OutputError,
-    table::table_for_history_pending,
+    fl,
+    table::{oma_display_with_normal_output, table_for_history_pending},
     utils::{dbus_check, root},
     ALLOWCTRLC,
 };
@@ -51,21 +56,63 @@ pub fn execute_history(sysroot: String) -> Result<i32, OutputError> {
         let selected = &list[selected];
         let id = selected.id;
         let op = find_history_by_id(&conn, id)?;
+        let detail = find_history_detail_by_id(&conn, id)?;
         let install = &op.install;
         let remove = &op.remove;
         let disk_size = &op.disk_size;
 
-        table_for_history_pending(install, remove, disk_size)?;
+        table_for_history_pending(install, remove, disk_size, &detail)?;
     }
 }
 
-pub fn execute_undo(oma_args: OmaArgs, sysroot: String) -> Result<i32, OutputError> {
+pub fn execute_history_show(id: i64, log: bool, sysroot: String) -> Result<i32, OutputError> {
+    let conn = connect_db(Path::new(&sysroot).join(DATABASE_PATH), false)?;
+
+    if !log {
+        let op = find_history_by_id(&conn, id)?;
+        let detail = find_history_detail_by_id(&conn, id)?;
+        table_for_history_pending(&op.install, &op.remove, &op.disk_size, &detail)?;
+
+        return Ok(0);
+    }
+
+    let Some(log) = find_term_log_by_id(&conn, id)? else {
+        return Err(OutputError {
+            description: fl!("history-no-term-log", id = id),
+            source: None,
+        });
+    };
+
+    let mut pager = oma_display_with_normal_output(false, log.lines().count())?;
+    let mut out = pager.get_writer().map_err(|e| OutputError {
+        description: "Failed to get writer".to_string(),
+        source: Some(Box::new(e)),
+    })?;
+
+    write!(out, "{log}").ok();
+
+    drop(out);
+    pager.wait_for_exit().map_err(|e| OutputError {
+        description: "Failed to wait exit".to_string(),
+        source: Some(Box::new(e)),
+    })?;
+
+    Ok(0)
+}
+
+pub fn execute_undo(
+    oma_args: OmaArgs,
+    sysroot: String,
+    to: Option<&str>,
+    yes: bool,
+) -> Result<i32, OutputError> {
     root()?;
     lock_oma()?;
 
     let OmaArgs {
         dry_run: _,
         network_thread,
+        speed_limit,
         no_progress,
         no_check_dbus,
         protect_essentials: protect_essential,
@@ -74,7 +121,7 @@ pub fn execute_undo(oma_args: OmaArgs, sysroot: String) -> Result<i32, OutputErr
     } = oma_args;
 
     let fds = if !no_check_dbus {
-        Some(dbus_check(false)?)
+        Some(dbus_check(yes)?)
     } else {
         no_check_dbus_warn();
         None
@@ -83,19 +130,40 @@ pub fn execute_undo(oma_args: OmaArgs, sysroot: String) -> Result<i32, OutputErr
     let conn = connect_db(Path::new(&sysroot).join(DATABASE_PATH), false)?;
 
     let list = list_history(&conn)?;
-    let display_list = format_summary_log(&list, true);
-    let selected = dialoguer_select_history(
-        &display_list
-            .clone()
-            .into_iter()
-            .map(|x| x.0)
-            .collect::<Vec<_>>(),
-        0,
-    )?;
-
-    let selected = &list[display_list[selected].1];
-    let id = selected.id;
-    let op = find_history_by_id(&conn, id)?;
+
+    let ops = if let Some(to) = to {
+        let after = history_after(&list, to)?;
+
+        if after.is_empty() {
+            info!("{}", fl!("undo-nothing-to-do"));
+            return Ok(0);
+        }
+
+        // 失败的操作可能只应用了一部分，按记录的内容回滚可能与实际情况不符
+        for i in after.iter().filter(|x| !x.is_success) {
+            warn!("{}", fl!("undo-failed-included", id = i.id));
+        }
+
+        // list_history 按 id 倒序排列，需要从最早的操作开始合并
+        after
+            .iter()
+            .rev()
+            .map(|x| find_history_by_id(&conn, x.id))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        let display_list = format_summary_log(&list, true);
+        let selected = dialoguer_select_history(
+            &display_list
+                .clone()
+                .into_iter()
+                .map(|x| x.0)
+                .collect::<Vec<_>>(),
+            0,
+        )?;
+
+        let selected = &list[display_list[selected].1];
+        vec![find_history_by_id(&conn, selected.id)?]
+    };
 
     let oma_apt_args = OmaAptArgs::builder()
         .sysroot(sysroot.clone())
@@ -103,27 +171,14 @@ pub fn execute_undo(oma_args: OmaArgs, sysroot: String) -> Result<i32, OutputErr
         .build();
     let mut apt = OmaApt::new(vec![], oma_apt_args, false, AptConfig::new())?;
 
-    let mut glob = vec![];
-    let mut install = vec![];
+    let UndoTargets {
+        remove: glob,
+        install,
+        unknown,
+    } = undo_targets(&ops);
 
-    if !op.install.is_empty() {
-        for i in &op.install {
-            match i.op() {
-                InstallOperation::Default | InstallOperation::Download => unreachable!(),
-                InstallOperation::Install => glob.push(i.name()),
-                InstallOperation::ReInstall => continue,
-                InstallOperation::Upgrade => install.push((i.name(), i.old_version().unwrap())),
-                InstallOperation::Downgrade => install.push((i.name(), i.old_version().unwrap())),
-            }
-        }
-    }
-
-    if !op.remove.is_empty() {
-        for i in &op.remove {
-            if let Some(ver) = i.version() {
-                install.push((i.name(), ver));
-            }
-        }
+    for i in unknown {
+        warn!("{}", fl!("undo-old-version-unknown", pkg = i));
     }
 
     let arch = dpkg_arch(&sysroot)?;
@@ -149,16 +204,21 @@ pub fn execute_undo(oma_args: OmaArgs, sysroot: String) -> Result<i32, OutputErr
 
     let pkgs = apt.filter_pkgs(&[FilterMode::Default])?.collect::<Vec<_>>();
 
+    let mut unavailable = vec![];
+
     let install = install
         .iter()
         .filter_map(|(pkg, ver)| {
-            let pkg = pkgs.iter().find(move |y| &y.name() == pkg);
+            let res = pkgs
+                .iter()
+                .find(|y| &y.name() == pkg)
+                .and_then(|x| Some((x, x.get_version(ver)?)));
 
-            if let Some(pkg) = pkg {
-                Some((pkg, pkg.get_version(ver)?))
-            } else {
-                None
+            if res.is_none() {
+                unavailable.push(format!("{pkg} ({ver})"));
             }
+
+            res
         })
         .map(|(x, y)| OmaPackage::new(&y, x))
         .collect::<Result<Vec<OmaPackage>, PtrIsNone>>()
@@ -167,6 +227,11 @@ pub fn execute_undo(oma_args: OmaArgs, sysroot: String) -> Result<i32, OutputErr
             source: None,
         })?;
 
+    // 镜像源中已经没有的版本无法回滚，告知用户后继续回滚其余软件包
+    for i in &unavailable {
+        warn!("{}", fl!("undo-version-unavailable", pkg = i.as_str()));
+    }
+
     apt.install(&install, false)?;
 
     let auth_config = AuthConfig::system(&sysroot)?;
@@ -177,12 +242,13 @@ pub fn execute_undo(oma_args: OmaArgs, sysroot: String) -> Result<i32, OutputErr
         request_type: SummaryType::Undo,
         no_fixbroken: false,
         network_thread,
+        speed_limit,
         no_progress,
         sysroot,
         fix_dpkg_status: true,
         protect_essential,
         client: &HTTP_CLIENT,
-        yes: false,
+        yes,
         remove_config: false,
         auth_config: &auth_config,
     };
@@ -194,6 +260,104 @@ pub fn execute_undo(oma_args: OmaArgs, sysroot: String) -> Result<i32, OutputErr
     Ok(code)
 }
 
+/// History entries after `to` (history id or date), newest first
+fn history_after<'a>(
+    list: &'a [HistoryListEntry],
+    to: &str,
+) -> Result<Vec<&'a HistoryListEntry>, OutputError> {
+    if let Ok(id) = to.parse::<i64>() {
+        if list.iter().all(|x| x.id != id) {
+            return Err(HistoryError::NoResult(id).into());
+        }
+
+        return Ok(list.iter().filter(|x| x.id > id).collect());
+    }
+
+    let time = parse_date(to).ok_or_else(|| OutputError {
+        description: fl!("undo-invalid-target", target = to),
+        source: None,
+    })?;
+
+    Ok(list.iter().filter(|x| x.time > time).collect())
+}
+
+/// Parse `%Y-%m-%d %H:%M:%S` or `%Y-%m-%d` (00:00:00 of this day) in local time
+fn parse_date(s: &str) -> Option<i64> {
+    let dt = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|x| x.and_time(NaiveTime::MIN)))
+        .ok()?;
+
+    Local
+        .from_local_datetime(&dt)
+        .earliest()
+        .map(|x| x.timestamp())
+}
+
+/// 软件包在第一个操作之前的状态
+enum Before<'a> {
+    NotInstalled,
+    Version(&'a str),
+    /// 历史记录中没有旧版本号
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct UndoTargets<'a> {
+    /// Packages to remove
+    remove: Vec<&'a str>,
+    /// Packages and versions to install
+    install: Vec<(&'a str, &'a str)>,
+    /// Packages whose previous version was not recorded, they will not be rolled back
+    unknown: Vec<&'a str>,
+}
+
+/// 将多个操作（按时间先后排列）合并为一个反向操作
+fn undo_targets(ops: &[OmaOperation]) -> UndoTargets<'_> {
+    let mut before: HashMap<&str, Before> = HashMap::new();
+
+    for op in ops {
+        for i in &op.install {
+            let state = match i.op() {
+                InstallOperation::Install => Before::NotInstalled,
+                InstallOperation::Upgrade | InstallOperation::Downgrade => i
+                    .old_version()
+                    .map(Before::Version)
+                    .unwrap_or(Before::Unknown),
+                InstallOperation::ReInstall
+                | InstallOperation::Default
+                | InstallOperation::Download => continue,
+            };
+
+            before.entry(i.name()).or_insert(state);
+        }
+
+        for i in &op.remove {
+            let state = i.version().map(Before::Version).unwrap_or(Before::Unknown);
+            before.entry(i.name()).or_insert(state);
+        }
+    }
+
+    let mut res = UndoTargets {
+        remove: vec![],
+        install: vec![],
+        unknown: vec![],
+    };
+
+    for (name, state) in before {
+        match state {
+            Before::Version(ver) => res.install.push((name, ver)),
+            Before::NotInstalled => res.remove.push(name),
+            Before::Unknown => res.unknown.push(name),
+        }
+    }
+
+    res.remove.sort_unstable();
+    res.install.sort_unstable();
+    res.unknown.sort_unstable();
+
+    res
+}
+
 fn dialoguer_select_history(
     display_list: &[String],
     old_selected: usize,
@@ -317,9 +481,21 @@ fn format_summary_log(list: &[HistoryListEntry], undo: bool) -> Vec<(String, usi
                     )
                 }
                 SummaryType::Undo => format!("Undone [{date}]"),
+                SummaryType::AutoUpgrade(v) if v.len() > 3 => format!(
+                    "{}Automatically upgraded {} ... (and {} more) [{date}]",
+                    format_success(log.is_success),
+                    v[..3].join(" "),
+                    v.len() - 3
+                ),
+                SummaryType::AutoUpgrade(v) => format!(
+                    "{}Automatically upgraded {} [{date}]",
+                    format_success(log.is_success),
+                    v.join(" "),
+                ),
                 SummaryType::Changes => "Change packages".to_string(),
             };
 
+            let s = format!("#{} {s}", log.id);
             let s = select_tui_display_msg(&s, false).to_string();
 
             (s, index)
@@ -347,3 +523,160 @@ fn format_success(is_success: bool) -> &'static str {
         "[FAIL] "
     }
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::{Local, TimeZone};
+    use oma_history::{HistoryListEntry, SummaryType};
+    use oma_pm::apt::{InstallEntry, InstallOperation, OmaOperation, RemoveEntry};
+
+    use super::{history_after, parse_date, undo_targets, UndoTargets};
+
+    fn install(name: &str, old: Option<&str>, new: &str, op: InstallOperation) -> InstallEntry {
+        InstallEntry::builder()
+            .name(name.to_string())
+            .name_without_arch(name.to_string())
+            .maybe_old_version(old.map(|x| x.to_string()))
+            .new_version(new.to_string())
+            .new_size(0)
+            .pkg_urls(vec![])
+            .arch("amd64".to_string())
+            .download_size(0)
+            .op(op)
+            .index(0)
+            .build()
+    }
+
+    fn remove(name: &str, ver: Option<&str>) -> RemoveEntry {
+        RemoveEntry::new(
+            name.to_string(),
+            ver.map(|x| x.to_string()),
+            0,
+            vec![],
+            "amd64".to_string(),
+            0,
+        )
+    }
+
+    fn op(install: Vec<InstallEntry>, remove: Vec<RemoveEntry>) -> OmaOperation {
+        OmaOperation {
+            install,
+            remove,
+            disk_size: ("+".into(), 0),
+            autoremovable: (0, 0),
+            total_download_size: 0,
+        }
+    }
+
+    #[test]
+    fn test_undo_targets() {
+        let ops = vec![
+            op(
+                vec![
+                    install("foo", None, "1.0", InstallOperation::Install),
+                    install("bar", Some("1.0"), "2.0", InstallOperation::Upgrade),
+                ],
+                vec![],
+            ),
+            op(
+                vec![
+                    install("foo", Some("1.0"), "1.1", InstallOperation::Upgrade),
+                    install("bar", Some("2.0"), "3.0", InstallOperation::Upgrade),
+                ],
+                vec![remove("baz", Some("0.5"))],
+            ),
+            op(
+                vec![
+                    install("baz", None, "0.6", InstallOperation::Install),
+                    install("qux", None, "2.0", InstallOperation::Downgrade),
+                    install("quux", None, "1.0", InstallOperation::ReInstall),
+                ],
+                vec![remove("foo", Some("1.1")), remove("corge", None)],
+            ),
+        ];
+
+        assert_eq!(
+            undo_targets(&ops),
+            UndoTargets {
+                // foo 在这些操作之前没有安装
+                remove: vec!["foo"],
+                install: vec![("bar", "1.0"), ("baz", "0.5")],
+                unknown: vec!["corge", "qux"],
+            }
+        );
+
+        // 只撤销最后一个操作
+        assert_eq!(
+            undo_targets(&ops[2..]),
+            UndoTargets {
+                remove: vec!["baz"],
+                install: vec![("foo", "1.1")],
+                unknown: vec!["corge", "qux"],
+            }
+        );
+    }
+
+    #[test]
+    fn test_history_after() {
+        let entry = |id, time, is_success| HistoryListEntry {
+            id,
+            t: SummaryType::Changes,
+            time,
+            is_success,
+        };
+
+        let day = |d| {
+            Local
+                .with_ymd_and_hms(2024, 1, d, 0, 0, 0)
+                .unwrap()
+                .timestamp()
+        };
+
+        // list_history 按 id 倒序排列
+        let list = vec![
+            entry(5, day(3) + 60, true),
+            entry(4, day(2) + 60, false),
+            entry(3, day(1) + 60, true),
+        ];
+
+        let ids = |to| {
+            history_after(&list, to)
+                .unwrap()
+                .iter()
+                .map(|x| x.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids("3"), vec![5, 4]);
+        assert_eq!(ids("5"), Vec::<i64>::new());
+        assert_eq!(ids("2024-01-02"), vec![5, 4]);
+        assert_eq!(ids("2024-01-02 00:01:00"), vec![5]);
+        assert!(history_after(&list, "6").is_err());
+        assert!(history_after(&list, "last week").is_err());
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(
+            parse_date("2024-01-02"),
+            Some(
+                Local
+                    .with_ymd_and_hms(2024, 1, 2, 0, 0, 0)
+                    .unwrap()
+                    .timestamp()
+            )
+        );
+        assert_eq!(
+            parse_date("2024-01-02 03:04:05"),
+            Some(
+                Local
+                    .with_ymd_and_hms(2024, 1, 2, 3, 4, 5)
+                    .unwrap()
+                    .timestamp()
+            )
+        );
+        assert_eq!(parse_date("2024-13-01"), None);
+        assert_eq!(parse_date("2024-01-02 25:00:00"), None);
+        assert_eq!(parse_date("yesterday"), None);
+    }
+}
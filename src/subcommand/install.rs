@@ -33,6 +33,7 @@ pub fn execute(
     let OmaArgs {
         dry_run,
         network_thread,
+        speed_limit,
         no_progress,
         no_check_dbus,
         protect_essentials: protect_essential,
@@ -57,6 +58,7 @@ pub fn execute(
             dry_run,
             no_progress,
             limit: network_thread,
+            speed_limit,
             sysroot: &args.sysroot,
             _refresh_topics: !args.no_refresh_topic,
             config: &apt_config,
@@ -125,6 +127,7 @@ pub fn execute(
         ),
         no_fixbroken: args.no_fixbroken,
         network_thread,
+        speed_limit,
         no_progress,
         sysroot: args.sysroot,
         fix_dpkg_status: true,
@@ -0,0 +1,536 @@
+use std::{
+    fs,
+    io::{self, stdout, Write},
+    path::{Path, PathBuf},
+};
+
+use apt_auth_config::AuthConfig;
+use oma_console::success;
+use oma_history::SummaryType;
+use oma_pm::{
+    apt::{AptConfig, FilterMode, OmaApt, OmaAptArgs},
+    matches::PackagesMatcher,
+};
+use oma_utils::dpkg::{dpkg_arch, get_selections, mark_version_status};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    error::OutputError,
+    fl,
+    utils::{dbus_check, root},
+    OmaArgs, HTTP_CLIENT,
+};
+
+use super::utils::{lock_oma, no_check_dbus_warn, CommitRequest, RefreshRequest};
+
+/// 清单格式有不兼容的变化时增加
+const MANIFEST_VERSION: u32 = 1;
+
+const SOURCES_LIST_DIR: &str = "etc/apt/sources.list.d";
+
+/// Managed by oma topics, will be restored by enabled topics
+const ATM_SOURCES_LIST: &str = "atm.list";
+
+/// Files changed by importing mirrors and topics
+const MIRROR_AND_TOPIC_FILES: &[&str] = &[
+    "etc/apt/sources.list",
+    "var/lib/apt/gen/status.json",
+    "etc/apt/sources.list.d/atm.list",
+    "var/lib/atm/state",
+];
+
+/// The system state exported by `oma export`
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    /// Manually installed packages
+    #[serde(default)]
+    packages: Vec<String>,
+    #[serde(default)]
+    holds: Vec<String>,
+    #[serde(default)]
+    topics: Vec<String>,
+    #[serde(default)]
+    mirrors: Vec<String>,
+    /// Extra sources in `/etc/apt/sources.list.d`
+    #[serde(default)]
+    sources: Vec<SourceFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SourceFile {
+    name: String,
+    content: String,
+}
+
+pub fn execute_export(
+    sysroot: String,
+    output: Option<&str>,
+    json: bool,
+    another_apt_options: Vec<String>,
+) -> Result<i32, OutputError> {
+    let oma_apt_args = OmaAptArgs::builder()
+        .sysroot(sysroot.clone())
+        .another_apt_options(another_apt_options)
+        .build();
+
+    let apt = OmaApt::new(vec![], oma_apt_args, false, AptConfig::new())?;
+
+    let mut packages = apt
+        .filter_pkgs(&[FilterMode::Installed, FilterMode::Manual])?
+        .map(|x| x.fullname(true))
+        .collect::<Vec<_>>();
+    packages.sort_unstable();
+
+    let holds = get_selections(&sysroot)?
+        .into_iter()
+        .filter(|(_, status)| status == "hold")
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+
+    #[cfg(feature = "aosc")]
+    let (topics, mirrors) = {
+        let arch = dpkg_arch(&sysroot)?;
+        let tm = crate::RT.block_on(oma_topics::TopicManager::new(
+            &HTTP_CLIENT,
+            &sysroot,
+            &arch,
+            true,
+        ))?;
+
+        let topics = tm
+            .enabled_topics()
+            .iter()
+            .map(|x| x.name.clone())
+            .collect::<Vec<_>>();
+
+        let mm = oma_mirror::MirrorManager::new((&sysroot).into())?;
+        let mirrors = mm
+            .enabled_mirrors()
+            .keys()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+
+        (topics, mirrors)
+    };
+
+    #[cfg(not(feature = "aosc"))]
+    let (topics, mirrors) = (vec![], vec![]);
+
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        packages,
+        holds,
+        topics,
+        mirrors,
+        sources: read_sources(&sysroot)?,
+    };
+
+    let s = if json {
+        serde_json::to_string_pretty(&manifest).map_err(|e| OutputError {
+            description: fl!("failed-to-serialize-struct"),
+            source: Some(Box::new(e)),
+        })?
+    } else {
+        toml::to_string_pretty(&manifest).map_err(|e| OutputError {
+            description: fl!("failed-to-serialize-struct"),
+            source: Some(Box::new(e)),
+        })?
+    };
+
+    match output {
+        Some(path) => {
+            fs::write(path, s).map_err(|e| OutputError {
+                description: fl!("failed-to-write-file", p = path),
+                source: Some(Box::new(e)),
+            })?;
+
+            success!("{}", fl!("export-done", p = path));
+        }
+        None => {
+            writeln!(stdout(), "{s}").ok();
+        }
+    }
+
+    Ok(0)
+}
+
+pub fn execute_import(path: &str, oma_args: OmaArgs, sysroot: String) -> Result<i32, OutputError> {
+    root()?;
+    lock_oma()?;
+
+    let manifest = read_manifest(path)?;
+
+    let fds = if !oma_args.no_check_dbus {
+        Some(dbus_check(false)?)
+    } else {
+        no_check_dbus_warn();
+        None
+    };
+
+    let dry_run = oma_args.dry_run;
+
+    // 软件源需要在刷新前写入才能找到其中的软件包，取消或失败时还原
+    let backup = (!dry_run).then(|| SourcesBackup::new(&sysroot, &manifest));
+
+    let res = import(&manifest, oma_args, &sysroot);
+
+    if !matches!(res, Ok(0)) {
+        if let Some(backup) = backup {
+            backup.restore();
+        }
+
+        return res;
+    }
+
+    // hold 只能设置给已安装的软件包，因此在安装完成后设置
+    import_holds(manifest.holds, dry_run, &sysroot)?;
+
+    drop(fds);
+
+    Ok(0)
+}
+
+fn import(manifest: &Manifest, oma_args: OmaArgs, sysroot: &str) -> Result<i32, OutputError> {
+    let OmaArgs {
+        dry_run,
+        network_thread,
+        speed_limit,
+        no_progress,
+        protect_essentials: protect_essential,
+        another_apt_options,
+        ..
+    } = oma_args;
+
+    if !dry_run {
+        write_sources(sysroot, &manifest.sources)?;
+    }
+
+    #[cfg(feature = "aosc")]
+    import_mirrors_and_topics(sysroot, manifest, dry_run, no_progress)?;
+
+    let apt_config = AptConfig::new();
+    let auth_config = AuthConfig::system(sysroot)?;
+
+    RefreshRequest {
+        client: &HTTP_CLIENT,
+        dry_run,
+        no_progress,
+        limit: network_thread,
+        speed_limit,
+        sysroot,
+        _refresh_topics: true,
+        config: &apt_config,
+        auth_config: &auth_config,
+    }
+    .run()?;
+
+    let oma_apt_args = OmaAptArgs::builder()
+        .sysroot(sysroot.to_string())
+        .another_apt_options(another_apt_options)
+        .build();
+
+    let arch = dpkg_arch(sysroot)?;
+
+    let mut apt = OmaApt::new(vec![], oma_apt_args, dry_run, apt_config)?;
+    let matcher = PackagesMatcher::builder()
+        .cache(&apt.cache)
+        .native_arch(&arch)
+        .build();
+
+    let (pkgs, no_result) =
+        matcher.match_pkgs_and_versions(manifest.packages.iter().map(|x| x.as_str()))?;
+
+    for i in no_result {
+        warn!("{}", fl!("import-pkg-not-found", name = i));
+    }
+
+    let (installed, not_installed): (Vec<_>, Vec<_>) = pkgs.into_iter().partition(|x| {
+        apt.cache
+            .get(&x.raw_pkg.fullname(true))
+            .is_some_and(|x| x.is_installed())
+    });
+
+    // 已经安装的软件包只需要标记为手动安装
+    let marked = if not_installed.is_empty() {
+        // 没有需要安装的软件包，无需确认
+        apt.mark_install_status(installed, false, dry_run)?
+    } else {
+        // 标记与安装操作一同提交，取消时都不会生效
+        let marked = apt.set_install_status(installed, false)?;
+        apt.install(&not_installed, false)?;
+
+        let request = CommitRequest {
+            apt,
+            dry_run,
+            request_type: SummaryType::Install(
+                not_installed
+                    .iter()
+                    .map(|x| format!("{} {}", x.raw_pkg.fullname(true), x.version_raw.version()))
+                    .collect::<Vec<_>>(),
+            ),
+            no_fixbroken: false,
+            network_thread,
+            speed_limit,
+            no_progress,
+            sysroot: sysroot.to_string(),
+            fix_dpkg_status: true,
+            protect_essential,
+            client: &HTTP_CLIENT,
+            yes: false,
+            remove_config: false,
+            auth_config: &auth_config,
+        };
+
+        let code = request.run()?;
+
+        if code != 0 {
+            return Ok(code);
+        }
+
+        marked
+    };
+
+    for (pkg, is_set) in marked {
+        if is_set {
+            success!("{}", fl!("setting-manual", name = pkg));
+        }
+    }
+
+    Ok(0)
+}
+
+fn import_holds(holds: Vec<String>, dry_run: bool, sysroot: &str) -> Result<(), OutputError> {
+    let installed = get_selections(sysroot)?
+        .into_iter()
+        .filter(|(_, status)| status == "install" || status == "hold")
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+
+    let (holds, not_installed): (Vec<_>, Vec<_>) =
+        holds.into_iter().partition(|x| installed.contains(x));
+
+    for i in not_installed {
+        warn!("{}", fl!("import-hold-not-installed", name = i));
+    }
+
+    for (pkg, is_set) in mark_version_status(&holds, true, dry_run, sysroot)? {
+        if is_set {
+            success!("{}", fl!("set-to-hold", name = pkg));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_manifest(path: &str) -> Result<Manifest, OutputError> {
+    let s = fs::read_to_string(path).map_err(|e| OutputError {
+        description: fl!("failed-to-operate-path", p = path),
+        source: Some(Box::new(e)),
+    })?;
+
+    let manifest: Manifest = if s.trim_start().starts_with('{') {
+        serde_json::from_str(&s).map_err(|e| OutputError {
+            description: fl!("failed-to-parse-file", p = path),
+            source: Some(Box::new(e)),
+        })?
+    } else {
+        toml::from_str(&s).map_err(|e| OutputError {
+            description: fl!("failed-to-parse-file", p = path),
+            source: Some(Box::new(e)),
+        })?
+    };
+
+    if manifest.version > MANIFEST_VERSION {
+        return Err(OutputError {
+            description: fl!("import-unsupported-version", version = manifest.version),
+            source: None,
+        });
+    }
+
+    Ok(manifest)
+}
+
+fn read_sources(sysroot: &str) -> Result<Vec<SourceFile>, OutputError> {
+    let dir = Path::new(sysroot).join(SOURCES_LIST_DIR);
+
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let read_dir = fs::read_dir(&dir).map_err(|e| OutputError {
+        description: fl!("failed-to-operate-path", p = dir.display().to_string()),
+        source: Some(Box::new(e)),
+    })?;
+
+    let mut res = vec![];
+
+    for entry in read_dir.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if name == ATM_SOURCES_LIST || !(name.ends_with(".list") || name.ends_with(".sources")) {
+            continue;
+        }
+
+        let path = entry.path();
+        let content = fs::read_to_string(&path).map_err(|e| OutputError {
+            description: fl!("failed-to-operate-path", p = path.display().to_string()),
+            source: Some(Box::new(e)),
+        })?;
+
+        res.push(SourceFile { name, content });
+    }
+
+    res.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(res)
+}
+
+/// Sources, mirrors and topics before importing, restored if the import is cancelled or failed
+struct SourcesBackup(Vec<(PathBuf, Option<Vec<u8>>)>);
+
+impl SourcesBackup {
+    fn new(sysroot: &str, manifest: &Manifest) -> Self {
+        let sysroot = Path::new(sysroot);
+        let dir = sysroot.join(SOURCES_LIST_DIR);
+
+        let files = manifest
+            .sources
+            .iter()
+            .filter(|x| is_valid_source_name(&x.name))
+            .map(|x| dir.join(&x.name))
+            .chain(MIRROR_AND_TOPIC_FILES.iter().map(|x| sysroot.join(x)))
+            // 读取失败的文件不会被还原，以免误删
+            .filter_map(|path| match fs::read(&path) {
+                Ok(content) => Some((path, Some(content))),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Some((path, None)),
+                Err(_) => None,
+            })
+            .collect();
+
+        Self(files)
+    }
+
+    fn restore(self) {
+        for (path, content) in self.0 {
+            let res = match content {
+                Some(content) => fs::write(&path, content),
+                None if path.exists() => fs::remove_file(&path),
+                None => Ok(()),
+            };
+
+            if let Err(e) = res {
+                warn!(
+                    "{}: {e}",
+                    fl!("failed-to-operate-path", p = path.display().to_string())
+                );
+            }
+        }
+
+        info!("{}", fl!("import-sources-restored"));
+    }
+}
+
+fn is_valid_source_name(name: &str) -> bool {
+    !name.contains('/') && !name.starts_with('.')
+}
+
+fn write_sources(sysroot: &str, sources: &[SourceFile]) -> Result<(), OutputError> {
+    let dir = Path::new(sysroot).join(SOURCES_LIST_DIR);
+
+    for source in sources {
+        // 不允许写入 sources.list.d 以外的位置
+        if !is_valid_source_name(&source.name) {
+            warn!(
+                "{}",
+                fl!("import-invalid-source", name = source.name.as_str())
+            );
+            continue;
+        }
+
+        let path = dir.join(&source.name);
+
+        if path.exists() {
+            if fs::read_to_string(&path).is_ok_and(|x| x != source.content) {
+                warn!(
+                    "{}",
+                    fl!("import-source-exists", p = path.display().to_string())
+                );
+            }
+            continue;
+        }
+
+        fs::write(&path, &source.content).map_err(|e| OutputError {
+            description: fl!("failed-to-write-file", p = path.display().to_string()),
+            source: Some(Box::new(e)),
+        })?;
+
+        info!(
+            "{}",
+            fl!("import-source-added", p = path.display().to_string())
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "aosc")]
+fn import_mirrors_and_topics(
+    sysroot: &str,
+    manifest: &Manifest,
+    dry_run: bool,
+    no_progress: bool,
+) -> Result<(), OutputError> {
+    use oma_mirror::MirrorManager;
+    use oma_topics::TopicManager;
+
+    if !manifest.mirrors.is_empty() {
+        let mut mm = MirrorManager::new(sysroot.into())?;
+        let mut changed = false;
+
+        for i in &manifest.mirrors {
+            match mm.add(i) {
+                Ok(added) => changed |= added,
+                Err(e) => warn!("{}", OutputError::from(e)),
+            }
+        }
+
+        if changed && !dry_run {
+            mm.write_status(Some(&fl!("do-not-edit-topic-sources-list")))?;
+        }
+    }
+
+    if manifest.topics.is_empty() {
+        return Ok(());
+    }
+
+    let arch = dpkg_arch(sysroot)?;
+
+    crate::RT.block_on(async {
+        let mut tm = TopicManager::new(&HTTP_CLIENT, sysroot, &arch, dry_run).await?;
+
+        super::topics::refresh_topics(no_progress, &mut tm).await?;
+
+        for i in &manifest.topics {
+            if let Err(e) = tm.add(i) {
+                warn!("{}", OutputError::from(e));
+            }
+        }
+
+        if dry_run {
+            return Ok(());
+        }
+
+        tm.write_enabled(&fl!("do-not-edit-topic-sources-list"), |topic, mirror| {
+            warn!(
+                "{}",
+                fl!("topic-not-in-mirror", topic = topic, mirror = mirror)
+            );
+            warn!("{}", fl!("skip-write-mirror"));
+        })
+        .await?;
+
+        Ok(())
+    })
+}
@@ -1,16 +1,12 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
 use std::fmt::Display;
 use std::io::stdout;
-use std::time::Duration;
-use std::time::Instant;
 
 use anyhow::anyhow;
 use apt_auth_config::AuthConfig;
 use dialoguer::console::style;
 use dialoguer::theme::ColorfulTheme;
 use dialoguer::Sort;
-use faster_hex::hex_string;
 use inquire::formatter::MultiOptionFormatter;
 use inquire::ui::Color;
 use inquire::ui::RenderConfig;
@@ -21,12 +17,10 @@ use oma_console::indicatif::HumanBytes;
 use oma_console::indicatif::ProgressBar;
 use oma_console::indicatif::ProgressStyle;
 use oma_console::success;
+use oma_mirror::speedtest::SpeedtestConfig;
 use oma_mirror::Mirror;
 use oma_mirror::MirrorManager;
 use oma_pm::apt::AptConfig;
-use reqwest::blocking;
-use sha2::Digest;
-use sha2::Sha256;
 use tabled::Tabled;
 use tracing::{error, info};
 
@@ -35,15 +29,12 @@ use crate::fl;
 use crate::pb::OmaProgressBar;
 use crate::table::PagerPrinter;
 use crate::utils::root;
-use crate::APP_USER_AGENT;
 use crate::HTTP_CLIENT;
+use crate::RT;
 
 use super::utils::tui_select_list_size;
 use super::utils::RefreshRequest;
 
-const REPO_TEST_SHA256: &str = "1e2a82e7babb443b2b26b61ce5dd2bd25b06b30422b42ee709fddd2cc3ffe231";
-const TEST_FILE_PREFIX: &str = ".repotest";
-
 struct MirrorDisplay((Box<str>, Mirror));
 
 impl Display for MirrorDisplay {
@@ -58,6 +49,7 @@ pub fn tui(
     no_progress: bool,
     refresh_topic: bool,
     network_threads: usize,
+    speed_limit: Option<u64>,
     no_refresh: bool,
 ) -> Result<i32, OutputError> {
     root()?;
@@ -106,7 +98,7 @@ pub fn tui(
     mm.write_status(Some(&fl!("do-not-edit-topic-sources-list")))?;
 
     if !no_refresh {
-        refresh(no_progress, network_threads, refresh_topic)?;
+        refresh(no_progress, network_threads, speed_limit, refresh_topic)?;
     }
 
     Ok(0)
@@ -122,6 +114,7 @@ pub fn operate(
     no_progress: bool,
     refresh_topic: bool,
     network_threads: usize,
+    speed_limit: Option<u64>,
     no_refresh: bool,
     args: Vec<&str>,
     subcmd: Operate,
@@ -149,7 +142,7 @@ pub fn operate(
     mm.write_status(Some(&fl!("do-not-edit-topic-sources-list")))?;
 
     if !no_refresh {
-        refresh(no_progress, network_threads, refresh_topic)?;
+        refresh(no_progress, network_threads, speed_limit, refresh_topic)?;
     }
 
     Ok(0)
@@ -159,6 +152,7 @@ pub fn set_order(
     no_progress: bool,
     refresh_topic: bool,
     network_threads: usize,
+    speed_limit: Option<u64>,
     no_refresh: bool,
 ) -> Result<i32, OutputError> {
     root()?;
@@ -184,7 +178,7 @@ pub fn set_order(
     mm.write_status(Some(&fl!("do-not-edit-topic-sources-list")))?;
 
     if !no_refresh {
-        refresh(no_progress, network_threads, refresh_topic)?;
+        refresh(no_progress, network_threads, speed_limit, refresh_topic)?;
     }
 
     Ok(0)
@@ -194,6 +188,7 @@ pub fn set_order(
 struct MirrorScoreDisplay<'a> {
     name: &'a str,
     score: String,
+    latency: String,
 }
 
 pub fn speedtest(
@@ -201,6 +196,7 @@ pub fn speedtest(
     set_fastest: bool,
     refresh_topic: bool,
     network_threads: usize,
+    speed_limit: Option<u64>,
     no_refresh: bool,
 ) -> Result<i32, OutputError> {
     if set_fastest {
@@ -225,89 +221,65 @@ pub fn speedtest(
         None
     };
 
-    let client = blocking::ClientBuilder::new()
-        .user_agent(APP_USER_AGENT)
-        .timeout(Duration::from_secs(120))
-        .build()?;
-
-    let mut score_map = HashMap::new();
-
     info!("{}", fl!("mirror-speedtest-start"));
 
-    for (name, mirror) in mirrors {
-        let mut sha256 = Sha256::new();
-        let timer = Instant::now();
-        let res = client
-            .get(format!("{}{}", mirror.url, TEST_FILE_PREFIX))
-            .send()
-            .and_then(|x| x.error_for_status())
-            .and_then(|mut x| x.copy_to(&mut sha256));
-
-        let dur = timer.elapsed();
-
-        match res {
-            Ok(_) => {
-                if REPO_TEST_SHA256 == hex_string(&sha256.finalize()) {
-                    score_map.insert(name, dur);
-                    let msg = format!(
-                        "{}: {}/s",
+    let res = RT.block_on(oma_mirror::speedtest::speedtest(
+        &HTTP_CLIENT,
+        mirrors,
+        &SpeedtestConfig::default(),
+        |name, res| {
+            let (prefix, msg) = match res {
+                Ok(score) => (
+                    style("INFO").blue().bold(),
+                    format!(
+                        "{}: {}/s ({} ms)",
                         name,
-                        HumanBytes((10.0 * 1024.0 * 1024.0 / dur.as_secs_f64()) as u64)
-                    );
-                    if let Some(ref pb) = pb {
-                        pb.writeln(&style("INFO").blue().bold().to_string(), &msg)
-                            .ok();
-                    } else {
-                        info!("{}", msg);
-                    }
-                } else {
-                    let msg = format!("{}: Checksum verification failed.", name);
-                    if let Some(ref pb) = pb {
-                        pb.writeln(&style("ERROR").red().bold().to_string(), &msg)
-                            .ok();
-                    } else {
-                        error!("{}", msg);
-                    }
-                }
-            }
-            Err(e) => {
-                let msg = format!("{}: {}", name, e.without_url());
-                if let Some(ref pb) = pb {
-                    pb.writeln(&style("ERROR").red().bold().to_string(), &msg)
-                        .ok();
-                } else {
-                    error!("{}", msg);
-                }
+                        HumanBytes(score.throughput()),
+                        score.latency.as_millis()
+                    ),
+                ),
+                Err(e) => (style("ERROR").red().bold(), format!("{}: {}", name, e)),
+            };
+
+            if let Some(ref pb) = pb {
+                pb.writeln(&prefix.to_string(), &msg).ok();
+                pb.inner.inc(1);
+            } else if res.is_ok() {
+                info!("{}", msg);
+            } else {
+                error!("{}", msg);
             }
-        }
-
-        if let Some(ref pb) = pb {
-            pb.inner.inc(1);
-        }
-    }
+        },
+    ));
 
     if let Some(ref pb) = pb {
         pb.inner.finish_and_clear();
     }
 
-    let mut printer = PagerPrinter::new(stdout());
-
-    let mut score = score_map.iter().collect::<Vec<_>>();
+    let score = res
+        .iter()
+        .filter_map(|(name, res)| Some((*name, res.as_ref().ok()?)))
+        .collect::<Vec<_>>();
 
-    score.sort_unstable_by(|a, b| a.1.cmp(b.1));
+    let mut printer = PagerPrinter::new(stdout());
 
-    let score_table = score.iter().map(|x| MirrorScoreDisplay {
-        name: x.0,
-        score: format!(
-            "{}/s",
-            HumanBytes((10.0 * 1024.0 * 1024.0 / x.1.as_secs_f64()) as u64)
-        ),
+    let score_table = score.iter().map(|(name, score)| MirrorScoreDisplay {
+        name,
+        score: format!("{}/s", HumanBytes(score.throughput())),
+        latency: format!("{} ms", score.latency.as_millis()),
     });
 
     success!("{}\n", fl!("speedtest-complete"));
 
     printer
-        .print_table(score_table, vec![&fl!("mirror-name"), &fl!("mirror-score")])
+        .print_table(
+            score_table,
+            vec![
+                &fl!("mirror-name"),
+                &fl!("mirror-score"),
+                &fl!("mirror-latency"),
+            ],
+        )
         .ok();
 
     if set_fastest {
@@ -316,12 +288,12 @@ pub fn speedtest(
             source: None,
         })?;
 
-        let name: Box<str> = Box::from(**name);
+        let name: Box<str> = Box::from(*name);
         mm.set(&[&name])?;
         mm.write_status(Some(&fl!("do-not-edit-topic-sources-list")))?;
 
         if !no_refresh {
-            refresh(no_progress, network_threads, refresh_topic)?;
+            refresh(no_progress, network_threads, speed_limit, refresh_topic)?;
         }
     }
 
@@ -331,6 +303,7 @@ pub fn speedtest(
 fn refresh(
     no_progress: bool,
     network_threads: usize,
+    speed_limit: Option<u64>,
     refresh_topic: bool,
 ) -> Result<(), OutputError> {
     let auth_config = AuthConfig::system("/")?;
@@ -340,6 +313,7 @@ fn refresh(
         dry_run: false,
         no_progress,
         limit: network_threads,
+        speed_limit,
         sysroot: "/",
         _refresh_topics: refresh_topic,
         config: &AptConfig::new(),
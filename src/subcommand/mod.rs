@@ -1,16 +1,23 @@
+pub mod apply;
+pub mod auto_upgrade;
+pub mod changelog;
 pub mod clean;
 pub mod command_not_found;
 pub mod contents_find;
+pub mod daemon;
 pub mod depends;
 pub mod download;
 pub mod fix_broken;
 pub mod history;
 pub mod install;
 pub mod list;
+pub mod manifest;
 pub mod mark;
 #[cfg(feature = "aosc")]
 pub mod mirror;
+pub mod offline;
 pub mod pick;
+pub mod pin;
 pub mod pkgnames;
 pub mod rdepends;
 pub mod refresh;
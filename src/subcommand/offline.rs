@@ -0,0 +1,225 @@
+use std::{fs, os::unix::fs::symlink, path::Path};
+
+use chrono::Local;
+use oma_history::{connect_db, create_db_file, write_history_entry, SummaryType};
+use oma_pm::apt::{AptConfig, CommitDownloadConfig, OmaApt, OmaAptArgs, OmaOperation, SummarySort};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use apt_auth_config::AuthConfig;
+
+use crate::{
+    error::OutputError,
+    fl,
+    install_progress::NoInstallProgressManager,
+    pb::NoProgressBar,
+    utils::{dbus_check, root},
+    OmaArgs, HTTP_CLIENT,
+};
+
+use super::utils::{enabled_mirrors, lock_oma, no_check_dbus_warn};
+
+/// The resolved operation of offline upgrade
+const OFFLINE_UPGRADE_PATH: &str = "var/lib/oma/offline-upgrade.json";
+
+/// See systemd.offline-updates(7)
+const SYSTEM_UPDATE_PATH: &str = "system-update";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OfflineUpgrade {
+    typ: SummaryType,
+    op: OmaOperation,
+}
+
+/// Record the resolved operation and create `/system-update` symlink,
+/// then the upgrade will be installed at next boot by `oma-offline-upgrade.service`
+pub fn prepare(sysroot: &str, typ: SummaryType, op: OmaOperation) -> Result<(), OutputError> {
+    let sysroot = Path::new(sysroot);
+    let path = sysroot.join(OFFLINE_UPGRADE_PATH);
+
+    let parent = path.parent().unwrap();
+    fs::create_dir_all(parent).map_err(|e| OutputError {
+        description: fl!("failed-to-operate-path", p = parent.display().to_string()),
+        source: Some(Box::new(e)),
+    })?;
+
+    let offline = OfflineUpgrade { typ, op };
+    let s = serde_json::to_vec(&offline).map_err(|e| OutputError {
+        description: fl!("failed-to-serialize-struct"),
+        source: Some(Box::new(e)),
+    })?;
+
+    fs::write(&path, s).map_err(|e| OutputError {
+        description: fl!("failed-to-write-file", p = path.display().to_string()),
+        source: Some(Box::new(e)),
+    })?;
+
+    let link = sysroot.join(SYSTEM_UPDATE_PATH);
+
+    // 若之前已经准备过离线更新，替换之
+    if link.is_symlink() {
+        remove_path(&link)?;
+    }
+
+    symlink(Path::new("/").join(OFFLINE_UPGRADE_PATH), &link).map_err(|e| OutputError {
+        description: fl!("failed-to-create-file", p = link.display().to_string()),
+        source: Some(Box::new(e)),
+    })?;
+
+    Ok(())
+}
+
+/// Install the offline upgrade prepared by `oma upgrade --offline` (at boot)
+pub fn execute(oma_args: OmaArgs, sysroot: String) -> Result<i32, OutputError> {
+    root()?;
+    lock_oma()?;
+
+    let OmaArgs {
+        dry_run,
+        network_thread,
+        speed_limit,
+        no_progress: _,
+        no_check_dbus,
+        protect_essentials: _,
+        another_apt_options,
+    } = oma_args;
+
+    let sysroot_path = Path::new(&sysroot);
+    let link = sysroot_path.join(SYSTEM_UPDATE_PATH);
+    let path = sysroot_path.join(OFFLINE_UPGRADE_PATH);
+
+    // 根据 systemd.offline-updates(7) 的要求，需要在开始安装前删除 /system-update
+    // 以免安装失败后每次启动都进入更新模式
+    if link.is_symlink() && !dry_run {
+        remove_path(&link)?;
+    }
+
+    if !path.is_file() {
+        return Err(OutputError {
+            description: fl!("offline-upgrade-not-prepared"),
+            source: None,
+        });
+    }
+
+    let s = fs::read(&path).map_err(|e| OutputError {
+        description: fl!("failed-to-operate-path", p = path.display().to_string()),
+        source: Some(Box::new(e)),
+    })?;
+
+    if !dry_run {
+        remove_path(&path)?;
+    }
+
+    let OfflineUpgrade { typ, op } = serde_json::from_slice(&s).map_err(|e| OutputError {
+        description: fl!("failed-to-parse-file", p = path.display().to_string()),
+        source: Some(Box::new(e)),
+    })?;
+
+    let fds = if !no_check_dbus {
+        Some(dbus_check(true)?)
+    } else {
+        no_check_dbus_warn();
+        None
+    };
+
+    info!("{}", fl!("offline-upgrade-start"));
+
+    let oma_apt_args = OmaAptArgs::builder()
+        .sysroot(sysroot.clone())
+        .yes(true)
+        .another_apt_options(another_apt_options)
+        .build();
+
+    let mut apt = OmaApt::new(vec![], oma_apt_args, dry_run, AptConfig::new())?;
+
+    apt.mark_operation(&op)?;
+    apt.resolve(false, false)?;
+
+    // 用户在准备离线更新时已经确认过要进行的操作
+    let current = apt.summary(SummarySort::Operation, |_| true, |_| true)?;
+
+    if !is_same_operation(&op, &current) {
+        return Err(OutputError {
+            description: fl!("offline-upgrade-changed"),
+            source: None,
+        });
+    }
+
+    let auth_config = AuthConfig::system(&sysroot)?;
+    let mirrors = enabled_mirrors(&sysroot);
+    let start_time = Local::now().timestamp();
+    let op_after = current.clone();
+
+    let term_log = apt.term_log();
+
+    let res = apt.commit(
+        &HTTP_CLIENT,
+        CommitDownloadConfig {
+            network_thread: Some(network_thread),
+            auth: &auth_config,
+            speed_limit,
+            mirrors: &mirrors,
+        },
+        &NoProgressBar::default(),
+        Box::new(NoInstallProgressManager),
+        current,
+    );
+
+    write_history_entry(
+        op_after,
+        typ,
+        {
+            let db = create_db_file(&sysroot)?;
+            connect_db(db, true)?
+        },
+        dry_run,
+        start_time,
+        res.as_ref().err().map(|e| e.to_string()),
+        term_log.read(),
+    )?;
+
+    res?;
+
+    drop(fds);
+
+    Ok(0)
+}
+
+/// 确认当前解析出的操作与准备离线更新时的一致，避免系统状态改变后安装非预期的软件包
+fn is_same_operation(recorded: &OmaOperation, current: &OmaOperation) -> bool {
+    let install = |op: &OmaOperation| {
+        let mut v = op
+            .install
+            .iter()
+            .map(|x| (x.name().to_string(), x.new_version().to_string()))
+            .collect::<Vec<_>>();
+        v.sort();
+        v
+    };
+
+    let remove = |op: &OmaOperation| {
+        let mut v = op
+            .remove
+            .iter()
+            .map(|x| x.name().to_string())
+            .collect::<Vec<_>>();
+        v.sort();
+        v
+    };
+
+    let res = install(recorded) == install(current) && remove(recorded) == remove(current);
+
+    if !res {
+        warn!("Recorded: {recorded}");
+        warn!("Current: {current}");
+    }
+
+    res
+}
+
+fn remove_path(path: &Path) -> Result<(), OutputError> {
+    fs::remove_file(path).map_err(|e| OutputError {
+        description: fl!("failed-to-operate-path", p = path.display().to_string()),
+        source: Some(Box::new(e)),
+    })
+}
@@ -31,6 +31,7 @@ pub fn execute(
     let OmaArgs {
         dry_run,
         network_thread,
+        speed_limit,
         no_progress,
         no_check_dbus,
         protect_essentials: protect_essential,
@@ -55,6 +56,7 @@ pub fn execute(
             dry_run,
             no_progress,
             limit: network_thread,
+            speed_limit,
             sysroot: &sysroot,
             _refresh_topics: !no_refresh_topic,
             config: &apt_config,
@@ -142,6 +144,7 @@ pub fn execute(
         ),
         no_fixbroken: false,
         network_thread,
+        speed_limit,
         no_progress,
         sysroot,
         fix_dpkg_status: true,
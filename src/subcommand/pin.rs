@@ -0,0 +1,210 @@
+use std::io::stdout;
+
+use oma_console::{print::Action, success};
+use oma_pm::{
+    apt::{AptConfig, OmaApt, OmaAptArgs},
+    pin::{read_pins, write_pins, Pin, PinTarget},
+};
+use tabled::Tabled;
+use tracing::info;
+
+use crate::{color_formatter, error::OutputError, fl, table::PagerPrinter, utils::root};
+
+#[derive(Debug, Tabled)]
+struct PinDisplay {
+    package: String,
+    pin: String,
+    priority: i32,
+    installed: String,
+    candidate: String,
+    effect: String,
+}
+
+/// Pin a package to a version or repository, replacing its existing pin
+pub fn execute_pin(
+    pkg: &str,
+    value: &str,
+    priority: i32,
+    sysroot: String,
+    another_apt_options: Vec<String>,
+) -> Result<i32, OutputError> {
+    root()?;
+
+    let apt = new_apt(&sysroot, another_apt_options.clone())?;
+
+    if apt.cache.get(pkg).is_none() {
+        return Err(OutputError {
+            description: fl!("can-not-get-pkg-from-database", name = pkg),
+            source: None,
+        });
+    }
+
+    let target = pin_target(&apt, pkg, value).ok_or_else(|| OutputError {
+        description: fl!("pin-target-not-found", name = pkg, target = value),
+        source: None,
+    })?;
+
+    drop(apt);
+
+    let mut pins = read_pins(&sysroot)?;
+    pins.retain(|x| x.package != pkg);
+
+    let pin_str = target.to_string();
+
+    pins.push(Pin {
+        package: pkg.to_string(),
+        target,
+        priority,
+    });
+
+    write_pins(&sysroot, &pins)?;
+
+    success!(
+        "{}",
+        fl!(
+            "pinned",
+            name = color_formatter()
+                .color_str(pkg, Action::Emphasis)
+                .to_string(),
+            pin = pin_str,
+            priority = priority
+        )
+    );
+
+    // 重新读取 apt 缓存，才能看到新的固定对候选版本的影响
+    let apt = new_apt(&sysroot, another_apt_options)?;
+
+    if let Some(cand) = apt.cache.get(pkg).and_then(|x| x.candidate()) {
+        info!(
+            "{}",
+            fl!("pin-candidate", name = pkg, version = cand.version())
+        );
+    }
+
+    Ok(0)
+}
+
+/// Remove the pins of packages
+pub fn execute_unpin(pkgs: Vec<String>, sysroot: String) -> Result<i32, OutputError> {
+    root()?;
+
+    let mut pins = read_pins(&sysroot)?;
+
+    for pkg in &pkgs {
+        let name = color_formatter()
+            .color_str(pkg, Action::Emphasis)
+            .to_string();
+
+        if pins.iter().any(|x| &x.package == pkg) {
+            pins.retain(|x| &x.package != pkg);
+            success!("{}", fl!("unpinned", name = name));
+        } else {
+            info!("{}", fl!("not-pinned", name = name));
+        }
+    }
+
+    write_pins(&sysroot, &pins)?;
+
+    Ok(0)
+}
+
+/// List pins and how they change the candidates
+pub fn execute_list(sysroot: String, another_apt_options: Vec<String>) -> Result<i32, OutputError> {
+    let pins = read_pins(&sysroot)?;
+
+    if pins.is_empty() {
+        info!("{}", fl!("no-pins"));
+        return Ok(0);
+    }
+
+    let apt = new_apt(&sysroot, another_apt_options)?;
+    let blocked = apt.pin_blocked_upgrades(&pins);
+
+    let display = pins.iter().map(|pin| {
+        let pkg = apt.cache.get(&pin.package);
+        let installed = pkg.as_ref().and_then(|x| x.installed());
+        let cand = pkg.as_ref().and_then(|x| x.candidate());
+
+        let effect = if let Some(b) = blocked.iter().find(|x| x.pin == *pin) {
+            fl!("pin-effect-hold-back", version = b.newest.as_str())
+        } else {
+            match (&installed, &cand) {
+                (_, None) => fl!("pin-effect-no-candidate"),
+                (Some(i), Some(c)) if c < i => {
+                    fl!("pin-effect-downgrade", version = c.version())
+                }
+                _ => String::new(),
+            }
+        };
+
+        PinDisplay {
+            package: pin.package.clone(),
+            pin: pin.target.to_string(),
+            priority: pin.priority,
+            installed: installed
+                .map(|x| x.version().to_string())
+                .unwrap_or_default(),
+            candidate: cand.map(|x| x.version().to_string()).unwrap_or_default(),
+            effect,
+        }
+    });
+
+    let mut printer = PagerPrinter::new(stdout());
+    printer
+        .print_table(
+            display,
+            vec![
+                &fl!("pin-list-package"),
+                &fl!("pin-list-pin"),
+                &fl!("pin-list-priority"),
+                &fl!("pin-list-installed"),
+                &fl!("pin-list-candidate"),
+                &fl!("pin-list-effect"),
+            ],
+        )
+        .ok();
+
+    Ok(0)
+}
+
+fn new_apt(sysroot: &str, another_apt_options: Vec<String>) -> Result<OmaApt, OutputError> {
+    let oma_apt_args = OmaAptArgs::builder()
+        .sysroot(sysroot.to_string())
+        .another_apt_options(another_apt_options)
+        .build();
+
+    Ok(OmaApt::new(vec![], oma_apt_args, false, AptConfig::new())?)
+}
+
+/// `key=value` is a release pin, otherwise a version (glob) of the package,
+/// or the origin, suite or hostname of a repository which has the package
+fn pin_target(apt: &OmaApt, pkg: &str, value: &str) -> Option<PinTarget> {
+    if value.contains('=') {
+        return Some(PinTarget::Release(value.to_string()));
+    }
+
+    let pkg = apt.cache.get(pkg)?;
+
+    if pkg
+        .versions()
+        .any(|x| glob_match::glob_match(value, x.version()))
+    {
+        return Some(PinTarget::Version(value.to_string()));
+    }
+
+    for file in pkg.versions().flat_map(|x| x.package_files()) {
+        if file.origin() == Some(value) {
+            return Some(PinTarget::Release(format!("o={value}")));
+        }
+
+        if file.archive() == Some(value) {
+            return Some(PinTarget::Release(format!("a={value}")));
+        }
+
+        if file.site() == Some(value) {
+            return Some(PinTarget::Origin(value.to_string()));
+        }
+    }
+
+    None
+}
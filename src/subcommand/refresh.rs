@@ -4,22 +4,32 @@ use oma_console::pb::spinner_style;
 use oma_console::success;
 use oma_pm::apt::{AptConfig, OmaApt, OmaAptArgs};
 
+#[cfg(feature = "aosc")]
+use crate::config::TopicExpiry;
 use crate::{error::OutputError, utils::root};
 use crate::{fl, OmaArgs, HTTP_CLIENT};
 
 use super::utils::RefreshRequest;
+#[cfg(feature = "aosc")]
+use super::{topics::RevertClosedTopics, utils::lock_oma};
 
 pub fn execute(
     oma_args: OmaArgs,
     sysroot: String,
     no_refresh_topics: bool,
+    #[cfg(feature = "aosc")] topic_expiry: TopicExpiry,
 ) -> Result<i32, OutputError> {
     root()?;
 
     let OmaArgs {
         dry_run: _,
         network_thread,
+        speed_limit,
         no_progress,
+        #[cfg(feature = "aosc")]
+        protect_essentials,
+        #[cfg(feature = "aosc")]
+        another_apt_options,
         ..
     } = oma_args;
 
@@ -31,6 +41,7 @@ pub fn execute(
         dry_run: false,
         no_progress,
         limit: network_thread,
+        speed_limit,
         sysroot: &sysroot,
         _refresh_topics: !no_refresh_topics,
         config: &apt_config,
@@ -38,6 +49,26 @@ pub fn execute(
     }
     .run()?;
 
+    #[cfg(feature = "aosc")]
+    {
+        lock_oma()?;
+
+        RevertClosedTopics {
+            policy: topic_expiry,
+            allow_revert: false,
+            sysroot: &sysroot,
+            dry_run: false,
+            yes: false,
+            network_thread,
+            speed_limit,
+            no_progress,
+            protect_essential: protect_essentials,
+            another_apt_options,
+            auth_config: &auth_config,
+        }
+        .run()?;
+    }
+
     let oma_apt_args = OmaAptArgs::builder().sysroot(sysroot).build();
     let apt = OmaApt::new(vec![], oma_apt_args, false, apt_config)?;
 
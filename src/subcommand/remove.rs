@@ -26,6 +26,7 @@ pub fn execute(glob: Vec<&str>, args: RemoveArgs, oma_args: OmaArgs) -> Result<i
     let OmaArgs {
         dry_run,
         network_thread,
+        speed_limit,
         no_progress,
         no_check_dbus,
         protect_essentials: protect,
@@ -115,6 +116,7 @@ pub fn execute(glob: Vec<&str>, args: RemoveArgs, oma_args: OmaArgs) -> Result<i
         request_type: SummaryType::Remove(remove_str),
         no_fixbroken: !args.fix_broken,
         network_thread,
+        speed_limit,
         no_progress,
         sysroot: args.sysroot,
         fix_dpkg_status: true,
@@ -3,10 +3,11 @@ use std::io::stdout;
 use oma_pm::{
     apt::{AptConfig, OmaApt, OmaAptArgs},
     matches::PackagesMatcher,
+    pin::read_pins,
     pkginfo::OmaPackage,
 };
 use oma_utils::dpkg::dpkg_arch;
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::error::OutputError;
 
@@ -37,6 +38,17 @@ pub fn execute(
 
     let (pkgs, no_result) = matcher.match_pkgs_and_versions(input)?;
 
+    let pins = read_pins(&sysroot).unwrap_or_else(|e| {
+        warn!("{}", OutputError::from(e));
+        vec![]
+    });
+
+    let blocked = apt
+        .pin_blocked_upgrades(&pins)
+        .into_iter()
+        .filter(|b| pkgs.iter().any(|x| x.raw_pkg.name() == b.pin.package))
+        .collect::<Vec<_>>();
+
     handle_no_result(sysroot, no_result, no_progress)?;
 
     let mut stdout = stdout();
@@ -104,5 +116,20 @@ pub fn execute(
         }
     }
 
+    if !json {
+        // 固定的版本阻止了升级
+        for b in blocked {
+            info!(
+                "{}",
+                fl!(
+                    "pin-blocks-upgrade",
+                    name = b.pin.package,
+                    pin = b.pin.target.to_string(),
+                    version = b.newest
+                )
+            );
+        }
+    }
+
     Ok(0)
 }
@@ -1,37 +1,55 @@
-use std::{fmt::Display, path::Path};
+use std::{
+    fmt::Display,
+    fs,
+    io::stdout,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use apt_auth_config::AuthConfig;
+use chrono::{DateTime, Local};
 use dialoguer::console::style;
 use inquire::{
     formatter::MultiOptionFormatter,
     ui::{Color, RenderConfig, StyleSheet, Styled},
     MultiSelect,
 };
-use oma_console::writer::Writeln;
-use oma_history::SummaryType;
+use oma_console::{indicatif::HumanBytes, writer::Writeln};
+use oma_fetch::{
+    checksum::Checksum, DownloadEntry, DownloadManager, DownloadProgressControl, DownloadSource,
+    DownloadSourceType,
+};
+use oma_history::{connect_db, create_db_file, write_history_entry, SummaryType};
 use oma_pm::{
     apt::{AptConfig, FilterMode, OmaApt, OmaAptArgs, Upgrade},
     matches::PackagesMatcher,
 };
+use oma_refresh::{
+    db::RefreshError,
+    inrelease::{verify_inrelease, InRelease, InReleaseChecksum},
+};
 use oma_utils::dpkg::dpkg_arch;
 use reqwest::Client;
+use tabled::Tabled;
 use tokio::task::spawn_blocking;
-use tracing::warn;
+use tracing::{info, warn};
 
 use crate::{
+    config::TopicExpiry,
     error::OutputError,
-    pb::OmaProgressBar,
+    pb::{NoProgressBar, OmaMultiProgressBar, OmaProgressBar},
+    table::PagerPrinter,
     utils::{dbus_check, root},
-    OmaArgs, RT,
+    OmaArgs, HTTP_CLIENT, RT,
 };
 
 use super::utils::{
-    lock_oma, no_check_dbus_warn, select_tui_display_msg, tui_select_list_size, CommitRequest,
-    RefreshRequest,
+    empty_operation, is_terminal, lock_oma, no_check_dbus_warn, select_tui_display_msg,
+    tui_select_list_size, CommitRequest, RefreshRequest,
 };
 use crate::fl;
 use anyhow::anyhow;
-use oma_topics::{scan_closed_topic, Topic, TopicManager};
+use oma_topics::{scan_closed_topic, OmaTopicsError, Topic, TopicManager};
 
 struct TopicChanged {
     opt_in: Vec<String>,
@@ -73,6 +91,428 @@ impl<'a> Display for TopicDisplay<'a> {
     }
 }
 
+#[derive(Debug, Tabled)]
+struct TopicListDisplay<'a> {
+    name: &'a str,
+    description: &'a str,
+    enabled: &'a str,
+}
+
+/// List available topics, fall back to the cached manifest when offline
+pub fn execute_list(
+    client: Client,
+    sysroot: String,
+    no_progress: bool,
+) -> Result<i32, OutputError> {
+    let arch = dpkg_arch(&sysroot)?;
+
+    RT.block_on(async {
+        let mut tm = TopicManager::new(&client, &sysroot, &arch, false).await?;
+
+        let pb = if !no_progress {
+            Some(OmaProgressBar::new_spinner(Some(fl!(
+                "refreshing-topic-metadata"
+            ))))
+        } else {
+            None
+        };
+
+        let res = tm.refresh().await;
+
+        if let Some(pb) = pb {
+            pb.inner.finish_and_clear();
+        }
+
+        if let Err(e) = res {
+            let Some(updated) = tm.refresh_from_cache().await? else {
+                return Err(OutputError::from(e));
+            };
+
+            let date = DateTime::from_timestamp(updated as i64, 0)
+                .map(|x| x.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_secs())
+                .unwrap_or_default();
+
+            warn!("{e}");
+            warn!(
+                "{}",
+                fl!(
+                    "topics-list-cached",
+                    date = date,
+                    age = format_age(now.saturating_sub(updated))
+                )
+            );
+        }
+
+        let enabled = tm.enabled_topics();
+
+        let display = tm
+            .all_topics()
+            .iter()
+            .filter(|x| {
+                (x.description.is_some() && !x.draft.is_some_and(|x| x)) || enabled.contains(x)
+            })
+            .map(|x| TopicListDisplay {
+                name: &x.name,
+                description: x.description.as_deref().unwrap_or_default(),
+                enabled: if enabled.contains(x) { "✔" } else { "" },
+            });
+
+        let mut printer = PagerPrinter::new(stdout());
+        printer
+            .print_table(
+                display,
+                vec![
+                    &fl!("topics-list-name"),
+                    &fl!("topics-list-description"),
+                    &fl!("topics-list-enabled"),
+                ],
+            )
+            .ok();
+
+        Ok(0)
+    })
+}
+
+#[derive(Debug, Tabled)]
+struct TopicPackageDisplay {
+    name: String,
+    topic: String,
+    installed: String,
+    stable: String,
+}
+
+/// Show packages of a topic, compared with the installed and stable versions
+///
+/// The topic is not enabled, its Packages is downloaded to a temporary lists directory
+/// which is used by the apt cache together with the system lists.
+pub fn execute_show(
+    name: &str,
+    client: Client,
+    sysroot: String,
+    no_progress: bool,
+    another_apt_options: Vec<String>,
+) -> Result<i32, OutputError> {
+    let arch = dpkg_arch(&sysroot)?;
+
+    let (topic, mirrors) = RT.block_on(async {
+        let mut tm = TopicManager::new(&client, &sysroot, &arch, false).await?;
+
+        if let Err(e) = tm.refresh().await {
+            if tm.refresh_from_cache().await?.is_none() {
+                return Err(OutputError::from(e));
+            }
+        }
+
+        let topic = tm
+            .all_topics()
+            .iter()
+            .chain(tm.enabled_topics())
+            .find(|x| x.name.eq_ignore_ascii_case(name))
+            .cloned()
+            .ok_or_else(|| OmaTopicsError::CanNotFindTopic(name.to_string()))?;
+
+        let mirrors = tm
+            .enabled_mirrors()
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+
+        Ok::<_, OutputError>((topic, mirrors))
+    })?;
+
+    let Some(mirror) = mirrors.first() else {
+        return Err(OutputError {
+            description: fl!("topics-show-unavailable", name = topic.name.as_str()),
+            source: None,
+        });
+    };
+
+    let tmp = tempfile::tempdir().map_err(|e| OutputError {
+        description: fl!(
+            "failed-to-create-file",
+            p = std::env::temp_dir().display().to_string()
+        ),
+        source: Some(Box::new(e)),
+    })?;
+
+    let lists_dir = tmp.path().join("lists");
+    let parts_dir = tmp.path().join("sources.list.d");
+
+    let mirror = mirror.trim_end_matches('/');
+    let dists_url = format!("{mirror}/debs/dists/{}", topic.name);
+    let topic_prefix = list_filename(&format!("{dists_url}/"));
+
+    let sysroot_path = Path::new(&sysroot);
+
+    // 已经启用了这个 topic 时，不能链接系统中同名的文件，以免下载时写入系统的文件
+    link_dir_entries(
+        &sysroot_path.join("var/lib/apt/lists"),
+        &lists_dir,
+        &topic_prefix,
+    )?;
+    link_dir_entries(&sysroot_path.join("etc/apt/sources.list.d"), &parts_dir, "")?;
+
+    let list_path = parts_dir.join("oma-topic-show.list");
+    fs::write(
+        &list_path,
+        format!("deb {mirror}/debs {} main\n", topic.name),
+    )
+    .map_err(|e| OutputError {
+        description: fl!("failed-to-write-file", p = list_path.display().to_string()),
+        source: Some(Box::new(e)),
+    })?;
+
+    let pm: &dyn DownloadProgressControl = if !no_progress {
+        &OmaMultiProgressBar::default()
+    } else {
+        &NoProgressBar::default()
+    };
+
+    let download = |tasks: Vec<DownloadEntry>| {
+        RT.block_on(
+            DownloadManager::builder()
+                .client(&client)
+                .download_list(tasks)
+                .progress_manager(pm)
+                .mirrors(&mirrors)
+                .build()
+                .start_download(),
+        )
+    };
+
+    let unavailable = |e: OutputError| OutputError {
+        description: fl!("topics-show-unavailable", name = topic.name.as_str()),
+        source: Some(Box::new(e)),
+    };
+
+    // 有经过验证的 InRelease，apt 才会信任这个源
+    let inrelease_url = format!("{dists_url}/InRelease");
+    let inrelease_filename = list_filename(&inrelease_url);
+    let inrelease_path = lists_dir.join(&inrelease_filename);
+
+    let inrelease_task = DownloadEntry::builder()
+        .source(vec![DownloadSource {
+            url: inrelease_url,
+            source_type: DownloadSourceType::Http { auth: None },
+        }])
+        .filename(inrelease_filename)
+        .dir(lists_dir.clone())
+        .allow_resume(false)
+        .msg(format!("{} InRelease", topic.name))
+        .build();
+
+    if let Some(Err(e)) = download(vec![inrelease_task]).into_iter().next() {
+        return Err(unavailable(OutputError::from(e)));
+    }
+
+    let inrelease = fs::read_to_string(&inrelease_path).map_err(|e| OutputError {
+        description: fl!(
+            "failed-to-operate-path",
+            p = inrelease_path.display().to_string()
+        ),
+        source: Some(Box::new(e)),
+    })?;
+
+    let inrelease_err = |e| {
+        OutputError::from(RefreshError::InReleaseParseError(
+            inrelease_path.display().to_string(),
+            e,
+        ))
+    };
+
+    let inrelease = verify_inrelease(&inrelease, None, &sysroot, false).map_err(inrelease_err)?;
+    let inrelease = InRelease::new(&inrelease).map_err(inrelease_err)?;
+    let (checksum_type, checksums) = inrelease
+        .get_or_try_init_checksum_type_and_list()
+        .map_err(inrelease_err)?;
+
+    let mut tasks = vec![];
+
+    // 没有 noarch 软件包的 topic 没有 binary-all，只下载 InRelease 中有的 Packages
+    for arch in [arch.as_str(), "all"] {
+        let name = format!("main/binary-{arch}/Packages");
+
+        let Some(item) = checksums.iter().find(|x| x.name == name) else {
+            continue;
+        };
+
+        let hash = match checksum_type {
+            InReleaseChecksum::Sha256 => Checksum::from_sha256_str(&item.checksum),
+            InReleaseChecksum::Sha512 => Checksum::from_sha512_str(&item.checksum),
+            InReleaseChecksum::Md5 => Checksum::from_md5_str(&item.checksum),
+        }
+        .map_err(|e| OutputError::from(RefreshError::ChecksumError(e)))?;
+
+        let url = format!("{dists_url}/{name}");
+
+        tasks.push(
+            DownloadEntry::builder()
+                .source(vec![DownloadSource {
+                    url: url.clone(),
+                    source_type: DownloadSourceType::Http { auth: None },
+                }])
+                .filename(list_filename(&url))
+                .dir(lists_dir.clone())
+                .hash(hash)
+                .allow_resume(false)
+                .msg(format!("{} {arch} Packages", topic.name))
+                .build(),
+        );
+    }
+
+    if tasks.is_empty() {
+        return Err(OutputError {
+            description: fl!("topics-show-unavailable", name = topic.name.as_str()),
+            source: None,
+        });
+    }
+
+    if let Some(Err(e)) = download(tasks).into_iter().find(|x| x.is_err()) {
+        return Err(unavailable(OutputError::from(e)));
+    }
+
+    let config = AptConfig::new();
+    config.set("Dir::State::Lists", &lists_dir.display().to_string());
+    config.set("Dir::Etc::SourceParts", &parts_dir.display().to_string());
+    // 不要覆盖系统的 apt 缓存
+    config.set("Dir::Cache::pkgcache", "");
+    config.set("Dir::Cache::srcpkgcache", "");
+
+    let oma_apt_args = OmaAptArgs::builder()
+        .sysroot(sysroot.clone())
+        .another_apt_options(another_apt_options)
+        .build();
+
+    let apt = OmaApt::new(vec![], oma_apt_args, true, config)?;
+
+    let topic_prefix = lists_dir.join(&topic_prefix).display().to_string();
+    let mut display = vec![];
+    let mut download_size = 0;
+
+    for name in &topic.packages {
+        let Some(pkg) = apt.cache.get(name) else {
+            continue;
+        };
+
+        let mut topic_ver = None;
+        let mut stable_ver = None;
+
+        for ver in pkg.versions() {
+            let in_topic = ver
+                .package_files()
+                .any(|x| x.filename().is_some_and(|x| x.starts_with(&topic_prefix)));
+
+            if in_topic {
+                topic_ver.get_or_insert(ver);
+            } else if ver.is_downloadable() {
+                stable_ver.get_or_insert(ver);
+            }
+        }
+
+        let installed = pkg.installed().map(|x| x.version().to_string());
+
+        if let Some(ver) = &topic_ver {
+            if installed.as_deref() != Some(ver.version()) {
+                download_size += ver.size();
+            }
+        }
+
+        display.push(TopicPackageDisplay {
+            name: name.to_string(),
+            topic: topic_ver
+                .map(|x| x.version().to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            installed: installed.unwrap_or_else(|| "-".to_string()),
+            stable: stable_ver
+                .map(|x| x.version().to_string())
+                .unwrap_or_else(|| "-".to_string()),
+        });
+    }
+
+    println!(
+        "{}",
+        style(topic.description.as_deref().unwrap_or(&topic.name)).bold()
+    );
+
+    let mut printer = PagerPrinter::new(stdout());
+    printer
+        .print_table(
+            display,
+            vec![
+                &fl!("topics-show-package"),
+                &fl!("topics-show-topic-version"),
+                &fl!("topics-show-installed-version"),
+                &fl!("topics-show-stable-version"),
+            ],
+        )
+        .ok();
+
+    println!(
+        "\n{}",
+        fl!(
+            "topics-show-download-size",
+            size = HumanBytes(download_size).to_string()
+        )
+    );
+
+    Ok(0)
+}
+
+/// apt style list file name of the url
+fn list_filename(url: &str) -> String {
+    url.split_once("://")
+        .map(|x| x.1)
+        .unwrap_or(url)
+        .replace('/', "_")
+}
+
+fn link_dir_entries(from: &Path, to: &Path, skip_prefix: &str) -> Result<(), OutputError> {
+    fs::create_dir_all(to).map_err(|e| OutputError {
+        description: fl!("failed-to-create-file", p = to.display().to_string()),
+        source: Some(Box::new(e)),
+    })?;
+
+    let Ok(dir) = fs::read_dir(from) else {
+        return Ok(());
+    };
+
+    for entry in dir.flatten() {
+        let name = entry.file_name();
+        let name_str = name.to_string_lossy();
+
+        if !entry.path().is_file()
+            || name_str == "lock"
+            || (!skip_prefix.is_empty() && name_str.starts_with(skip_prefix))
+        {
+            continue;
+        }
+
+        std::os::unix::fs::symlink(entry.path(), to.join(&name)).map_err(|e| OutputError {
+            description: fl!("failed-to-operate-path", p = to.display().to_string()),
+            source: Some(Box::new(e)),
+        })?;
+    }
+
+    Ok(())
+}
+
+fn format_age(secs: u64) -> String {
+    let (days, hours, mins) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {mins}m")
+    } else {
+        format!("{mins}m")
+    }
+}
+
 pub fn execute(args: TopicArgs, client: Client, oma_args: OmaArgs) -> Result<i32, OutputError> {
     root()?;
     lock_oma()?;
@@ -87,6 +527,8 @@ pub fn execute(args: TopicArgs, client: Client, oma_args: OmaArgs) -> Result<i32
         no_check_dbus,
     } = args;
 
+    let speed_limit = oma_args.speed_limit;
+
     let fds = if !no_check_dbus {
         Some(dbus_check(false)?)
     } else {
@@ -121,6 +563,7 @@ pub fn execute(args: TopicArgs, client: Client, oma_args: OmaArgs) -> Result<i32
         dry_run,
         no_progress,
         limit: network_thread,
+        speed_limit,
         sysroot: &sysroot,
         _refresh_topics: true,
         config: &apt_config,
@@ -173,6 +616,7 @@ pub fn execute(args: TopicArgs, client: Client, oma_args: OmaArgs) -> Result<i32
         },
         no_fixbroken: false,
         network_thread,
+        speed_limit,
         no_progress,
         sysroot,
         fix_dpkg_status: true,
@@ -190,6 +634,167 @@ pub fn execute(args: TopicArgs, client: Client, oma_args: OmaArgs) -> Result<i32
     Ok(code)
 }
 
+/// Revert packages of the topics closed upstream according to the `topic_expiry` policy
+///
+/// The closure is recorded in the history as `TopicsChanged` once it is handled. Without
+/// `allow_revert`, packages are never changed and closed topics are left for `oma upgrade`.
+pub(crate) struct RevertClosedTopics<'a> {
+    pub policy: TopicExpiry,
+    pub allow_revert: bool,
+    pub sysroot: &'a str,
+    pub dry_run: bool,
+    pub yes: bool,
+    pub network_thread: usize,
+    pub speed_limit: Option<u64>,
+    pub no_progress: bool,
+    pub protect_essential: bool,
+    pub another_apt_options: Vec<String>,
+    pub auth_config: &'a AuthConfig,
+}
+
+impl RevertClosedTopics<'_> {
+    pub(crate) fn run(self) -> Result<i32, OutputError> {
+        let RevertClosedTopics {
+            policy,
+            allow_revert,
+            sysroot,
+            dry_run,
+            yes,
+            network_thread,
+            speed_limit,
+            no_progress,
+            protect_essential,
+            another_apt_options,
+            auth_config,
+        } = self;
+
+        let arch = dpkg_arch(sysroot)?;
+        let mut tm = RT.block_on(TopicManager::new(&HTTP_CLIENT, sysroot, &arch, dry_run))?;
+
+        let closed = tm.closed_topics().to_vec();
+
+        if closed.is_empty() {
+            return Ok(0);
+        }
+
+        let names = closed.iter().map(|x| x.name.clone()).collect::<Vec<_>>();
+
+        warn!("{}", fl!("topics-closed", topics = names.join(", ")));
+
+        if policy == TopicExpiry::Keep {
+            close_topics(&mut tm, sysroot, dry_run, names)?;
+            return Ok(0);
+        }
+
+        // 无法询问用户或不允许更改软件包时（如 oma refresh）留到下次再处理
+        if !allow_revert || (policy == TopicExpiry::Ask && !yes && !is_terminal()) {
+            info!("{}", fl!("topics-closed-pending"));
+            return Ok(0);
+        }
+
+        let enabled_pkgs = tm
+            .enabled_topics()
+            .iter()
+            .flat_map(|x| x.packages.clone())
+            .collect::<Vec<_>>();
+
+        let oma_apt_args = OmaAptArgs::builder()
+            .sysroot(sysroot.to_string())
+            .another_apt_options(another_apt_options)
+            .build();
+
+        let mut apt = OmaApt::new(vec![], oma_apt_args, dry_run, AptConfig::new())?;
+
+        let matcher = PackagesMatcher::builder()
+            .cache(&apt.cache)
+            .native_arch(&arch)
+            .build();
+
+        let mut pkgs = vec![];
+
+        for name in closed.iter().flat_map(|x| &x.packages) {
+            if enabled_pkgs.contains(name) {
+                continue;
+            }
+
+            let Some(installed) = apt.cache.get(name).and_then(|x| x.installed()) else {
+                continue;
+            };
+
+            // 已安装的版本仍然可以下载，说明不是来自已关闭的 topic
+            if installed.is_downloadable() {
+                continue;
+            }
+
+            // 只存在于 topic 中的软件包没有可以回退的版本
+            if let Ok(pkginfo) = matcher.find_candidate_by_pkgname(name) {
+                pkgs.push(pkginfo);
+            }
+        }
+
+        if pkgs.is_empty() {
+            close_topics(&mut tm, sysroot, dry_run, names)?;
+            return Ok(0);
+        }
+
+        apt.install(&pkgs, false)?;
+
+        let code = CommitRequest {
+            apt,
+            dry_run,
+            request_type: SummaryType::TopicsChanged {
+                add: vec![],
+                remove: names.clone(),
+            },
+            no_fixbroken: false,
+            network_thread,
+            speed_limit,
+            no_progress,
+            sysroot: sysroot.to_string(),
+            fix_dpkg_status: true,
+            protect_essential,
+            client: &HTTP_CLIENT,
+            yes: yes || policy == TopicExpiry::Downgrade,
+            remove_config: false,
+            auth_config,
+        }
+        .run()?;
+
+        // 用户拒绝回退时视为保留
+        close_topics(&mut tm, sysroot, dry_run, names)?;
+
+        Ok(code)
+    }
+}
+
+/// Record the closure of topics in the history and forget them
+fn close_topics(
+    tm: &mut TopicManager<'_>,
+    sysroot: &str,
+    dry_run: bool,
+    names: Vec<String>,
+) -> Result<(), OutputError> {
+    write_history_entry(
+        empty_operation(),
+        SummaryType::TopicsChanged {
+            add: vec![],
+            remove: names,
+        },
+        {
+            let db = create_db_file(sysroot)?;
+            connect_db(db, true)?
+        },
+        dry_run,
+        Local::now().timestamp(),
+        None,
+        None,
+    )?;
+
+    RT.block_on(tm.clear_closed())?;
+
+    Ok(())
+}
+
 async fn topics_inner(
     mut opt_in: Vec<String>,
     mut opt_out: Vec<String>,
@@ -322,7 +927,10 @@ fn select_prompt(
     Ok((opt_in, opt_out))
 }
 
-async fn refresh_topics(no_progress: bool, tm: &mut TopicManager<'_>) -> Result<(), OutputError> {
+pub(crate) async fn refresh_topics(
+    no_progress: bool,
+    tm: &mut TopicManager<'_>,
+) -> Result<(), OutputError> {
     let pb = if !no_progress {
         let pb = OmaProgressBar::new_spinner(Some(fl!("refreshing-topic-metadata")));
 
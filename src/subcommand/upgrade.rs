@@ -28,6 +28,9 @@ use tracing::warn;
 
 use crate::color_formatter;
 use crate::error::OutputError;
+use crate::event;
+use crate::event::Event;
+use crate::event::EventProgress;
 use crate::fl;
 use crate::install_progress::NoInstallProgressManager;
 use crate::install_progress::OmaInstallProgressManager;
@@ -43,14 +46,21 @@ use crate::OmaArgs;
 use crate::UpgradeArgs;
 use crate::HTTP_CLIENT;
 
+use super::changelog::show_upgrade_changelogs;
+use super::offline;
 use super::remove::ask_user_do_as_i_say;
+use super::utils::enabled_mirrors;
 use super::utils::handle_features;
 use super::utils::handle_no_result;
 use super::utils::is_nothing_to_do;
 use super::utils::lock_oma;
 use super::utils::no_check_dbus_warn;
+use super::utils::pin_blocked_tips;
 use super::utils::RefreshRequest;
 
+#[cfg(feature = "aosc")]
+use super::topics::RevertClosedTopics;
+
 pub fn execute(
     pkgs_unparse: Vec<String>,
     args: UpgradeArgs,
@@ -62,6 +72,7 @@ pub fn execute(
     let OmaArgs {
         dry_run,
         network_thread,
+        speed_limit,
         no_progress,
         no_check_dbus,
         protect_essentials,
@@ -78,12 +89,14 @@ pub fn execute(
     let apt_config = AptConfig::new();
 
     let auth_config = AuthConfig::system(&args.sysroot)?;
+    let mirrors = enabled_mirrors(&args.sysroot);
 
     RefreshRequest {
         client: &HTTP_CLIENT,
         dry_run,
         no_progress,
         limit: network_thread,
+        speed_limit,
         sysroot: &args.sysroot,
         _refresh_topics: !args.no_refresh_topcs,
         config: &apt_config,
@@ -95,6 +108,22 @@ pub fn execute(
         warn!("{}", fl!("automatic-mode-warn"));
     }
 
+    #[cfg(feature = "aosc")]
+    RevertClosedTopics {
+        policy: args.topic_expiry,
+        allow_revert: true,
+        sysroot: &args.sysroot,
+        dry_run,
+        yes: args.yes,
+        network_thread,
+        speed_limit,
+        no_progress,
+        protect_essential: protect_essentials,
+        another_apt_options: another_apt_options.clone(),
+        auth_config: &auth_config,
+    }
+    .run()?;
+
     let local_debs = pkgs_unparse
         .iter()
         .filter(|x| x.ends_with(".deb"))
@@ -172,16 +201,20 @@ pub fn execute(
             pb.inner.finish_and_clear()
         }
 
+        pin_blocked_tips(&apt);
+
         let op = apt.summary(
             SummarySort::Operation,
             |pkg| {
-                if protect_essentials {
+                if protect_essentials || event::is_stdout() {
                     false
                 } else {
                     ask_user_do_as_i_say(pkg).unwrap_or(false)
                 }
             },
-            |features| handle_features(features, protect_essentials).unwrap_or(false),
+            |features| {
+                handle_features(features, protect_essentials || event::is_stdout()).unwrap_or(false)
+            },
         )?;
 
         apt.check_disk_size(&op)?;
@@ -193,16 +226,25 @@ pub fn execute(
         let disk_size = &op.disk_size;
         let (ar_count, ar_size) = op.autoremovable;
 
+        if event::enabled() {
+            event::emit(&Event::Operation(&op));
+        }
+
         if is_nothing_to_do(install, remove, false) {
             autoremovable_tips(ar_count, ar_size)?;
             return Ok(0);
         }
 
-        if retry_times == 1 {
-            match table_for_install_pending(install, remove, disk_size, !args.yes, dry_run)? {
-                PagerExit::NormalExit => {}
-                x @ PagerExit::Sigint => return Ok(x.into()),
-                x @ PagerExit::DryRun => return Ok(x.into()),
+        if retry_times == 1 && !event::is_stdout() {
+            loop {
+                match table_for_install_pending(
+                    install, remove, disk_size, !args.yes, dry_run, true,
+                )? {
+                    PagerExit::NormalExit => break,
+                    PagerExit::ShowChangelog => show_upgrade_changelogs(&apt, install)?,
+                    x @ PagerExit::Sigint => return Ok(x.into()),
+                    x @ PagerExit::DryRun => return Ok(x.into()),
+                }
             }
         }
 
@@ -214,20 +256,51 @@ pub fn execute(
 
         let start_time = Local::now().timestamp();
 
-        let progress_manager: &dyn DownloadProgressControl = if !no_progress {
+        let progress_manager: &dyn DownloadProgressControl = if event::enabled() {
+            &EventProgress::default()
+        } else if !no_progress {
             &OmaMultiProgressBar::default()
         } else {
             &NoProgressBar::default()
         };
 
+        if args.offline {
+            apt.commit_download_only(
+                &HTTP_CLIENT,
+                CommitDownloadConfig {
+                    network_thread: Some(network_thread),
+                    auth: &auth_config,
+                    speed_limit,
+                    mirrors: &mirrors,
+                },
+                progress_manager,
+                &op,
+            )?;
+
+            if !dry_run {
+                offline::prepare(&args.sysroot, typ, op)?;
+                success!("{}", fl!("offline-upgrade-prepared"));
+                info!("{}", fl!("offline-upgrade-reboot-tips"));
+            }
+
+            drop(fds);
+            return Ok(0);
+        }
+
+        let term_log = apt.term_log();
+
         match apt.commit(
             &HTTP_CLIENT,
             CommitDownloadConfig {
                 network_thread: Some(network_thread),
                 auth: &auth_config,
+                speed_limit,
+                mirrors: &mirrors,
             },
             progress_manager,
-            if no_progress || !is_terminal() {
+            if event::enabled() {
+                Box::new(EventProgress::default())
+            } else if no_progress || !is_terminal() {
                 Box::new(NoInstallProgressManager)
             } else {
                 Box::new(OmaInstallProgressManager)
@@ -244,7 +317,8 @@ pub fn execute(
                     },
                     dry_run,
                     start_time,
-                    true,
+                    None,
+                    term_log.read(),
                 )?;
 
                 let cmd = color_formatter().color_str("oma undo", Action::Emphasis);
@@ -283,7 +357,8 @@ pub fn execute(
                             },
                             dry_run,
                             start_time,
-                            false,
+                            Some(e.to_string()),
+                            term_log.read(),
                         )?;
                         let cmd = color_formatter().color_str("oma undo", Action::Emphasis);
                         info!("{}", fl!("history-tips-2", cmd = cmd.to_string()));
@@ -14,6 +14,9 @@ use std::sync::atomic::Ordering;
 
 use crate::color_formatter;
 use crate::error::OutputError;
+use crate::event;
+use crate::event::Event;
+use crate::event::EventProgress;
 use crate::fl;
 use crate::install_progress::NoInstallProgressManager;
 use crate::install_progress::OmaInstallProgressManager;
@@ -37,9 +40,10 @@ use oma_console::print::Action;
 use oma_console::success;
 use oma_console::writer::Writeln;
 use oma_console::WRITER;
-use oma_contents::searcher::pure_search;
-use oma_contents::searcher::ripgrep_search;
+use oma_contents::index::build_index;
+use oma_contents::searcher::search;
 use oma_contents::searcher::Mode;
+use oma_contents::searcher::QueryKind;
 use oma_fetch::DownloadProgressControl;
 use oma_history::connect_db;
 use oma_history::create_db_file;
@@ -48,6 +52,7 @@ use oma_history::SummaryType;
 use oma_pm::apt::AptConfig;
 use oma_pm::apt::CommitDownloadConfig;
 use oma_pm::apt::OmaApt;
+use oma_pm::apt::OmaOperation;
 use oma_pm::apt::SummarySort;
 use oma_pm::apt::{InstallEntry, RemoveEntry};
 use oma_refresh::db::HandleRefresh;
@@ -103,11 +108,17 @@ pub(crate) fn handle_no_result(
                 error!("{}", fl!("could-not-find-pkg-from-keyword", c = word));
             }
 
-            contents_search(&sysroot, Mode::BinProvides, word, |(pkg, file)| {
-                if file == format!("/usr/bin/{}", word) {
-                    bin.push((pkg, word));
-                }
-            })
+            contents_search(
+                &sysroot,
+                Mode::BinProvides,
+                word,
+                QueryKind::Plain,
+                |(pkg, file)| {
+                    if file == format!("/usr/bin/{}", word) {
+                        bin.push((pkg, word));
+                    }
+                },
+            )
             .ok();
         }
     }
@@ -144,13 +155,17 @@ pub fn contents_search(
     sysroot: impl AsRef<Path>,
     mode: Mode,
     input: &str,
+    kind: QueryKind,
     cb: impl FnMut((String, String)) + Send + Sync,
 ) -> Result<(), OutputError> {
-    if which::which("rg").is_ok() {
-        ripgrep_search(sysroot.as_ref().join("var/lib/apt/lists"), mode, input, cb)?;
-    } else {
-        pure_search(sysroot.as_ref().join("var/lib/apt/lists"), mode, input, cb)?;
-    };
+    search(
+        sysroot.as_ref().join("var/lib/apt/lists"),
+        sysroot.as_ref().join(CONTENTS_INDEX_PATH),
+        mode,
+        input,
+        kind,
+        cb,
+    )?;
 
     Ok(())
 }
@@ -186,11 +201,15 @@ pub(crate) fn lock_oma() -> Result<(), LockError> {
     Ok(())
 }
 
+/// Contents index, relative to sysroot
+pub(crate) const CONTENTS_INDEX_PATH: &str = "var/lib/oma/contents.idx";
+
 pub struct RefreshRequest<'a> {
     pub client: &'a Client,
     pub dry_run: bool,
     pub no_progress: bool,
     pub limit: usize,
+    pub speed_limit: Option<u64>,
     pub sysroot: &'a str,
     pub _refresh_topics: bool,
     pub config: &'a AptConfig,
@@ -204,6 +223,7 @@ impl<'a> RefreshRequest<'a> {
             dry_run,
             no_progress,
             limit,
+            speed_limit,
             sysroot,
             _refresh_topics,
             config,
@@ -220,23 +240,30 @@ impl<'a> RefreshRequest<'a> {
 
         let msg = fl!("do-not-edit-topic-sources-list");
 
-        let pm: &dyn HandleRefresh = if !no_progress && is_terminal() {
+        let pm: &dyn HandleRefresh = if event::enabled() {
+            &EventProgress::default()
+        } else if !no_progress && is_terminal() {
             &OmaMultiProgressBar::default()
         } else {
             &NoProgressBar::default()
         };
 
         let arch = dpkg_arch(&sysroot)?;
+        let mirrors = enabled_mirrors(&sysroot);
+        let lists_dir = sysroot.join("var/lib/apt/lists");
+        let contents_index = sysroot.join(CONTENTS_INDEX_PATH);
 
         let refresh = OmaRefresh::builder()
-            .download_dir(sysroot.join("var/lib/apt/lists"))
+            .download_dir(lists_dir.clone())
             .source(sysroot)
             .threads(limit)
+            .maybe_speed_limit(speed_limit)
             .arch(arch)
             .apt_config(config)
             .client(client)
             .progress_manager(pm)
             .auth_config(auth_config)
+            .mirrors(&mirrors)
             .topic_msg(&msg);
 
         #[cfg(feature = "aosc")]
@@ -247,6 +274,24 @@ impl<'a> RefreshRequest<'a> {
 
         RT.block_on(async move { refresh.start().await })?;
 
+        let pb = if !no_progress {
+            Some(OmaProgressBar::new_spinner(Some(fl!(
+                "building-contents-index"
+            ))))
+        } else {
+            None
+        };
+
+        // 索引只用于加速搜索，构建失败时会回退到直接搜索 Contents 文件
+        match build_index(&lists_dir, &contents_index) {
+            Ok(summary) => debug!("Contents index: {summary:?}"),
+            Err(e) => warn!("{}", fl!("build-contents-index-failed", e = e.to_string())),
+        }
+
+        if let Some(pb) = pb {
+            pb.inner.finish_and_clear();
+        }
+
         Ok(())
     }
 }
@@ -257,6 +302,7 @@ pub struct CommitRequest<'a> {
     pub request_type: SummaryType,
     pub no_fixbroken: bool,
     pub network_thread: usize,
+    pub speed_limit: Option<u64>,
     pub no_progress: bool,
     pub sysroot: String,
     pub fix_dpkg_status: bool,
@@ -275,6 +321,7 @@ impl<'a> CommitRequest<'a> {
             request_type: typ,
             no_fixbroken,
             network_thread,
+            speed_limit,
             no_progress,
             sysroot,
             fix_dpkg_status,
@@ -298,12 +345,14 @@ impl<'a> CommitRequest<'a> {
             pb.inner.finish_and_clear()
         }
 
+        pin_blocked_tips(&apt);
+
         let op = apt.summary(
             SummarySort::Operation,
             |pkg| {
                 if dry_run {
                     true
-                } else if protect_essential {
+                } else if protect_essential || event::is_stdout() {
                     false
                 } else {
                     ask_user_do_as_i_say(pkg).unwrap_or(false)
@@ -313,7 +362,8 @@ impl<'a> CommitRequest<'a> {
                 if dry_run {
                     true
                 } else {
-                    handle_features(features, protect_essential).unwrap_or(false)
+                    handle_features(features, protect_essential || event::is_stdout())
+                        .unwrap_or(false)
                 }
             },
         )?;
@@ -328,34 +378,50 @@ impl<'a> CommitRequest<'a> {
         let disk_size = &op.disk_size;
         let (ar_count, ar_size) = op.autoremovable;
 
+        if event::enabled() {
+            event::emit(&Event::Operation(&op));
+        }
+
         if is_nothing_to_do(install, remove, !no_fixbroken) {
             autoremovable_tips(ar_count, ar_size)?;
             return Ok(0);
         }
 
-        match table_for_install_pending(install, remove, disk_size, !yes, dry_run)? {
-            PagerExit::NormalExit => {}
-            x @ PagerExit::Sigint => return Ok(x.into()),
-            x @ PagerExit::DryRun => return Ok(x.into()),
+        if !event::is_stdout() {
+            match table_for_install_pending(install, remove, disk_size, !yes, dry_run, false)? {
+                PagerExit::NormalExit => {}
+                x @ PagerExit::Sigint => return Ok(x.into()),
+                x @ PagerExit::DryRun => return Ok(x.into()),
+                PagerExit::ShowChangelog => unreachable!(),
+            }
         }
 
         let start_time = Local::now().timestamp();
 
-        let pm: Box<dyn DownloadProgressControl> = if !no_progress {
+        let pm: Box<dyn DownloadProgressControl> = if event::enabled() {
+            Box::new(EventProgress::default())
+        } else if !no_progress {
             let pb = OmaMultiProgressBar::default();
             Box::new(pb)
         } else {
             Box::new(NoProgressBar::default())
         };
 
+        let term_log = apt.term_log();
+        let mirrors = enabled_mirrors(&sysroot);
+
         let res = apt.commit(
             client,
             CommitDownloadConfig {
                 network_thread: Some(network_thread),
                 auth: auth_config,
+                speed_limit,
+                mirrors: &mirrors,
             },
             pm.as_ref(),
-            if no_progress || !is_terminal() {
+            if event::enabled() {
+                Box::new(EventProgress::default())
+            } else if no_progress || !is_terminal() {
                 Box::new(NoInstallProgressManager)
             } else {
                 Box::new(OmaInstallProgressManager)
@@ -381,7 +447,8 @@ impl<'a> CommitRequest<'a> {
                     },
                     dry_run,
                     start_time,
-                    true,
+                    None,
+                    term_log.read(),
                 )?;
 
                 autoremovable_tips(ar_count, ar_size)?;
@@ -400,7 +467,8 @@ impl<'a> CommitRequest<'a> {
                     },
                     dry_run,
                     start_time,
-                    false,
+                    Some(e.to_string()),
+                    term_log.read(),
                 )?;
                 Err(e.into())
             }
@@ -408,6 +476,46 @@ impl<'a> CommitRequest<'a> {
     }
 }
 
+/// An operation which changes nothing, for recording events in the history
+pub(crate) fn empty_operation() -> OmaOperation {
+    OmaOperation {
+        install: vec![],
+        remove: vec![],
+        disk_size: ("+".into(), 0),
+        autoremovable: (0, 0),
+        total_download_size: 0,
+    }
+}
+
+/// 提示本次操作中因为固定而不会升级的软件包
+pub fn pin_blocked_tips(apt: &OmaApt) {
+    let blocked = match apt.blocked_by_pins() {
+        Ok(blocked) => blocked,
+        Err(e) => {
+            warn!("{}", OutputError::from(e));
+            return;
+        }
+    };
+
+    for b in &blocked {
+        if event::enabled() {
+            event::emit(&Event::PinBlocked(b));
+        }
+
+        if !event::is_stdout() {
+            warn!(
+                "{}",
+                fl!(
+                    "pin-blocks-upgrade",
+                    name = b.pin.package.as_str(),
+                    pin = b.pin.target.to_string(),
+                    version = b.newest.as_str()
+                )
+            );
+        }
+    }
+}
+
 pub fn autoremovable_tips(count: u64, total_size: u64) -> Result<(), OutputError> {
     if count == 0 {
         return Ok(());
@@ -572,6 +680,34 @@ pub fn select_tui_display_msg(s: &str, is_inquire: bool) -> Cow<str> {
     }
 }
 
+/// URLs of the enabled mirrors, a file on one of them can also be downloaded from the others
+#[cfg(feature = "aosc")]
+pub(crate) fn enabled_mirrors(sysroot: impl AsRef<Path>) -> Vec<String> {
+    let sysroot = sysroot.as_ref();
+
+    // 没有使用 oma mirror 管理镜像源时不要创建状态文件
+    if !sysroot.join("var/lib/apt/gen/status.json").is_file() {
+        return vec![];
+    }
+
+    match oma_mirror::MirrorManager::new(sysroot.to_path_buf()) {
+        Ok(mm) => mm
+            .enabled_mirrors()
+            .values()
+            .map(|x| x.to_string())
+            .collect(),
+        Err(e) => {
+            debug!("Failed to read enabled mirrors: {e}");
+            vec![]
+        }
+    }
+}
+
+#[cfg(not(feature = "aosc"))]
+pub(crate) fn enabled_mirrors(_sysroot: impl AsRef<Path>) -> Vec<String> {
+    vec![]
+}
+
 pub fn is_terminal() -> bool {
     let res = stderr().is_terminal() && stdout().is_terminal() && stdin().is_terminal();
     debug!("is terminal: {}", res);
@@ -9,6 +9,7 @@ use oma_console::indicatif::HumanBytes;
 use oma_console::pager::{Pager, PagerExit, PagerUIText};
 use oma_console::print::Action;
 use oma_console::WRITER;
+use oma_history::HistoryDetail;
 use oma_pm::apt::{InstallEntry, InstallOperation, RemoveEntry, RemoveTag};
 use tabled::settings::object::Columns;
 use tabled::settings::peaker::PriorityMax;
@@ -106,7 +107,10 @@ pub fn oma_display_with_normal_output(
         Pager::plain()
     } else {
         Pager::external(
-            &OmaPagerUIText { is_question: false },
+            &OmaPagerUIText {
+                is_question: false,
+                changelog: false,
+            },
             None,
             color_formatter(),
         )
@@ -121,11 +125,12 @@ pub fn oma_display_with_normal_output(
 
 struct OmaPagerUIText {
     is_question: bool,
+    changelog: bool,
 }
 
 impl PagerUIText for OmaPagerUIText {
     fn normal_tips(&self) -> String {
-        tips(self.is_question)
+        tips(self.is_question, self.changelog)
     }
 
     fn search_tips_with_result(&self) -> String {
@@ -145,12 +150,18 @@ impl PagerUIText for OmaPagerUIText {
     }
 }
 
-fn tips(is_question: bool) -> String {
+fn tips(is_question: bool, changelog: bool) -> String {
     let has_x11 = std::env::var("DISPLAY");
     let has_wayland = std::env::var("WAYLAND_DISPLAY");
     let has_gui = has_x11.is_ok() || has_wayland.is_ok();
 
-    if is_question {
+    if is_question && changelog {
+        if has_gui {
+            fl!("question-tips-with-changelog-with-gui")
+        } else {
+            fl!("question-tips-with-changelog")
+        }
+    } else if is_question {
         if has_gui {
             fl!("question-tips-with-gui")
         } else {
@@ -209,25 +220,32 @@ pub fn table_for_install_pending(
     disk_size: &(Box<str>, u64),
     is_pager: bool,
     dry_run: bool,
+    changelog: bool,
 ) -> Result<PagerExit, OutputError> {
     if dry_run {
         return Ok(PagerExit::NormalExit);
     }
 
+    let ui_text = OmaPagerUIText {
+        is_question: true,
+        changelog,
+    };
+
     let mut pager = if is_pager {
-        Pager::external(
-            &OmaPagerUIText { is_question: true },
-            Some(fl!("pending-op")),
-            color_formatter(),
-        )
-        .map_err(|e| OutputError {
-            description: "Failed to get pager".to_string(),
-            source: Some(Box::new(e)),
+        Pager::external(&ui_text, Some(fl!("pending-op")), color_formatter()).map_err(|e| {
+            OutputError {
+                description: "Failed to get pager".to_string(),
+                source: Some(Box::new(e)),
+            }
         })?
     } else {
         Pager::plain()
     };
 
+    if changelog {
+        pager = pager.with_changelog_key();
+    }
+
     let out = pager.get_writer().map_err(|e| OutputError {
         description: "Failed to get writer".to_string(),
         source: Some(Box::new(e)),
@@ -264,9 +282,13 @@ pub fn table_for_history_pending(
     install: &[InstallEntry],
     remove: &[RemoveEntry],
     disk_size: &(Box<str>, u64),
+    detail: &HistoryDetail,
 ) -> Result<(), OutputError> {
     let mut pager = Pager::external(
-        &OmaPagerUIText { is_question: false },
+        &OmaPagerUIText {
+            is_question: false,
+            changelog: false,
+        },
         Some(fl!("pending-op")),
         color_formatter(),
     )
@@ -283,6 +305,7 @@ pub fn table_for_history_pending(
 
     printer.print("\n\n").ok();
 
+    print_history_detail(&mut printer, detail);
     print_pending_inner(printer, remove, install, disk_size);
     pager.wait_for_exit().map_err(|e| OutputError {
         description: "Failed to wait exit".to_string(),
@@ -292,6 +315,60 @@ pub fn table_for_history_pending(
     Ok(())
 }
 
+fn print_history_detail<W: Write>(printer: &mut PagerPrinter<W>, detail: &HistoryDetail) {
+    let HistoryDetail {
+        cmdline,
+        user,
+        error,
+        duration,
+    } = detail;
+
+    if let Some(cmdline) = cmdline {
+        printer
+            .print(format!(
+                "{}{} {cmdline}",
+                style(fl!("history-cmdline")).bold(),
+                fl!("colon")
+            ))
+            .ok();
+    }
+
+    if let Some(user) = user {
+        printer
+            .print(format!(
+                "{}{} {user}",
+                style(fl!("history-user")).bold(),
+                fl!("colon")
+            ))
+            .ok();
+    }
+
+    if let Some(duration) = duration {
+        printer
+            .print(format!(
+                "{}{} {}",
+                style(fl!("history-duration")).bold(),
+                fl!("colon"),
+                fl!("history-duration-seconds", secs = *duration)
+            ))
+            .ok();
+    }
+
+    if let Some(error) = error {
+        printer
+            .print(format!(
+                "{}{} {error}",
+                style(fl!("history-error")).red().bold(),
+                fl!("colon")
+            ))
+            .ok();
+    }
+
+    if cmdline.is_some() || user.is_some() || duration.is_some() || error.is_some() {
+        printer.print("").ok();
+    }
+}
+
 fn print_pending_inner<W: Write>(
     mut printer: PagerPrinter<W>,
     remove: &[RemoveEntry],
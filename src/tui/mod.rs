@@ -30,6 +30,7 @@ pub struct TuiArgs {
     pub no_progress: bool,
     pub dry_run: bool,
     pub network_thread: usize,
+    pub speed_limit: Option<u64>,
     pub no_check_dbus: bool,
     pub another_apt_options: Vec<String>,
 }
@@ -59,6 +60,7 @@ pub fn execute(tui: TuiArgs) -> Result<i32, OutputError> {
         no_progress,
         dry_run,
         network_thread,
+        speed_limit,
         no_check_dbus,
         another_apt_options,
     } = tui;
@@ -71,6 +73,7 @@ pub fn execute(tui: TuiArgs) -> Result<i32, OutputError> {
         dry_run,
         no_progress,
         limit: network_thread,
+        speed_limit,
         sysroot: &sysroot,
         _refresh_topics: true,
         config: &apt_config,
@@ -151,6 +154,7 @@ pub fn execute(tui: TuiArgs) -> Result<i32, OutputError> {
             request_type: SummaryType::Changes,
             no_fixbroken: false,
             network_thread,
+            speed_limit,
             no_progress,
             sysroot,
             fix_dpkg_status: true,